@@ -0,0 +1,27 @@
+//! Parsing cost per message, for RFC3164 and RFC5424 samples.
+
+use chrono::Local;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use syslog_server::{parser, TimestampFormat};
+
+const RFC3164: &str = "<34>Oct 11 22:14:15 mymachine su[1234]: 'su root' failed for lonvick on /dev/pts/8";
+const RFC5424: &str = "<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 \
+    [exampleSDID@32473 iut=\"3\" eventSource=\"Application\" eventID=\"1011\"] An application event log entry";
+
+fn parse_priority(c: &mut Criterion) {
+    c.bench_function("parse_priority", |b| b.iter(|| parser::parse_priority(black_box(RFC3164))));
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    let received_at = Local::now();
+    for (name, message) in [("rfc3164", RFC3164), ("rfc5424", RFC5424)] {
+        group.bench_function(name, |b| {
+            b.iter(|| parser::parse(black_box(message), "192.0.2.1", received_at, TimestampFormat::default()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse_priority, parse);
+criterion_main!(benches);
@@ -0,0 +1,32 @@
+//! Messages per second through the queue, parser and CSV writer, with the
+//! output going to `/dev/null` so the disk doesn't set the pace.
+
+use std::path::Path;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+const MESSAGES: usize = 10_000;
+
+fn pipeline(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let messages: Vec<Vec<u8>> = (0..MESSAGES)
+        .map(|i| format!("<34>Oct 11 22:14:15 mymachine su[{}]: 'su root' failed for lonvick on /dev/pts/8", i).into_bytes())
+        .collect();
+
+    let mut group = c.benchmark_group("pipeline");
+    group.throughput(Throughput::Elements(MESSAGES as u64));
+    group.sample_size(20);
+    for workers in [1, 4] {
+        group.bench_function(format!("{}_workers", workers), |b| {
+            b.iter(|| {
+                runtime
+                    .block_on(syslog_server::process_messages(Path::new("/dev/null"), messages.clone(), workers, 100))
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, pipeline);
+criterion_main!(benches);
@@ -0,0 +1,33 @@
+use std::net::IpAddr;
+
+use ipnet::IpNet;
+
+/// Decides which source addresses may send logs. Deny rules win over allow
+/// rules, and with no allow rules everything not denied is accepted.
+#[derive(Debug, Default)]
+pub struct SourceFilter {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+}
+
+impl SourceFilter {
+    pub fn new(allow: Vec<IpNet>, deny: Vec<IpNet>) -> Self {
+        SourceFilter { allow, deny }
+    }
+
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        // IPv4 senders on a dual-stack socket show up as ::ffff:a.b.c.d
+        let ip = ip.to_canonical();
+        if self.deny.iter().any(|net| net.contains(&ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(&ip))
+    }
+}
+
+/// Parses a CIDR block, treating a bare address as a single-host network.
+pub fn parse_cidr(cidr: &str) -> Result<IpNet, String> {
+    cidr.parse::<IpNet>()
+        .or_else(|_| cidr.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("expected a CIDR block such as 10.0.0.0/8, got `{}`", cidr))
+}
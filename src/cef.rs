@@ -0,0 +1,231 @@
+use serde_json::{json, Map, Value};
+
+/// CEF header fields after the version, in order.
+const CEF_HEADER: [&str; 6] = ["device_vendor", "device_product", "device_version", "signature_id", "name", "severity"];
+
+/// LEEF header fields after the version, in order.
+const LEEF_HEADER: [&str; 4] = ["vendor", "product", "product_version", "event_id"];
+
+/// Parses a message body in ArcSight CEF or IBM LEEF format into its header
+/// fields and extensions. `None` for anything else, or a header cut short.
+pub fn parse(message: &str) -> Option<Value> {
+    let message = message.trim_start();
+    if let Some(rest) = message.strip_prefix("CEF:") {
+        parse_cef(rest)
+    } else if let Some(rest) = message.strip_prefix("LEEF:") {
+        parse_leef(rest)
+    } else {
+        None
+    }
+}
+
+/// `Version|Vendor|Product|DeviceVersion|SignatureID|Name|Severity|Extension`,
+/// where header fields escape `|` and `\` with a backslash.
+fn parse_cef(rest: &str) -> Option<Value> {
+    let mut fields = Vec::with_capacity(CEF_HEADER.len() + 1);
+    let mut field = String::new();
+    let mut chars = rest.char_indices();
+    let mut extension = None;
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, escaped @ ('|' | '\\'))) => field.push(escaped),
+                Some((_, other)) => {
+                    field.push('\\');
+                    field.push(other);
+                }
+                None => field.push('\\'),
+            },
+            '|' => {
+                fields.push(std::mem::take(&mut field));
+                if fields.len() == CEF_HEADER.len() + 1 {
+                    extension = Some(&rest[i + 1..]);
+                    break;
+                }
+            }
+            _ => field.push(c),
+        }
+    }
+    // The extension may be empty, but the bar before it may not be missing.
+    let extension = extension?;
+
+    let mut parsed = Map::new();
+    parsed.insert("format".to_string(), json!("CEF"));
+    let mut fields = fields.into_iter();
+    parsed.insert("version".to_string(), Value::String(fields.next()?));
+    for (name, value) in CEF_HEADER.iter().zip(fields) {
+        parsed.insert(name.to_string(), Value::String(value));
+    }
+    parsed.insert("extensions".to_string(), Value::Object(cef_extensions(extension)));
+    Some(Value::Object(parsed))
+}
+
+/// Space-separated `key=value` pairs. Values may contain spaces, so each runs
+/// up to the next ` key=`; `=` and `\` in them are escaped with a backslash,
+/// and `\n` and `\r` stand for line breaks.
+fn cef_extensions(extension: &str) -> Map<String, Value> {
+    // Where each key starts, and the unescaped `=` that ends it.
+    let mut keys = Vec::new();
+    let mut escaped = false;
+    for (i, c) in extension.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '=' {
+            let start = extension[..i].rfind(' ').map_or(0, |space| space + 1);
+            let key = &extension[start..i];
+            if !key.is_empty() && key.chars().all(is_key_char) {
+                keys.push((start, i));
+            }
+        }
+    }
+
+    let mut extensions = Map::new();
+    for (n, &(start, equals)) in keys.iter().enumerate() {
+        let end = keys.get(n + 1).map_or(extension.len(), |&(next, _)| next);
+        let value = extension[equals + 1..end].trim_end_matches(' ');
+        extensions.insert(extension[start..equals].to_string(), Value::String(unescape_cef_value(value)));
+    }
+    extensions
+}
+
+fn is_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-' | '[' | ']')
+}
+
+fn unescape_cef_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// `Version|Vendor|Product|Version|EventID|Attributes`. Attributes are
+/// tab-separated `key=value` pairs; LEEF 2.0 may name another delimiter in
+/// an extra header field, as a character or as hex such as `x5E`.
+fn parse_leef(rest: &str) -> Option<Value> {
+    let mut parts = rest.splitn(LEEF_HEADER.len() + 2, '|');
+    let version = parts.next()?;
+    let mut parsed = Map::new();
+    parsed.insert("format".to_string(), json!("LEEF"));
+    parsed.insert("version".to_string(), json!(version));
+    for name in LEEF_HEADER {
+        parsed.insert(name.to_string(), json!(parts.next()?));
+    }
+    let mut attributes = parts.next()?;
+    let mut delimiter = '\t';
+    if version.starts_with('2') {
+        if let Some((field, after)) = attributes.split_once('|') {
+            if let Some(named) = leef_delimiter(field) {
+                delimiter = named;
+                attributes = after;
+            }
+        }
+    }
+
+    let attributes: Map<String, Value> = attributes
+        .split(delimiter)
+        .filter_map(|pair| pair.split_once('='))
+        .filter(|(key, _)| !key.is_empty())
+        .map(|(key, value)| (key.to_string(), json!(value)))
+        .collect();
+    parsed.insert("attributes".to_string(), Value::Object(attributes));
+    Some(Value::Object(parsed))
+}
+
+/// A LEEF 2.0 delimiter field: one character, or its code in hex.
+fn leef_delimiter(field: &str) -> Option<char> {
+    let mut chars = field.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => {
+            let hex = field.strip_prefix("0x").or_else(|| field.strip_prefix('x'))?;
+            u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_cef_sample() {
+        let parsed = parse(
+            r"CEF:0|Security|threatmanager|1.0|100|worm successfully stopped|10|src=10.0.0.1 dst=2.1.2.2 spt=1232 msg=Detected a threat. No action needed.",
+        )
+        .unwrap();
+        assert_eq!(
+            parsed,
+            json!({
+                "format": "CEF",
+                "version": "0",
+                "device_vendor": "Security",
+                "device_product": "threatmanager",
+                "device_version": "1.0",
+                "signature_id": "100",
+                "name": "worm successfully stopped",
+                "severity": "10",
+                "extensions": {
+                    "src": "10.0.0.1",
+                    "dst": "2.1.2.2",
+                    "spt": "1232",
+                    "msg": "Detected a threat. No action needed.",
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn cef_escapes() {
+        let parsed = parse(r"CEF:0|security|threat\|manager|1.0|100|detected a \\ in packet|10|act=blocked a \= dst=1.1.1.1 msg=line\none").unwrap();
+        assert_eq!(parsed["device_product"], "threat|manager");
+        assert_eq!(parsed["name"], r"detected a \ in packet");
+        assert_eq!(parsed["extensions"]["act"], "blocked a =");
+        assert_eq!(parsed["extensions"]["dst"], "1.1.1.1");
+        assert_eq!(parsed["extensions"]["msg"], "line\none");
+
+        assert_eq!(parse("CEF:0|Security|threatmanager|1.0|100|worm stopped|10|").unwrap()["extensions"], json!({}));
+        assert_eq!(parse("CEF:0|Security|threatmanager|1.0|100"), None);
+    }
+
+    #[test]
+    fn leef_samples() {
+        let parsed = parse("LEEF:1.0|Microsoft|MSExchange|4.0 SP1|15345|src=192.0.2.0\tdst=172.50.123.1\tsev=5").unwrap();
+        assert_eq!(
+            parsed,
+            json!({
+                "format": "LEEF",
+                "version": "1.0",
+                "vendor": "Microsoft",
+                "product": "MSExchange",
+                "product_version": "4.0 SP1",
+                "event_id": "15345",
+                "attributes": {"src": "192.0.2.0", "dst": "172.50.123.1", "sev": "5"},
+            })
+        );
+
+        let parsed = parse("LEEF:2.0|Lancope|StealthWatch|1.0|41|^|src=10.0.1.8^dst=10.0.0.5^sev=5").unwrap();
+        assert_eq!(parsed["attributes"], json!({"src": "10.0.1.8", "dst": "10.0.0.5", "sev": "5"}));
+        let parsed = parse("LEEF:2.0|Lancope|StealthWatch|1.0|41|x5E|src=10.0.1.8^dst=10.0.0.5").unwrap();
+        assert_eq!(parsed["attributes"]["dst"], "10.0.0.5");
+    }
+
+    #[test]
+    fn other_messages_are_left_alone() {
+        assert_eq!(parse("Accepted password for root from 10.0.0.1"), None);
+        assert_eq!(parse("see CEF:0|a|b|c|d|e|f|"), None);
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::Path;
+
+use chrono::Local;
+
+use crate::output::{dated_path, OutputFormat};
+use crate::{load_template, megabytes, tls, Args, SyslogError};
+
+/// Runs `syslog-server check`: reports every problem with the settings and
+/// fails if there was any. Sockets are never bound, and existing output files
+/// are opened for appending but not written to.
+pub fn run(args: &Args) -> Result<(), SyslogError> {
+    let problems = problems(args);
+    if problems.is_empty() {
+        match &args.config {
+            Some(path) => println!("{} is valid", path.display()),
+            None => println!("Settings are valid"),
+        }
+        return Ok(());
+    }
+    for problem in &problems {
+        eprintln!("{}", problem);
+    }
+    Err(SyslogError::Config(format!("Found {} problem(s) in the settings", problems.len())))
+}
+
+/// Everything that would stop the server from starting with `args`, beyond
+/// what parsing them already rejects.
+fn problems(args: &Args) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let mut ports = HashSet::new();
+    for port in args.port.iter().filter(|port| !ports.insert(**port)) {
+        problems.push(format!("Port {} is listed more than once", port));
+    }
+    if let Err(e) = load_template(args) {
+        problems.push(e.to_string());
+    }
+
+    let mut outputs = Vec::new();
+    if !args.no_file {
+        outputs.push(("output", args.output.as_path(), args.output_format));
+        for (_, path) in &args.facility_route {
+            outputs.push(("facility output", path.as_path(), args.output_format));
+        }
+    }
+    for sink in &args.sink {
+        outputs.push(("sink", sink.path.as_path(), sink.format));
+    }
+    for (kind, path, format) in outputs {
+        if let Err(e) = check_format(format) {
+            problems.push(format!("Cannot write {} {}: {}", kind, path.display(), e));
+        }
+        let path = match dated_path(path, Local::now()) {
+            Ok(path) => path,
+            Err(e) => {
+                problems.push(format!("Cannot write to {}: {}", kind, e));
+                continue;
+            }
+        };
+        if let Err(e) = check_writable(&path) {
+            problems.push(format!("Cannot write to {} {}: {}", kind, path.display(), e));
+        }
+    }
+    if let Some(path) = &args.dead_letter {
+        if let Err(e) = check_writable(path) {
+            problems.push(format!("Cannot write to dead letter file {}: {}", path.display(), e));
+        }
+    }
+    if let Some(path) = &args.summary_output {
+        if let Err(e) = check_writable(path) {
+            problems.push(format!("Cannot write to summary output {}: {}", path.display(), e));
+        }
+    }
+    if let Some(path) = &args.unix_socket {
+        if let Err(e) = check_dir_writable(path) {
+            problems.push(format!("Cannot create Unix socket {}: {}", path.display(), e));
+        }
+    }
+    if let Some(path) = &args.replay {
+        if let Err(e) = fs::File::open(path) {
+            problems.push(format!("Cannot read replay file {}: {}", path.display(), e));
+        }
+    }
+
+    if let (Some(cert), Some(key)) = (&args.tls_cert, &args.tls_key) {
+        if let Err(e) = tls::load_acceptor(cert, key) {
+            problems.push(e.to_string());
+        }
+    }
+    if let Some(target) = &args.forward_to {
+        let port = target.rsplit_once(':').and_then(|(_, port)| port.parse::<u16>().ok());
+        if port.is_none() {
+            problems.push(format!("Forward target `{}` is not HOST:PORT", target));
+        }
+    }
+    if let Some(url) = &args.loki_url {
+        if let Err(e) = crate::loki::parse_loki_url(url) {
+            problems.push(e);
+        }
+    }
+    if let Some(Err(e)) = args.rotate_size_mb.map(|mb| megabytes("--rotate-size-mb", mb)) {
+        problems.push(e.to_string());
+    }
+    if let Some(Err(e)) = args.max_disk_mb.map(|mb| megabytes("--max-disk-mb", mb)) {
+        problems.push(e.to_string());
+    }
+    if args.kafka_brokers.is_some() && !cfg!(feature = "kafka") {
+        problems.push("--kafka-brokers requires building with `--features kafka`".to_string());
+    }
+    problems
+}
+
+fn check_format(format: OutputFormat) -> Result<(), &'static str> {
+    match format {
+        OutputFormat::Parquet if !cfg!(feature = "parquet") => Err("Parquet output requires building with `--features parquet`"),
+        OutputFormat::Sqlite if !cfg!(feature = "sqlite") => Err("SQLite output requires building with `--features sqlite`"),
+        _ => Ok(()),
+    }
+}
+
+/// Opens an existing file for appending without writing to it, or else checks
+/// that it could be created.
+fn check_writable(path: &Path) -> io::Result<()> {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_dir() => Err(io::Error::other("is a directory")),
+        Ok(_) => OpenOptions::new().append(true).open(path).map(drop),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => check_dir_writable(path),
+        Err(e) => Err(e),
+    }
+}
+
+/// Creates and removes a scratch file next to `path`.
+fn check_dir_writable(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let probe = dir.join(format!(".syslog-server-check-{}", std::process::id()));
+    OpenOptions::new().write(true).create_new(true).open(&probe)?;
+    fs::remove_file(probe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn every_problem_is_reported() {
+        let missing = std::env::temp_dir().join(format!("syslog-server-check-{}", std::process::id())).join("out.csv");
+        let args = Args::try_parse_from([
+            "syslog-server",
+            "--port",
+            "514",
+            "--port",
+            "514",
+            "--output",
+            missing.to_str().unwrap(),
+            "--output-format",
+            "template",
+            "--template",
+            "{nope}",
+            "--max-disk-mb",
+            "18446744073709551615",
+            "check",
+        ])
+        .unwrap();
+        assert_eq!(args.command, Some(crate::Command::Check));
+
+        let problems = problems(&args);
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems[0].contains("514"));
+        assert!(problems[1].contains("{nope}"));
+        assert!(problems[2].contains("out.csv"));
+        assert!(problems[3].contains("--max-disk-mb"));
+    }
+
+    #[test]
+    fn valid_settings_leave_existing_outputs_untouched() {
+        let output = std::env::temp_dir().join(format!("syslog-server-check-{}.csv", std::process::id()));
+        fs::write(&output, "kept\n").unwrap();
+        let args = Args::try_parse_from(["syslog-server", "--output", output.to_str().unwrap(), "check"]).unwrap();
+
+        assert!(problems(&args).is_empty());
+        assert_eq!(fs::read_to_string(&output).unwrap(), "kept\n");
+        fs::remove_file(output).unwrap();
+    }
+}
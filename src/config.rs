@@ -0,0 +1,319 @@
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use serde::Deserialize;
+
+use crate::acl::parse_cidr;
+use crate::forward::ForwardProtocol;
+use crate::output::fields::select_fields;
+use crate::output::sd_column::parse_sd_column;
+use crate::output::sink::parse_sink;
+use crate::redact::parse_pattern;
+use crate::sample::parse_facility_rate;
+use crate::output::{parse_csv_delimiter, CsvQuoteStyle, FsyncPolicy, OutputFormat, RotateInterval};
+use crate::{parse_facility_route, parse_severity_remap, Args, EventTimeSource, LogFormat, NewlineHandling, OnMalformed, Protocol, SyslogError, TimestampFormat, Trim};
+
+/// Settings read from a `--config` TOML file. Keys mirror the `Args` fields
+/// and every key is optional; anything left out keeps its CLI default.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    /// A single port or a list of them
+    port: Option<Ports>,
+    bind_address: Option<IpAddr>,
+    /// CIDR blocks, as with --allow-cidr and --deny-cidr
+    allow_cidr: Option<Vec<String>>,
+    deny_cidr: Option<Vec<String>>,
+    output: Option<PathBuf>,
+    output_format: Option<OutputFormat>,
+    /// `FORMAT:PATH` entries, as with --sink
+    sink: Option<Vec<String>>,
+    console: Option<bool>,
+    template: Option<String>,
+    csv_delimiter: Option<String>,
+    csv_quote_style: Option<CsvQuoteStyle>,
+    /// Field names, as with --fields
+    fields: Option<Vec<String>>,
+    /// `SD-ID.PARAM=COLUMN` mappings, as with --sd-column
+    sd_column: Option<Vec<String>>,
+    parquet_row_group_size: Option<usize>,
+    rotate_size_mb: Option<u64>,
+    rotate_interval: Option<RotateInterval>,
+    fsync: Option<FsyncPolicy>,
+    fsync_interval_ms: Option<u64>,
+    compress_rotated: Option<bool>,
+    max_disk_mb: Option<u64>,
+    summary_output: Option<PathBuf>,
+    summary_interval: Option<u64>,
+    min_severity: Option<u8>,
+    /// `FROM=TO` remaps, as with --severity-remap
+    severity_remap: Option<Vec<String>>,
+    /// `FACILITY=PATH` entries, as with --facility-route
+    facility_route: Option<Vec<String>>,
+    dedup_window_ms: Option<u64>,
+    sample_rate: Option<f64>,
+    /// `FACILITY=RATE` entries, as with --sample-facility
+    sample_facility: Option<Vec<String>>,
+    on_malformed: Option<OnMalformed>,
+    default_priority: Option<u8>,
+    named_levels: Option<bool>,
+    normalize_v4_mapped: Option<bool>,
+    resolve_hostnames: Option<bool>,
+    max_skew_secs: Option<u64>,
+    event_time_source: Option<EventTimeSource>,
+    timestamp_format: Option<TimestampFormat>,
+    trim: Option<Trim>,
+    newline_handling: Option<NewlineHandling>,
+    /// Regexes, as with --redact-pattern
+    redact_pattern: Option<Vec<String>>,
+    keep_raw: Option<bool>,
+    parse_cef: Option<bool>,
+    hash_messages: Option<bool>,
+    metrics_port: Option<u16>,
+    metrics_required: Option<bool>,
+    no_metrics: Option<bool>,
+    tail_buffer: Option<usize>,
+    max_source_labels: Option<usize>,
+    queue_size: Option<usize>,
+    queue_warn_pct: Option<u8>,
+    send_timeout_ms: Option<u64>,
+    max_message_bytes: Option<usize>,
+    recv_buffer_bytes: Option<usize>,
+    bind_retry: Option<u32>,
+    protocol: Option<Protocol>,
+    unix_socket: Option<PathBuf>,
+    /// Octal permission bits, e.g. `0o660`
+    unix_socket_mode: Option<u32>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    tcp_backlog: Option<u32>,
+    tcp_max_connections: Option<u32>,
+    tcp_idle_timeout_secs: Option<u64>,
+    proxy_protocol: Option<bool>,
+    http_ingest_port: Option<u16>,
+    trust_xff: Option<bool>,
+    batch_size: Option<usize>,
+    flush_interval_ms: Option<u64>,
+    shutdown_drain_timeout_secs: Option<u64>,
+    write_retries: Option<u32>,
+    write_retry_backoff_ms: Option<u64>,
+    dead_letter: Option<PathBuf>,
+    replay_dead_letter: Option<bool>,
+    workers: Option<usize>,
+    ordered: Option<bool>,
+    forward_to: Option<String>,
+    loki_url: Option<String>,
+    forward_protocol: Option<ForwardProtocol>,
+    forward_buffer_size: Option<usize>,
+    forward_max_backoff_ms: Option<u64>,
+    kafka_brokers: Option<String>,
+    kafka_topic: Option<String>,
+    kafka_max_in_flight: Option<usize>,
+    no_file: Option<bool>,
+    replay: Option<PathBuf>,
+    replay_rate: Option<u32>,
+    log_format: Option<LogFormat>,
+    verbose: Option<u8>,
+    quiet: Option<u8>,
+    dry_run: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Ports {
+    One(u16),
+    Many(Vec<u16>),
+}
+
+impl FileConfig {
+    pub fn load(path: &Path) -> Result<Self, SyslogError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| SyslogError::Config(format!("Failed to read config file {}: {}", path.display(), e)))?;
+        let config = toml::from_str(&contents)
+            .map_err(|e| SyslogError::Config(format!("Invalid config file {}: {}", path.display(), e)))?;
+        Ok(config)
+    }
+
+    /// Fills in `args` from the file wherever the flag was not given on the
+    /// command line, so explicit flags always win.
+    pub fn apply(self, args: &mut Args, matches: &ArgMatches) -> Result<(), SyslogError> {
+        let from_cli = |id: &str| matches.value_source(id).is_some_and(|source| source != ValueSource::DefaultValue);
+
+        macro_rules! merge {
+            ($($field:ident),* $(,)?) => {
+                $(if let Some(value) = self.$field.filter(|_| !from_cli(stringify!($field))) {
+                    args.$field = value;
+                })*
+            };
+        }
+        macro_rules! merge_optional {
+            ($($field:ident),* $(,)?) => {
+                $(if let Some(value) = self.$field.filter(|_| !from_cli(stringify!($field))) {
+                    args.$field = Some(value);
+                })*
+            };
+        }
+
+        if self.min_severity.is_some_and(|severity| severity > 7) {
+            return Err(SyslogError::Config("min_severity must be between 0 and 7".to_string()));
+        }
+        if self.default_priority.is_some_and(|priority| priority > 191) {
+            return Err(SyslogError::Config("default_priority must be between 0 and 191".to_string()));
+        }
+        if self.sample_rate.is_some_and(|rate| !(0.0..=1.0).contains(&rate)) {
+            return Err(SyslogError::Config("sample_rate must be between 0.0 and 1.0".to_string()));
+        }
+        if let Some(routes) = &self.facility_route {
+            if !from_cli("facility_route") {
+                args.facility_route = routes
+                    .iter()
+                    .map(|route| parse_facility_route(route))
+                    .collect::<Result<_, _>>()
+                    .map_err(SyslogError::Config)?;
+            }
+        }
+
+        if let Some(rules) = self.sample_facility.as_ref().filter(|_| !from_cli("sample_facility")) {
+            args.sample_facility = rules.iter().map(|rule| parse_facility_rate(rule)).collect::<Result<_, _>>().map_err(SyslogError::Config)?;
+        }
+
+        if let Some(remaps) = self.severity_remap.as_ref().filter(|_| !from_cli("severity_remap")) {
+            args.severity_remap = remaps.iter().map(|remap| parse_severity_remap(remap)).collect::<Result<_, _>>().map_err(SyslogError::Config)?;
+        }
+
+        if let Some(patterns) = self.redact_pattern.as_ref().filter(|_| !from_cli("redact_pattern")) {
+            args.redact_pattern = patterns.iter().map(|pattern| parse_pattern(pattern)).collect::<Result<_, _>>().map_err(SyslogError::Config)?;
+        }
+
+        if let Some(sinks) = self.sink.as_ref().filter(|_| !from_cli("sink")) {
+            args.sink = sinks.iter().map(|sink| parse_sink(sink)).collect::<Result<_, _>>().map_err(SyslogError::Config)?;
+        }
+
+        if let Some(ports) = self.port.filter(|_| !from_cli("port")) {
+            args.port = match ports {
+                Ports::One(port) => vec![port],
+                Ports::Many(ports) if !ports.is_empty() => ports,
+                Ports::Many(_) => return Err(SyslogError::Config("port must list at least one port".to_string())),
+            };
+        }
+
+        if let Some(delimiter) = self.csv_delimiter.as_deref().filter(|_| !from_cli("csv_delimiter")) {
+            args.csv_delimiter = parse_csv_delimiter(delimiter).map_err(SyslogError::Config)?;
+        }
+
+        if let Some(fields) = self.fields.as_ref().filter(|_| !from_cli("fields")) {
+            args.fields = Some(select_fields(fields.iter().map(String::as_str)).map_err(SyslogError::Config)?);
+        }
+
+        if let Some(columns) = self.sd_column.as_ref().filter(|_| !from_cli("sd_column")) {
+            args.sd_column = columns.iter().map(|column| parse_sd_column(column)).collect::<Result<_, _>>().map_err(SyslogError::Config)?;
+        }
+
+        for (id, cidrs, target) in [
+            ("allow_cidr", &self.allow_cidr, &mut args.allow_cidr),
+            ("deny_cidr", &self.deny_cidr, &mut args.deny_cidr),
+        ] {
+            if let Some(cidrs) = cidrs.as_ref().filter(|_| !from_cli(id)) {
+                *target = cidrs.iter().map(|cidr| parse_cidr(cidr)).collect::<Result<_, _>>().map_err(SyslogError::Config)?;
+            }
+        }
+
+        merge!(
+            bind_address,
+            output,
+            output_format,
+            console,
+            csv_quote_style,
+            parquet_row_group_size,
+            fsync,
+            fsync_interval_ms,
+            compress_rotated,
+            summary_interval,
+            min_severity,
+            sample_rate,
+            on_malformed,
+            default_priority,
+            named_levels,
+            normalize_v4_mapped,
+            resolve_hostnames,
+            event_time_source,
+            timestamp_format,
+            trim,
+            newline_handling,
+            keep_raw,
+            parse_cef,
+            hash_messages,
+            metrics_port,
+            metrics_required,
+            no_metrics,
+            tail_buffer,
+            max_source_labels,
+            queue_size,
+            max_message_bytes,
+            recv_buffer_bytes,
+            bind_retry,
+            protocol,
+            unix_socket_mode,
+            tcp_backlog,
+            proxy_protocol,
+            trust_xff,
+            batch_size,
+            flush_interval_ms,
+            write_retries,
+            write_retry_backoff_ms,
+            replay_dead_letter,
+            workers,
+            ordered,
+            forward_protocol,
+            forward_buffer_size,
+            forward_max_backoff_ms,
+            kafka_max_in_flight,
+            no_file,
+            log_format,
+            verbose,
+            quiet,
+            dry_run,
+        );
+        merge_optional!(template, shutdown_drain_timeout_secs, http_ingest_port, tcp_max_connections, tcp_idle_timeout_secs, max_skew_secs, queue_warn_pct, send_timeout_ms, rotate_size_mb, rotate_interval, max_disk_mb, summary_output, dedup_window_ms, unix_socket, tls_cert, tls_key, forward_to, loki_url, kafka_brokers, kafka_topic, replay, replay_rate, dead_letter);
+
+        if args.queue_warn_pct.is_some_and(|pct| !(1..=100).contains(&pct)) {
+            return Err(SyslogError::Config("queue_warn_pct must be between 1 and 100".to_string()));
+        }
+        if args.summary_interval == 0 {
+            return Err(SyslogError::Config("summary_interval must be at least 1".to_string()));
+        }
+        if !(1..=65535).contains(&args.tcp_backlog) {
+            return Err(SyslogError::Config("tcp_backlog must be between 1 and 65535".to_string()));
+        }
+        if args.no_metrics && args.metrics_required {
+            return Err(SyslogError::Config("no_metrics and metrics_required can't both be set".to_string()));
+        }
+        if args.tcp_max_connections == Some(0) {
+            return Err(SyslogError::Config("tcp_max_connections must be at least 1".to_string()));
+        }
+        if args.tcp_idle_timeout_secs == Some(0) {
+            return Err(SyslogError::Config("tcp_idle_timeout_secs must be at least 1".to_string()));
+        }
+        if args.replay_rate == Some(0) {
+            return Err(SyslogError::Config("replay_rate must be at least 1".to_string()));
+        }
+        if args.unix_socket_mode > 0o777 {
+            return Err(SyslogError::Config("unix_socket_mode must be at most 0o777".to_string()));
+        }
+        if args.tls_cert.is_some() != args.tls_key.is_some() {
+            return Err(SyslogError::Config("tls_cert and tls_key must be set together".to_string()));
+        }
+        if args.verbose > 0 && args.quiet > 0 {
+            return Err(SyslogError::Config("verbose and quiet cannot both be set".to_string()));
+        }
+        if args.kafka_brokers.is_some() != args.kafka_topic.is_some() {
+            return Err(SyslogError::Config("kafka_brokers and kafka_topic must be set together".to_string()));
+        }
+        if args.replay_dead_letter && args.dead_letter.is_none() {
+            return Err(SyslogError::Config("replay_dead_letter requires dead_letter".to_string()));
+        }
+        Ok(())
+    }
+}
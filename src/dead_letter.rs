@@ -0,0 +1,70 @@
+use std::path::{Path, PathBuf};
+
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tracing::warn;
+
+use crate::{SysLogEntry, SyslogError};
+
+/// Appends `entries` to the dead-letter file at `path` as JSON lines.
+pub async fn spill(path: &Path, entries: &[SysLogEntry]) -> Result<(), SyslogError> {
+    let mut lines = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut lines, entry)?;
+        lines.push(b'\n');
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path).await?;
+    file.write_all(&lines).await?;
+    file.sync_data().await?;
+    Ok(())
+}
+
+/// Reads every entry in the dead-letter file at `path` to be replayed. The
+/// file is first moved aside to `<path>.replaying`, so entries that fail
+/// again are spilled into a fresh file, and is only removed by `finish` once
+/// the replay is done. A `.replaying` file left behind by a replay that was
+/// cut short is read again, along with anything spilled since. Lines that
+/// can't be parsed are dropped with a warning; a missing file holds no
+/// entries.
+pub async fn take(path: &Path) -> Result<Vec<SysLogEntry>, SyslogError> {
+    let replaying = replaying_path(path);
+    match fs::read(path).await {
+        Ok(spilled) if fs::try_exists(&replaying).await? => {
+            let mut file = OpenOptions::new().append(true).open(&replaying).await?;
+            file.write_all(&spilled).await?;
+            file.sync_data().await?;
+            fs::remove_file(path).await?;
+        }
+        Ok(_) => fs::rename(path, &replaying).await?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    let contents = match fs::read_to_string(&replaying).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut entries = Vec::new();
+    for (number, line) in contents.lines().enumerate().filter(|(_, line)| !line.is_empty()) {
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => warn!("Dropping unreadable line {} of {}: {}", number + 1, replaying.display(), e),
+        }
+    }
+    Ok(entries)
+}
+
+/// Removes the entries `take` read from `path`, once they have all been
+/// written or spilled again.
+pub async fn finish(path: &Path) -> Result<(), SyslogError> {
+    match fs::remove_file(replaying_path(path)).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn replaying_path(path: &Path) -> PathBuf {
+    let mut replaying = path.as_os_str().to_owned();
+    replaying.push(".replaying");
+    PathBuf::from(replaying)
+}
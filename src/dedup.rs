@@ -0,0 +1,162 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+
+use crate::{SysLogEntry, TimestampFormat};
+
+/// Upper bound on the number of distinct messages tracked at once.
+const DEDUP_CAPACITY: usize = 10_000;
+
+/// Suppresses identical messages from the same source that repeat within a
+/// time window.
+///
+/// The first occurrence is written as usual. Repeats inside the window are
+/// only counted, and once the window closes a summary entry carrying the
+/// original message and its `repeat_count` is handed back by `take_closed`.
+/// Tracking is bounded by an LRU, so memory stays flat however many distinct
+/// messages arrive; evicted windows are summarized early.
+pub struct Deduplicator {
+    window: Duration,
+    /// Used for the `event_time` of summaries, which is when they close.
+    timestamp_format: TimestampFormat,
+    recent: LruCache<u64, Window>,
+    closed: Vec<SysLogEntry>,
+}
+
+struct Window {
+    opened: Instant,
+    repeats: u64,
+    entry: SysLogEntry,
+}
+
+impl Deduplicator {
+    pub fn new(window: Duration, timestamp_format: TimestampFormat) -> Self {
+        Deduplicator {
+            window,
+            timestamp_format,
+            recent: LruCache::new(NonZeroUsize::new(DEDUP_CAPACITY).expect("capacity is non-zero")),
+            closed: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if `entry` repeats a message seen within the window and
+    /// should be suppressed.
+    pub fn is_duplicate(&mut self, entry: &SysLogEntry) -> bool {
+        let key = Self::key(entry);
+        let now = Instant::now();
+        if let Some(window) = self.recent.get_mut(&key) {
+            if now.duration_since(window.opened) < self.window {
+                window.repeats += 1;
+                return true;
+            }
+        }
+
+        let window = Window {
+            opened: now,
+            repeats: 0,
+            entry: entry.clone(),
+        };
+        // Either the expired window for this key or the least recently used
+        // one comes back out.
+        if let Some((_, replaced)) = self.recent.push(key, window) {
+            self.close(replaced);
+        }
+        false
+    }
+
+    /// Takes summaries for every window that has closed, or for all windows
+    /// when `all` is set (e.g. at shutdown).
+    pub fn take_closed(&mut self, all: bool) -> Vec<SysLogEntry> {
+        let expired: Vec<u64> = self
+            .recent
+            .iter()
+            .filter(|(_, window)| all || window.opened.elapsed() >= self.window)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in expired {
+            if let Some(window) = self.recent.pop(&key) {
+                self.close(window);
+            }
+        }
+        std::mem::take(&mut self.closed)
+    }
+
+    fn close(&mut self, window: Window) {
+        if window.repeats == 0 {
+            return;
+        }
+        let mut summary = window.entry;
+        summary.event_time = self.timestamp_format.format(chrono::Local::now());
+        summary.repeat_count = Some(window.repeats);
+        self.closed.push(summary);
+    }
+
+    fn key(entry: &SysLogEntry) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        entry.device_ip.hash(&mut hasher);
+        entry.listen_port.hash(&mut hasher);
+        entry.facility.hash(&mut hasher);
+        entry.severity.hash(&mut hasher);
+        entry.syslog.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(syslog: &str) -> SysLogEntry {
+        SysLogEntry {
+            device_ip: "10.0.0.1".to_string(),
+            syslog: syslog.to_string(),
+            ..SysLogEntry::default()
+        }
+    }
+
+    #[test]
+    fn repeats_within_the_window_are_summarized_once_it_closes() {
+        let mut dedup = Deduplicator::new(Duration::from_millis(200), TimestampFormat::default());
+        assert!(!dedup.is_duplicate(&entry("disk full")));
+        assert!(dedup.is_duplicate(&entry("disk full")));
+        assert!(dedup.is_duplicate(&entry("disk full")));
+        assert!(dedup.take_closed(false).is_empty());
+
+        std::thread::sleep(Duration::from_millis(250));
+        let summaries = dedup.take_closed(false);
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].syslog, "disk full");
+        assert_eq!(summaries[0].repeat_count, Some(2));
+        assert!(dedup.take_closed(false).is_empty());
+    }
+
+    #[test]
+    fn a_window_without_repeats_closes_silently() {
+        let mut dedup = Deduplicator::new(Duration::from_millis(200), TimestampFormat::default());
+        assert!(!dedup.is_duplicate(&entry("disk full")));
+        assert!(!dedup.is_duplicate(&entry("disk ok")));
+        std::thread::sleep(Duration::from_millis(250));
+        assert!(dedup.take_closed(false).is_empty());
+        assert!(dedup.take_closed(true).is_empty());
+    }
+
+    #[test]
+    fn evicted_windows_are_summarized_early() {
+        let mut dedup = Deduplicator::new(Duration::from_secs(3600), TimestampFormat::default());
+        assert!(!dedup.is_duplicate(&entry("disk full")));
+        assert!(dedup.is_duplicate(&entry("disk full")));
+        for i in 0..DEDUP_CAPACITY {
+            assert!(!dedup.is_duplicate(&entry(&format!("message {}", i))));
+        }
+
+        let summaries = dedup.take_closed(false);
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].syslog, "disk full");
+        assert_eq!(summaries[0].repeat_count, Some(1));
+        // Its window is gone, so the message is written again
+        assert!(!dedup.is_duplicate(&entry("disk full")));
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+use metrics::{gauge, increment_counter};
+
+/// Upper bound on the number of source addresses remembered at once.
+const CACHE_CAPACITY: usize = 10_000;
+
+/// How long a lookup result, including a failed one, is reused.
+const CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// Reverse DNS names for source addresses, resolved in the background.
+///
+/// `hostname` never waits on DNS. An address that isn't cached (or whose
+/// entry has expired) gets no name this time, and a lookup is started so
+/// later messages from it find one. Failed lookups are cached like
+/// successful ones, so an address without a PTR record is not retried on
+/// every message.
+pub struct HostnameCache {
+    state: Mutex<CacheState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+struct CacheState {
+    names: LruCache<IpAddr, (Instant, Option<String>)>,
+    /// Addresses with a lookup in flight.
+    pending: HashSet<IpAddr>,
+}
+
+impl HostnameCache {
+    pub fn new() -> Self {
+        HostnameCache {
+            state: Mutex::new(CacheState {
+                names: LruCache::new(NonZeroUsize::new(CACHE_CAPACITY).expect("capacity is non-zero")),
+                pending: HashSet::new(),
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// The cached name for `source_ip`, if any. Must be called from within
+    /// the Tokio runtime, which runs any lookup this starts.
+    pub fn hostname(self: &Arc<Self>, source_ip: &str) -> Option<String> {
+        let ip: IpAddr = source_ip.parse().ok()?;
+        let mut state = self.state.lock().unwrap();
+        if let Some((resolved, name)) = state.names.get(&ip) {
+            if resolved.elapsed() < CACHE_TTL {
+                let name = name.clone();
+                drop(state);
+                self.record(true);
+                return name;
+            }
+        }
+        let start_lookup = state.pending.insert(ip);
+        drop(state);
+        self.record(false);
+
+        if start_lookup {
+            let cache = Arc::clone(self);
+            tokio::spawn(async move {
+                increment_counter!("syslog_dns_lookups_total");
+                // getnameinfo blocks, so it runs on the blocking pool just
+                // like tokio's own forward lookups.
+                let name = tokio::task::spawn_blocking(move || dns_lookup::lookup_addr(&ip).ok())
+                    .await
+                    .ok()
+                    .flatten()
+                    .filter(|name| name.parse::<IpAddr>().is_err());
+                let mut state = cache.state.lock().unwrap();
+                state.pending.remove(&ip);
+                state.names.put(ip, (Instant::now(), name));
+            });
+        }
+        None
+    }
+
+    fn record(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        let hits = self.hits.load(Ordering::Relaxed) as f64;
+        let misses = self.misses.load(Ordering::Relaxed) as f64;
+        gauge!("syslog_dns_cache_hit_ratio", hits / (hits + misses));
+    }
+}
@@ -0,0 +1,130 @@
+use std::io;
+
+use metrics::increment_counter;
+use thiserror::Error;
+
+/// What went wrong setting up the server, or receiving, parsing or writing a
+/// message. Only `run` boxes it, along with the errors of the libraries it
+/// starts up with.
+#[derive(Debug, Error)]
+pub enum SyslogError {
+    /// The `<PRI>` opening a message is missing or invalid.
+    #[error("{0}")]
+    MalformedPriority(String),
+    /// The header after the PRI follows neither RFC5424 nor RFC3164.
+    #[error("{0}")]
+    MalformedHeader(String),
+    /// A connection's PROXY protocol header is missing or invalid.
+    #[error("{0}")]
+    MalformedProxyHeader(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// An entry couldn't be encoded for an output, or decoded from one.
+    #[error("{0}")]
+    Encoding(String),
+    /// The processor or a sink has stopped taking messages.
+    #[error("the processing channel is closed")]
+    ChannelSend,
+    /// An output can't be used, or its Parquet or SQLite backend failed.
+    #[error("{0}")]
+    Output(String),
+    /// A listening socket couldn't be bound or set up, or a relay couldn't
+    /// be reached.
+    #[error("{0}")]
+    Socket(String),
+    /// A setting, config file, template or TLS certificate is invalid.
+    #[error("{0}")]
+    Config(String),
+}
+
+impl SyslogError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            SyslogError::MalformedPriority(_)
+            | SyslogError::MalformedHeader(_)
+            | SyslogError::MalformedProxyHeader(_)
+            | SyslogError::Config(_) => ErrorKind::Parse,
+            SyslogError::Io(_) | SyslogError::Output(_) | SyslogError::Socket(_) => ErrorKind::Io,
+            SyslogError::Encoding(_) => ErrorKind::Encoding,
+            SyslogError::ChannelSend => ErrorKind::Send,
+        }
+    }
+
+    /// Counts this error in `syslog_errors_total`, once it has been handled.
+    pub fn count(&self) {
+        count_error(self.kind());
+    }
+}
+
+/// The `kind` label of `syslog_errors_total`, kept to these few values so
+/// the counter never grows new series.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A message, frame or PROXY header that couldn't be parsed, or an
+    /// invalid setting.
+    Parse,
+    /// A socket or output that failed to bind, read or write.
+    Io,
+    /// Text that wasn't valid UTF-8, or an entry that couldn't be encoded.
+    Encoding,
+    /// A message that couldn't be queued for processing.
+    Send,
+}
+
+impl ErrorKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::Parse => "parse",
+            ErrorKind::Io => "io",
+            ErrorKind::Encoding => "encoding",
+            ErrorKind::Send => "send",
+        }
+    }
+}
+
+/// Counts a handled error of `kind` in `syslog_errors_total`.
+pub fn count_error(kind: ErrorKind) {
+    increment_counter!("syslog_errors_total", "kind" => kind.as_str());
+}
+
+impl From<csv::Error> for SyslogError {
+    fn from(e: csv::Error) -> Self {
+        if !e.is_io_error() {
+            return SyslogError::Encoding(e.to_string());
+        }
+        match e.into_kind() {
+            csv::ErrorKind::Io(e) => SyslogError::Io(e),
+            kind => SyslogError::Encoding(format!("{:?}", kind)),
+        }
+    }
+}
+
+impl From<serde_json::Error> for SyslogError {
+    fn from(e: serde_json::Error) -> Self {
+        if e.is_io() {
+            SyslogError::Io(e.into())
+        } else {
+            SyslogError::Encoding(e.to_string())
+        }
+    }
+}
+
+impl<T> From<tokio::sync::mpsc::error::SendError<T>> for SyslogError {
+    fn from(_: tokio::sync::mpsc::error::SendError<T>) -> Self {
+        SyslogError::ChannelSend
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl From<parquet::errors::ParquetError> for SyslogError {
+    fn from(e: parquet::errors::ParquetError) -> Self {
+        SyslogError::Output(e.to_string())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for SyslogError {
+    fn from(e: rusqlite::Error) -> Self {
+        SyslogError::Output(e.to_string())
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use metrics::increment_counter;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{error, info, warn};
+
+use crate::SyslogError;
+
+/// Delay before the first retry after the relay fails; doubled on every
+/// further failure up to the configured maximum.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Longest a TCP relay may take to accept a connection or a message before
+/// the attempt counts as failed.
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// PRI used for relayed messages that arrived without one (user.notice, the
+/// RFC3164 default).
+const DEFAULT_PRI: &str = "<13>";
+
+#[derive(clap::ValueEnum, serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ForwardProtocol {
+    Udp,
+    /// RFC6587 octet-counted framing over TCP
+    Tcp,
+}
+
+/// Relays every message received on `rx` to the upstream collector at
+/// `target`. Failures are counted in `syslog_forward_errors_total` and never
+/// propagate back to local processing.
+///
+/// While the relay is failing, messages wait in a backlog of at most
+/// `buffer_size` entries and sending is retried with exponential backoff up
+/// to `max_backoff`. Once a send succeeds the backlog is flushed in order.
+/// Messages arriving at a full backlog are counted in
+/// `syslog_forward_dropped_total`. Connecting and sending over TCP each give
+/// up after `SEND_TIMEOUT`, so an upstream that stops reading is retried
+/// like one that is down.
+pub async fn run_forwarder(
+    target: SocketAddr,
+    protocol: ForwardProtocol,
+    mut rx: mpsc::Receiver<String>,
+    buffer_size: usize,
+    max_backoff: Duration,
+) {
+    info!("Forwarding messages to {} over {:?}", target, protocol);
+    let max_backoff = max_backoff.max(INITIAL_BACKOFF);
+    let mut relay = Relay::new(target, protocol);
+    let mut backlog = VecDeque::new();
+    let mut backoff = INITIAL_BACKOFF;
+    let mut retry_at: Option<Instant> = None;
+    let mut closed = false;
+
+    loop {
+        if backlog.is_empty() {
+            if closed {
+                break;
+            }
+            match rx.recv().await {
+                Some(message) => backlog.push_back(with_priority(&message)),
+                None => break,
+            }
+        }
+
+        // Keep accepting (or dropping) new messages until the next attempt.
+        if let Some(at) = retry_at {
+            tokio::select! {
+                received = rx.recv(), if !closed => match received {
+                    Some(message) if backlog.len() < buffer_size => backlog.push_back(with_priority(&message)),
+                    Some(_) => increment_counter!("syslog_forward_dropped_total"),
+                    None => closed = true,
+                },
+                _ = tokio::time::sleep_until(at) => retry_at = None,
+            }
+            continue;
+        }
+
+        while let Some(message) = backlog.front() {
+            match relay.send(message).await {
+                Ok(()) => {
+                    backlog.pop_front();
+                    backoff = INITIAL_BACKOFF;
+                }
+                Err(e) => {
+                    increment_counter!("syslog_forward_errors_total");
+                    warn!(
+                        "Failed to forward to {}: {}; retrying in {:?} with {} messages buffered",
+                        target,
+                        e,
+                        backoff,
+                        backlog.len()
+                    );
+                    retry_at = Some(Instant::now() + backoff);
+                    backoff = (backoff * 2).min(max_backoff);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Makes sure the relay sees a valid syslog frame by keeping the sender's
+/// original PRI, or adding the default one if there was none.
+fn with_priority(message: &str) -> String {
+    let message = message.trim_end_matches(['\r', '\n']);
+    if message.starts_with('<') {
+        message.to_string()
+    } else {
+        format!("{}{}", DEFAULT_PRI, message)
+    }
+}
+
+struct Relay {
+    target: SocketAddr,
+    protocol: ForwardProtocol,
+    udp: Option<UdpSocket>,
+    tcp: Option<TcpStream>,
+}
+
+impl Relay {
+    fn new(target: SocketAddr, protocol: ForwardProtocol) -> Self {
+        Relay {
+            target,
+            protocol,
+            udp: None,
+            tcp: None,
+        }
+    }
+
+    async fn send(&mut self, message: &str) -> Result<(), SyslogError> {
+        match self.protocol {
+            ForwardProtocol::Udp => {
+                if self.udp.is_none() {
+                    let local: SocketAddr = if self.target.is_ipv4() {
+                        ([0, 0, 0, 0], 0).into()
+                    } else {
+                        ([0u16; 8], 0).into()
+                    };
+                    let socket = UdpSocket::bind(local).await?;
+                    socket.connect(self.target).await?;
+                    self.udp = Some(socket);
+                }
+                if let Some(socket) = &self.udp {
+                    socket.send(message.as_bytes()).await?;
+                }
+            }
+            ForwardProtocol::Tcp => {
+                if self.tcp.is_none() {
+                    let stream = tokio::time::timeout(SEND_TIMEOUT, TcpStream::connect(self.target))
+                        .await
+                        .map_err(|_| SyslogError::Socket(format!("not connected within {:?}", SEND_TIMEOUT)))??;
+                    self.tcp = Some(stream);
+                    info!("Connected to relay {}", self.target);
+                }
+                let frame = format!("{} {}", message.len(), message);
+                if let Some(stream) = &mut self.tcp {
+                    let written = tokio::time::timeout(SEND_TIMEOUT, stream.write_all(frame.as_bytes()))
+                        .await
+                        .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, format!("not sent within {:?}", SEND_TIMEOUT))));
+                    if let Err(e) = written {
+                        // Reconnect on the next message; a frame cut short
+                        // by the timeout leaves this stream unusable anyway.
+                        self.tcp = None;
+                        error!("Lost connection to relay {}", self.target);
+                        return Err(e.into());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn messages_wait_for_a_relay_that_comes_back() {
+        crate::test_metrics::install();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel(64);
+        let forwarder = tokio::spawn(run_forwarder(target, ForwardProtocol::Tcp, rx, 3, Duration::from_millis(200)));
+
+        tx.send("<13>first".to_string()).await.unwrap();
+        let (mut upstream, _) = listener.accept().await.unwrap();
+        let mut buf = vec![0; 64];
+        let size = upstream.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..size], b"9 <13>first");
+
+        // The relay goes away, resetting the connection so the next send
+        // fails rather than vanishing into the dead socket.
+        upstream.set_zero_linger().unwrap();
+        drop(upstream);
+        drop(listener);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Three wait in the backlog; the rest find it full.
+        for n in 0..10 {
+            tx.send(format!("<13>m{}", n)).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(crate::test_metrics::counter("syslog_forward_dropped_total", &[]), 7);
+        assert!(crate::test_metrics::counter("syslog_forward_errors_total", &[]) >= 1);
+
+        let listener = TcpListener::bind(target).await.unwrap();
+        let (mut upstream, _) = tokio::time::timeout(Duration::from_secs(5), listener.accept()).await.unwrap().unwrap();
+        drop(tx);
+        let mut received = Vec::new();
+        upstream.read_to_end(&mut received).await.unwrap();
+        assert_eq!(String::from_utf8(received).unwrap(), "6 <13>m06 <13>m16 <13>m2");
+        forwarder.await.unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use tokio::sync::watch;
+use tracing::{error, info};
+
+use crate::status::{self, StatusHistory};
+use crate::SyslogError;
+use crate::tail::TailBuffer;
+
+/// Parsing a message takes microseconds.
+const PARSE_BUCKETS: &[f64] = &[1e-6, 5e-6, 1e-5, 2.5e-5, 5e-5, 1e-4, 2.5e-4, 5e-4, 1e-3, 5e-3];
+
+/// Writing a batch is dominated by I/O.
+const WRITE_BUCKETS: &[f64] = &[1e-4, 5e-4, 1e-3, 2.5e-3, 5e-3, 0.01, 0.025, 0.05, 0.1, 0.5, 1.0];
+
+/// Message sizes, up to the largest UDP payload.
+const SIZE_BUCKETS: &[f64] = &[64.0, 128.0, 256.0, 512.0, 1024.0, 2048.0, 4096.0, 8192.0, 16384.0, 65536.0];
+
+/// Clock skew, from a few seconds up to a day or more.
+const SKEW_BUCKETS: &[f64] = &[1.0, 5.0, 30.0, 60.0, 300.0, 900.0, 3600.0, 21600.0, 86400.0];
+
+/// Entries returned by `/tail` without a `limit`.
+const DEFAULT_TAIL_LIMIT: usize = 50;
+
+/// Liveness state reported by `/healthz`.
+pub struct Health {
+    started: Instant,
+    task_down: AtomicBool,
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Health {
+            started: Instant::now(),
+            task_down: AtomicBool::new(false),
+        }
+    }
+
+    /// Records that a receive, processing or sink task has exited or
+    /// panicked, after which the server is running degraded.
+    pub fn mark_task_down(&self) {
+        self.task_down.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        !self.task_down.load(Ordering::Relaxed)
+    }
+}
+
+/// Serves `/metrics` in the Prometheus text format, `/healthz` for liveness
+/// probes, `/status` for a plain-text summary with recent rates and, with a
+/// `tail` buffer, `/tail?limit=N` for the latest entries as JSON, all on the
+/// same port, until `shutdown` fires.
+///
+/// The port is bound before this returns, so a failure to bind it is
+/// reported to the caller; the server then runs on its own task.
+pub fn start_metrics_server(
+    port: u16,
+    health: Arc<Health>,
+    tail: Option<Arc<TailBuffer>>,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), SyslogError> {
+    let recorder = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Full("syslog_parse_duration_seconds".to_string()), PARSE_BUCKETS)
+        .and_then(|builder| builder.set_buckets_for_metric(Matcher::Full("syslog_write_duration_seconds".to_string()), WRITE_BUCKETS))
+        .and_then(|builder| builder.set_buckets_for_metric(Matcher::Full("syslog_message_size_bytes".to_string()), SIZE_BUCKETS))
+        .and_then(|builder| builder.set_buckets_for_metric(Matcher::Full("syslog_clock_skew_seconds".to_string()), SKEW_BUCKETS))
+        .map_err(|e| SyslogError::Config(format!("Invalid metrics buckets: {}", e)))?
+        .build_recorder();
+    let handle = recorder.handle();
+    let history = Arc::new(StatusHistory::new());
+
+    let sampler = {
+        let (handle, history, mut shutdown) = (handle.clone(), Arc::clone(&history), shutdown.clone());
+        async move {
+            let mut interval = tokio::time::interval(status::SAMPLE_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => history.record(Instant::now(), &handle.render()),
+                    _ = shutdown.wait_for(|&stop| stop) => return,
+                }
+            }
+        }
+    };
+
+    let make_service = make_service_fn(move |_| {
+        let handle = handle.clone();
+        let health = Arc::clone(&health);
+        let history = Arc::clone(&history);
+        let tail = tail.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let response = route(&request, &handle, &health, &history, tail.as_deref());
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&([0, 0, 0, 0], port).into())
+        .map_err(|e| SyslogError::Socket(format!("Failed to bind metrics port {}: {}", port, e)))?;
+    metrics::set_boxed_recorder(Box::new(recorder)).map_err(|e| SyslogError::Config(e.to_string()))?;
+    let server = server.serve(make_service).with_graceful_shutdown(async move {
+        let _ = shutdown.wait_for(|&stop| stop).await;
+    });
+    info!("Serving metrics on port {}", port);
+    tokio::spawn(sampler);
+    tokio::spawn(async move {
+        match server.await {
+            Ok(()) => info!("Metrics server stopped"),
+            Err(e) => error!("Metrics server error: {}", e),
+        }
+    });
+    Ok(())
+}
+
+fn route(
+    request: &Request<Body>,
+    handle: &PrometheusHandle,
+    health: &Health,
+    history: &StatusHistory,
+    tail: Option<&TailBuffer>,
+) -> Response<Body> {
+    match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => Response::new(Body::from(handle.render())),
+        (&Method::GET, "/status") => {
+            let report = history.report(Instant::now(), &handle.render(), health.started.elapsed(), health.is_healthy());
+            Response::builder()
+                .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+                .body(Body::from(report))
+                .expect("static response is valid")
+        }
+        (&Method::GET, "/healthz") => {
+            let (status, label) = if health.is_healthy() {
+                (StatusCode::OK, "ok")
+            } else {
+                (StatusCode::SERVICE_UNAVAILABLE, "unhealthy")
+            };
+            let body = serde_json::json!({
+                "status": label,
+                "uptime_secs": health.started.elapsed().as_secs(),
+            });
+            Response::builder()
+                .status(status)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .expect("static response is valid")
+        }
+        (&Method::GET, "/tail") if tail.is_some() => {
+            let limit = request
+                .uri()
+                .query()
+                .unwrap_or("")
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("limit="))
+                .map(str::parse);
+            let (status, body) = match limit.unwrap_or(Ok(DEFAULT_TAIL_LIMIT)) {
+                Ok(limit) => {
+                    let entries = tail.expect("checked by the match guard").recent(limit);
+                    (StatusCode::OK, serde_json::to_string(&entries).expect("entries serialize to JSON"))
+                }
+                Err(_) => (StatusCode::BAD_REQUEST, serde_json::json!({"error": "limit must be a number"}).to_string()),
+            };
+            Response::builder()
+                .status(status)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .expect("static response is valid")
+        }
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .expect("static response is valid"),
+    }
+}
@@ -0,0 +1,233 @@
+use std::borrow::Cow;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use hyper::body::HttpBody;
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use metrics::increment_counter;
+use tokio::sync::{mpsc, watch};
+use tracing::{error, warn};
+
+use crate::acl::SourceFilter;
+use crate::queue::QueueMonitor;
+use crate::{RawMessage, SyslogError};
+
+/// Largest request body read; a webhook batching more than this is refused.
+const MAX_BODY_BYTES: usize = 4 * 1024 * 1024;
+
+/// Settings for the `POST /ingest` endpoint.
+pub struct IngestSettings {
+    pub port: u16,
+    /// Take device_ip from the first `X-Forwarded-For` address rather than
+    /// the connection's peer.
+    pub trust_xff: bool,
+    /// Longest message accepted; a request holding a longer one is refused.
+    pub max_message_bytes: usize,
+    /// Swapped out when the config is reloaded.
+    pub sources: Arc<ArcSwap<SourceFilter>>,
+    /// Longest wait for queue space before the rest of a request is refused
+    /// with 503; without it a request waits for as long as it takes.
+    pub send_timeout: Option<Duration>,
+    pub queue: Arc<QueueMonitor>,
+}
+
+/// Serves `POST /ingest` on `listener` until `shutdown` fires, feeding each
+/// message in the body into the processing channel.
+pub fn start_ingest_server(
+    listener: std::net::TcpListener,
+    settings: Arc<IngestSettings>,
+    tx: mpsc::Sender<RawMessage>,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), SyslogError> {
+    let make_service = make_service_fn(move |connection: &AddrStream| {
+        let peer = connection.remote_addr();
+        let settings = Arc::clone(&settings);
+        let tx = tx.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let settings = Arc::clone(&settings);
+                let tx = tx.clone();
+                async move { Ok::<_, Infallible>(ingest(request, peer, &settings, &tx).await) }
+            }))
+        }
+    });
+
+    let server = Server::from_tcp(listener)
+        .map_err(|e| SyslogError::Socket(format!("Failed to listen for HTTP ingest: {}", e)))?
+        .serve(make_service)
+        .with_graceful_shutdown(async move {
+            let _ = shutdown.wait_for(|&stop| stop).await;
+        });
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!("HTTP ingest server error: {}", e);
+        }
+    });
+    Ok(())
+}
+
+async fn ingest(request: Request<Body>, peer: SocketAddr, settings: &IngestSettings, tx: &mpsc::Sender<RawMessage>) -> Response<Body> {
+    if request.uri().path() != "/ingest" {
+        return reply(StatusCode::NOT_FOUND, serde_json::json!({"error": "not found"}));
+    }
+    if request.method() != Method::POST {
+        return reply(StatusCode::METHOD_NOT_ALLOWED, serde_json::json!({"error": "use POST"}));
+    }
+    let device_ip = client_ip(&request, peer, settings.trust_xff);
+    if !settings.sources.load().permits(device_ip) {
+        increment_counter!("syslog_denied_total");
+        return reply(StatusCode::FORBIDDEN, serde_json::json!({"error": "source not allowed"}));
+    }
+    let json = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+
+    let body = match read_body(request.into_body()).await {
+        Ok(body) => body,
+        Err((status, e)) => return rejected(status, e),
+    };
+    let messages = match split_messages(&body, json) {
+        Ok(messages) => messages,
+        Err(e) => return rejected(StatusCode::BAD_REQUEST, e),
+    };
+    if let Some(long) = messages.iter().find(|message| message.len() > settings.max_message_bytes) {
+        let e = format!("a {}-byte message is over --max-message-bytes {}", long.len(), settings.max_message_bytes);
+        return rejected(StatusCode::PAYLOAD_TOO_LARGE, e);
+    }
+
+    let source = device_ip.to_string();
+    let mut accepted = 0;
+    for message in messages {
+        let message = RawMessage::decode(source.clone(), Some(settings.port), &message);
+        let sent = match settings.send_timeout {
+            Some(timeout) => match tx.send_timeout(message, timeout).await {
+                Err(mpsc::error::SendTimeoutError::Timeout(_)) => {
+                    increment_counter!("syslog_send_timeout_total");
+                    Err(format!("the queue stayed full for {:?}", timeout))
+                }
+                sent => sent.map_err(|e| e.to_string()),
+            },
+            None => tx.send(message).await.map_err(|e| e.to_string()),
+        };
+        if let Err(e) = sent {
+            warn!("Refused the rest of a request from {}: {}", source, e);
+            return reply(StatusCode::SERVICE_UNAVAILABLE, serde_json::json!({"error": e, "accepted": accepted}));
+        }
+        settings.queue.record(tx);
+        accepted += 1;
+    }
+    reply(StatusCode::ACCEPTED, serde_json::json!({"accepted": accepted}))
+}
+
+/// The leftmost `X-Forwarded-For` address, being the original client, when
+/// trusted and valid; otherwise the peer.
+fn client_ip(request: &Request<Body>, peer: SocketAddr, trust_xff: bool) -> IpAddr {
+    let forwarded = trust_xff
+        .then(|| request.headers().get("x-forwarded-for"))
+        .flatten()
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .and_then(|first| first.trim().parse().ok());
+    forwarded.unwrap_or(peer.ip())
+}
+
+/// Reads the whole body, failing with the status to reply with once it
+/// grows past `MAX_BODY_BYTES` or if it is cut off. Nothing from a body that
+/// didn't arrive whole is queued, as its last line may be incomplete.
+async fn read_body(mut body: Body) -> Result<Vec<u8>, (StatusCode, String)> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| (StatusCode::BAD_REQUEST, format!("the body was cut off: {}", e)))?;
+        if bytes.len() + chunk.len() > MAX_BODY_BYTES {
+            return Err((StatusCode::PAYLOAD_TOO_LARGE, format!("the body is over {} bytes", MAX_BODY_BYTES)));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// A JSON body is an array of message strings; any other body holds one
+/// message per line, with blank lines skipped.
+fn split_messages(body: &[u8], json: bool) -> Result<Vec<Cow<'_, [u8]>>, String> {
+    if !json {
+        return Ok(body
+            .split(|&b| b == b'\n')
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .filter(|line| !line.is_empty())
+            .map(Cow::Borrowed)
+            .collect());
+    }
+    let messages: Vec<String> =
+        serde_json::from_slice(body).map_err(|e| format!("expected a JSON array of strings: {}", e))?;
+    Ok(messages.into_iter().map(|message| Cow::Owned(message.into_bytes())).collect())
+}
+
+fn rejected(status: StatusCode, error: String) -> Response<Body> {
+    increment_counter!("syslog_http_ingest_rejected_total");
+    reply(status, serde_json::json!({"error": error}))
+}
+
+fn reply(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("static response is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bodies_split_into_messages() {
+        let raw = split_messages(b"<13>one\r\n\n<13>two\n", false).unwrap();
+        assert_eq!(raw, [&b"<13>one"[..], &b"<13>two"[..]]);
+
+        let json = split_messages(br#"["<13>one", "<13>say \"two\""]"#, true).unwrap();
+        assert_eq!(json, [&b"<13>one"[..], &br#"<13>say "two""#[..]]);
+        assert!(split_messages(br#"[{"message": "<13>one"}]"#, true).is_err());
+    }
+
+    #[test]
+    fn forwarded_for_is_only_used_when_trusted() {
+        let peer: SocketAddr = "192.0.2.1:40000".parse().unwrap();
+        let request = Request::post("/ingest")
+            .header("X-Forwarded-For", "203.0.113.7, 198.51.100.2")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(client_ip(&request, peer, true), "203.0.113.7".parse::<IpAddr>().unwrap());
+        assert_eq!(client_ip(&request, peer, false), peer.ip());
+
+        let request = Request::post("/ingest").header("X-Forwarded-For", "unknown").body(Body::empty()).unwrap();
+        assert_eq!(client_ip(&request, peer, true), peer.ip());
+    }
+
+    #[tokio::test]
+    async fn a_cut_off_body_is_rejected_without_queueing_any_of_it() {
+        let settings = IngestSettings {
+            port: 8514,
+            trust_xff: false,
+            max_message_bytes: 8192,
+            sources: Arc::new(ArcSwap::from_pointee(SourceFilter::default())),
+            send_timeout: None,
+            queue: Arc::new(QueueMonitor::new(None)),
+        };
+        let (tx, mut rx) = mpsc::channel(10);
+        let (mut sender, body) = Body::channel();
+        sender.try_send_data(hyper::body::Bytes::from("<13>one\n<13>tw")).unwrap();
+        sender.abort();
+
+        let request = Request::post("/ingest").body(body).unwrap();
+        let response = ingest(request, "192.0.2.1:40000".parse().unwrap(), &settings, &tx).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(rx.try_recv().is_err());
+    }
+}
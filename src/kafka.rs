@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use metrics::increment_counter;
+use rdkafka::config::ClientConfig;
+use rdkafka::producer::{BaseRecord, DeliveryResult, Producer, ProducerContext, ThreadedProducer};
+use rdkafka::ClientContext;
+use tokio::sync::mpsc;
+use tracing::{debug, error, info};
+
+use crate::{SysLogEntry, SyslogError};
+
+/// Longest wait at shutdown for in-flight messages to be acknowledged.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Counts messages the brokers never acknowledged.
+struct DeliveryCounter;
+
+impl ClientContext for DeliveryCounter {}
+
+impl ProducerContext for DeliveryCounter {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _: ()) {
+        if let Err((e, _)) = result {
+            increment_counter!("syslog_kafka_errors_total");
+            debug!("Kafka delivery failed: {}", e);
+        }
+    }
+}
+
+/// Produces entries to a Kafka topic as JSON, keyed by `device_ip`.
+pub struct KafkaSink {
+    producer: ThreadedProducer<DeliveryCounter>,
+    topic: String,
+}
+
+impl KafkaSink {
+    /// Configures a producer for the comma-separated `brokers`, holding at
+    /// most `max_in_flight` unacknowledged messages. librdkafka connects
+    /// lazily, so unreachable brokers only show up as delivery errors.
+    pub fn new(brokers: &str, topic: &str, max_in_flight: usize) -> Result<Self, SyslogError> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("queue.buffering.max.messages", max_in_flight.to_string())
+            .create_with_context(DeliveryCounter)
+            .map_err(|e| SyslogError::Output(format!("Failed to create Kafka producer for {}: {}", brokers, e)))?;
+        Ok(KafkaSink {
+            producer,
+            topic: topic.to_string(),
+        })
+    }
+
+    /// Produces every entry received on `rx` without waiting for delivery.
+    /// Entries that don't fit in the in-flight limit, and deliveries that
+    /// fail, are counted in `syslog_kafka_errors_total`. Once `rx` closes,
+    /// waits briefly for the remaining messages to be acknowledged.
+    pub async fn run(self, mut rx: mpsc::Receiver<SysLogEntry>) {
+        info!("Producing entries to Kafka topic {}", self.topic);
+        while let Some(entry) = rx.recv().await {
+            let payload = match serde_json::to_vec(&entry) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    error!("Failed to encode entry for Kafka: {}", e);
+                    continue;
+                }
+            };
+            let record = BaseRecord::to(&self.topic).key(&entry.device_ip).payload(&payload);
+            if let Err((e, _)) = self.producer.send(record) {
+                increment_counter!("syslog_kafka_errors_total");
+                debug!("Failed to produce to Kafka: {}", e);
+            }
+        }
+
+        let producer = self.producer;
+        match tokio::task::spawn_blocking(move || producer.flush(FLUSH_TIMEOUT)).await {
+            Ok(Ok(())) => {}
+            _ => error!("Timed out delivering remaining messages to Kafka"),
+        }
+    }
+}
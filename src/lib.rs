@@ -0,0 +1,2763 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Local, SecondsFormat};
+use arc_swap::ArcSwap;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use metrics::{counter, describe_counter, describe_gauge, describe_histogram, histogram, increment_counter};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, watch, Mutex};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::fmt::{format::FmtSpan, writer::BoxMakeWriter};
+use tracing_subscriber::{self, EnvFilter};
+use std::error::Error;
+
+mod acl;
+mod cef;
+mod check;
+mod config;
+mod dead_letter;
+mod dedup;
+mod dns;
+mod error;
+mod forward;
+mod http;
+mod ingest;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod loki;
+mod output;
+pub mod parser;
+mod proxy;
+mod queue;
+mod redact;
+#[cfg(unix)]
+mod reload;
+mod replay;
+mod sample;
+mod status;
+mod summary;
+mod tail;
+mod tcp;
+#[cfg(test)]
+mod test_metrics;
+mod tls;
+mod udp;
+#[cfg(unix)]
+mod unix;
+
+use dedup::Deduplicator;
+pub use error::SyslogError;
+use queue::QueueMonitor;
+use redact::Redactor;
+use sample::Sampler;
+use summary::Summarizer;
+use forward::ForwardProtocol;
+
+/// `event_time` layout of `--timestamp-format legacy`.
+const LEGACY_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+/// Facility and severity recorded for kept messages whose PRI was invalid.
+const MALFORMED_PRIORITY: u8 = 255;
+
+/// Pause before the first --bind-retry; each further one waits twice as long.
+const BIND_RETRY_BACKOFF: Duration = Duration::from_millis(250);
+
+/// `severity` label values for `syslog_received_by_severity`.
+const SEVERITY_LABELS: [&str; 8] = ["0", "1", "2", "3", "4", "5", "6", "7"];
+
+/// Standard syslog severity keywords, indexed by severity (RFC5424 table 2).
+const SEVERITY_NAMES: [&str; 8] = ["emergency", "alert", "critical", "error", "warning", "notice", "info", "debug"];
+
+/// Standard syslog facility keywords, indexed by facility (RFC5424 table 1).
+const FACILITY_NAMES: [&str; 24] = [
+    "kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news", "uucp", "cron", "authpriv", "ftp", "ntp",
+    "audit", "alert", "clock", "local0", "local1", "local2", "local3", "local4", "local5", "local6", "local7",
+];
+
+/// How long shutdown waits for queued messages to be relayed upstream.
+const FORWARD_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+use output::sink::{SinkSender, SinkSpec};
+use output::{CsvQuoteStyle, FsyncPolicy, OutputFormat, OutputOptions, OutputRouter, OutputWriter, RotateInterval, RotationPolicy};
+
+#[derive(clap::ValueEnum, serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Protocol {
+    Udp,
+    Tcp,
+    Both,
+}
+
+/// What to do with a message whose `<PRI>` header is invalid.
+#[derive(clap::ValueEnum, serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum OnMalformed {
+    Drop,
+    /// Write the raw line with facility and severity set to 255
+    Keep,
+}
+
+/// Which time goes in `event_time`; the other goes in `secondary_time`.
+#[derive(clap::ValueEnum, serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum EventTimeSource {
+    /// When the server received the message
+    #[default]
+    Received,
+    /// The message's own timestamp, falling back to when it was received
+    Parsed,
+}
+
+/// How `event_time` is written.
+#[derive(clap::ValueEnum, serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimestampFormat {
+    /// RFC3339 in local time with nanoseconds and the UTC offset
+    #[default]
+    Rfc3339,
+    /// Milliseconds since the Unix epoch
+    EpochMillis,
+    /// `2024-01-15 12:00:00.123` in local time, without an offset
+    Legacy,
+}
+
+impl TimestampFormat {
+    fn format(self, time: DateTime<Local>) -> String {
+        match self {
+            TimestampFormat::Rfc3339 => time.to_rfc3339_opts(SecondsFormat::Nanos, false),
+            TimestampFormat::EpochMillis => time.timestamp_millis().to_string(),
+            TimestampFormat::Legacy => time.format(LEGACY_TIME_FORMAT).to_string(),
+        }
+    }
+}
+
+/// Which ends of a message have their whitespace trimmed.
+#[derive(clap::ValueEnum, serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Trim {
+    /// Neither; the message is kept exactly as received
+    None,
+    /// Both
+    #[default]
+    Both,
+    /// Only the end, keeping leading indentation
+    Trailing,
+}
+
+impl Trim {
+    fn apply(self, mut message: String) -> String {
+        let start = match self {
+            Trim::None => return message,
+            Trim::Both => message.len() - message.trim_start().len(),
+            Trim::Trailing => 0,
+        };
+        message.truncate(message.trim_end().len());
+        message.drain(..start);
+        message
+    }
+}
+
+/// What happens to line breaks inside a message.
+#[derive(clap::ValueEnum, serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum NewlineHandling {
+    /// Remove them, joining the lines
+    #[default]
+    Strip,
+    /// Replace each one with a literal `\n`
+    Escape,
+    /// Keep them as they are; CSV output quotes the field
+    Keep,
+}
+
+impl NewlineHandling {
+    fn apply(self, message: String) -> String {
+        if !message.contains('\n') {
+            return message;
+        }
+        match self {
+            NewlineHandling::Strip => message.replace('\n', ""),
+            NewlineHandling::Escape => message.replace('\n', "\\n"),
+            NewlineHandling::Keep => message,
+        }
+    }
+}
+
+/// Format of the server's own diagnostic logs.
+#[derive(clap::ValueEnum, serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum LogFormat {
+    Text,
+    /// One JSON object per line, for log aggregation
+    Json,
+}
+
+impl Protocol {
+    fn udp(self) -> bool {
+        matches!(self, Protocol::Udp | Protocol::Both)
+    }
+
+    fn tcp(self) -> bool {
+        matches!(self, Protocol::Tcp | Protocol::Both)
+    }
+}
+
+#[derive(Parser, Serialize, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    #[serde(skip)]
+    command: Option<Command>,
+
+    /// TOML file with defaults for any of these options; flags given on the
+    /// command line take precedence
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Port to listen on; may be repeated to listen on several
+    #[arg(short, long, default_value = "514")]
+    port: Vec<u16>,
+
+    /// Address to listen on; `::` accepts both IPv6 and IPv4 senders
+    #[arg(long, default_value = "0.0.0.0")]
+    bind_address: IpAddr,
+
+    /// Only accept logs from this CIDR block; may be repeated. Without any,
+    /// all sources not denied are accepted
+    #[arg(long, value_parser = acl::parse_cidr)]
+    #[serde(serialize_with = "serialize_displayed")]
+    allow_cidr: Vec<ipnet::IpNet>,
+
+    /// Drop logs from this CIDR block, even if allowed; may be repeated
+    #[arg(long, value_parser = acl::parse_cidr)]
+    #[serde(serialize_with = "serialize_displayed")]
+    deny_cidr: Vec<ipnet::IpNet>,
+
+    /// Output file; strftime placeholders such as `syslog-%Y-%m-%d.csv`
+    /// start a new file whenever the date they give changes
+    #[arg(short, long, default_value = "syslog.csv")]
+    output: PathBuf,
+
+    #[arg(long, value_enum, default_value = "csv")]
+    output_format: OutputFormat,
+
+    /// Also write every entry to this `FORMAT:PATH` output, e.g.
+    /// `jsonl:/mnt/ship/syslog.jsonl`; may be repeated. Each sink is written
+    /// by its own task, and one that fails or falls behind drops its own
+    /// entries without holding up the others
+    #[arg(long, value_parser = output::sink::parse_sink)]
+    sink: Vec<SinkSpec>,
+
+    /// Also print entries to stdout in aligned columns, colored by severity
+    /// when stdout is a terminal. The server's own logs go to stderr instead
+    #[arg(long)]
+    console: bool,
+
+    /// Line format for `--output-format template`, e.g.
+    /// `{event_time} {device_ip} [{severity}] {syslog}`. Placeholders name
+    /// output columns; write `{{` and `}}` for literal braces
+    #[arg(long)]
+    template: Option<String>,
+
+    /// Field separator for CSV output, e.g. `|` or `\t` for tabs
+    #[arg(long, default_value = ",", value_parser = output::parse_csv_delimiter)]
+    #[serde(serialize_with = "serialize_delimiter")]
+    csv_delimiter: u8,
+
+    /// When CSV fields are quoted
+    #[arg(long, value_enum, default_value = "necessary")]
+    csv_quote_style: CsvQuoteStyle,
+
+    /// Comma-separated fields to write to CSV and JSON lines output, in
+    /// order, e.g. `event_time,device_ip,syslog`; all of them without it
+    #[arg(long, value_parser = output::fields::parse_fields)]
+    fields: Option<output::fields::FieldSelection>,
+
+    /// Move an RFC5424 SD-PARAM out of structured_data into a CSV column of
+    /// its own, as `SD-ID.PARAM=COLUMN`, e.g. `exampleSDID@32473.iut=iut`;
+    /// may be repeated
+    #[arg(long, value_parser = output::sd_column::parse_sd_column)]
+    #[serde(serialize_with = "serialize_displayed")]
+    sd_column: Vec<output::sd_column::SdColumn>,
+
+    /// Rows per Parquet row group; buffered rows are written at shutdown
+    #[arg(long, default_value = "10000")]
+    parquet_row_group_size: usize,
+
+    /// Rotate the output file once it grows past this many megabytes
+    #[arg(long)]
+    rotate_size_mb: Option<u64>,
+
+    /// Rotate the output file at every hour or day boundary
+    #[arg(long, value_enum)]
+    rotate_interval: Option<RotateInterval>,
+
+    /// When written output is forced onto the disk. `always` survives a
+    /// power cut at the cost of throughput
+    #[arg(long, value_enum, default_value = "never")]
+    fsync: FsyncPolicy,
+
+    /// Time between syncs with `--fsync interval`
+    #[arg(long, default_value = "1000")]
+    fsync_interval_ms: u64,
+
+    /// Gzip files once they have been rotated out
+    #[arg(long)]
+    compress_rotated: bool,
+
+    /// Disk budget for the output files and their rotated copies; the
+    /// oldest rotated files are deleted to stay within it
+    #[arg(long)]
+    max_disk_mb: Option<u64>,
+
+    /// Every --summary-interval seconds, append how many messages each source
+    /// sent at each severity to this JSON lines file
+    #[arg(long)]
+    summary_output: Option<PathBuf>,
+
+    /// Length of a --summary-output window in seconds
+    #[arg(long, default_value = "60", value_parser = clap::value_parser!(u64).range(1..))]
+    summary_interval: u64,
+
+    /// Only keep messages at this severity or more severe (0 = emergency,
+    /// 7 = debug)
+    #[arg(long, default_value = "7", value_parser = clap::value_parser!(u8).range(0..=7))]
+    min_severity: u8,
+
+    /// Treat one severity as another, e.g. `6=4` for appliances that send
+    /// warnings as informational; may be repeated. Applied before
+    /// --min-severity, keeping the severity sent as original_severity
+    #[arg(long, value_parser = parse_severity_remap)]
+    #[serde(serialize_with = "serialize_severity_remaps")]
+    severity_remap: Vec<(u8, u8)>,
+
+    /// Write a facility to its own file, e.g. `4=auth.csv`; may be repeated.
+    /// Unrouted facilities go to --output
+    #[arg(long, value_parser = parse_facility_route)]
+    #[serde(serialize_with = "serialize_facility_routes")]
+    facility_route: Vec<(u8, PathBuf)>,
+
+    /// Collapse identical messages from a source repeated within this many
+    /// milliseconds into a single summary entry with a repeat_count
+    #[arg(long)]
+    dedup_window_ms: Option<u64>,
+
+    /// Keep only this share of messages (0.0-1.0), chosen at random; the
+    /// rest are still counted as received
+    #[arg(long, default_value = "1.0", value_parser = sample::parse_rate)]
+    sample_rate: f64,
+
+    /// Sample a facility at its own rate instead, e.g. `7=0.1`; may be
+    /// repeated
+    #[arg(long, value_parser = sample::parse_facility_rate)]
+    #[serde(serialize_with = "serialize_facility_rates")]
+    sample_facility: Vec<(u8, f64)>,
+
+    /// Whether messages with an invalid PRI are dropped or kept
+    #[arg(long, value_enum, default_value = "drop")]
+    on_malformed: OnMalformed,
+
+    /// PRI given to messages that have none at all; the default of 13 is
+    /// user.notice, as RFC3164 suggests
+    #[arg(long, default_value = "13", value_parser = clap::value_parser!(u8).range(0..=191))]
+    default_priority: u8,
+
+    /// Also fill severity_name and facility_name (e.g. `warning`, `local0`)
+    #[arg(long)]
+    named_levels: bool,
+
+    /// Store IPv4 senders seen through a dual-stack socket as `192.0.2.1`
+    /// rather than `::ffff:192.0.2.1`
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    normalize_v4_mapped: bool,
+
+    /// Fill source_hostname with the reverse DNS name of device_ip. Names
+    /// are cached and looked up in the background, so the first messages
+    /// from a new source have none
+    #[arg(long)]
+    resolve_hostnames: bool,
+
+    /// Flag entries whose own timestamp is more than this many seconds
+    /// away from the time they were received as suspect_time
+    #[arg(long)]
+    max_skew_secs: Option<u64>,
+
+    /// Whether event_time is when the message was received or the timestamp
+    /// it carries; the other is written as secondary_time
+    #[arg(long, value_enum, default_value = "received")]
+    event_time_source: EventTimeSource,
+
+    /// How event_time is written; `legacy` is the offset-less format used
+    /// before RFC3339 became the default
+    #[arg(long, value_enum, default_value = "rfc3339")]
+    timestamp_format: TimestampFormat,
+
+    /// Whitespace trimmed from the ends of a message; `none` stores the
+    /// payload exactly as received
+    #[arg(long, value_enum, default_value = "both")]
+    trim: Trim,
+
+    /// What happens to line breaks inside a message, such as a multiline
+    /// stack trace
+    #[arg(long, value_enum, default_value = "strip")]
+    newline_handling: NewlineHandling,
+
+    /// Replace every match of this regex in the message with `***` before it
+    /// is stored, e.g. `password=\S+`; may be repeated
+    #[arg(long, value_parser = redact::parse_pattern)]
+    #[serde(serialize_with = "serialize_displayed")]
+    redact_pattern: Vec<regex::Regex>,
+
+    /// Also record each message exactly as received, before decoding or
+    /// newline handling, as base64 in a `raw` column
+    #[arg(long)]
+    keep_raw: bool,
+
+    /// Extract the header fields and extensions of CEF and LEEF messages
+    /// into a `parsed_fields` JSON column
+    #[arg(long)]
+    parse_cef: bool,
+
+    /// Add a `msg_hash` column: an xxHash3 of the message as stored, the same
+    /// across restarts, for grouping identical messages downstream
+    #[arg(long)]
+    hash_messages: bool,
+
+    #[arg(short, long, default_value = "9000")]
+    metrics_port: u16,
+
+    /// Refuse to start if the metrics port can't be bound, instead of
+    /// running on without metrics
+    #[arg(long)]
+    metrics_required: bool,
+
+    /// Don't serve metrics, /healthz, /status or /tail, or bind the metrics
+    /// port; metrics are then never recorded
+    #[arg(long, conflicts_with = "metrics_required")]
+    no_metrics: bool,
+
+    /// Keep this many of the latest entries in memory and serve them as
+    /// JSON at `/tail?limit=N` on the metrics port; 0 turns it off
+    #[arg(long, default_value = "0")]
+    tail_buffer: usize,
+
+    /// Distinct source IPs tracked individually in syslog_received_by_source;
+    /// any further sources are counted as `other`
+    #[arg(long, default_value = "100")]
+    max_source_labels: usize,
+
+    #[arg(long, default_value = "1000")]
+    queue_size: usize,
+
+    /// Warn, at most every ten seconds, while the queue is at least this
+    /// percent full
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    queue_warn_pct: Option<u8>,
+
+    /// Longest wait for queue space before a received message is dropped.
+    /// Without it UDP and Unix socket messages are dropped at once, while TCP
+    /// waits for as long as it takes
+    #[arg(long)]
+    send_timeout_ms: Option<u64>,
+
+    /// Longest message accepted; larger UDP datagrams are truncated and
+    /// larger TCP frames rejected
+    #[arg(long, default_value = "8192")]
+    max_message_bytes: usize,
+
+    /// Kernel receive buffer size requested for the UDP socket
+    #[arg(long, default_value = "262144")]
+    recv_buffer_bytes: usize,
+
+    /// Retry binding a port this many times, backing off from 250ms, if it
+    /// is still held, e.g. by the previous process during a restart
+    #[arg(long, default_value = "0")]
+    bind_retry: u32,
+
+    /// Transport(s) to listen on; TCP accepts RFC6587 octet-counted or
+    /// newline-delimited frames
+    #[arg(long, value_enum, default_value = "udp")]
+    protocol: Protocol,
+
+    /// Also receive datagrams from local daemons on this Unix socket, e.g.
+    /// `/dev/log`; entries from it have device_ip `local`
+    #[arg(long)]
+    unix_socket: Option<PathBuf>,
+
+    /// Octal permissions for --unix-socket
+    #[arg(long, default_value = "666", value_parser = parse_mode)]
+    #[serde(serialize_with = "serialize_mode")]
+    unix_socket_mode: u32,
+
+    /// PEM certificate chain; wraps TCP connections in TLS (RFC5425)
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Length of the queue of TCP connections waiting to be accepted; the
+    /// kernel may cap it (net.core.somaxconn on Linux)
+    #[arg(long, default_value = "1024", value_parser = clap::value_parser!(u32).range(1..=65535))]
+    tcp_backlog: u32,
+
+    /// Most TCP connections open at once, across all ports; further
+    /// connections are closed as soon as they are accepted
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    tcp_max_connections: Option<u32>,
+
+    /// Close TCP connections that send nothing for this many seconds
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    tcp_idle_timeout_secs: Option<u64>,
+
+    /// Expect a PROXY protocol v1 or v2 header on every TCP connection and
+    /// record the client it names as device_ip
+    #[arg(long)]
+    proxy_protocol: bool,
+
+    /// Also accept `POST /ingest` on this port, with one message per line or,
+    /// as `application/json`, an array of messages; for sources that can only
+    /// ship logs as HTTP webhooks
+    #[arg(long)]
+    http_ingest_port: Option<u16>,
+
+    /// Record the first `X-Forwarded-For` address of an ingest request as
+    /// device_ip; only safe behind a proxy that sets the header itself
+    #[arg(long, requires = "http_ingest_port")]
+    trust_xff: bool,
+
+    /// Number of entries written to the output in one go
+    #[arg(long, default_value = "100")]
+    batch_size: usize,
+
+    /// Longest time a partial batch waits before it is written
+    #[arg(long, default_value = "1000")]
+    flush_interval_ms: u64,
+
+    /// On shutdown, stop processing what is still queued after this many
+    /// seconds and abandon the rest; 0 exits straight away. Without it the
+    /// queue is drained fully
+    #[arg(long)]
+    shutdown_drain_timeout_secs: Option<u64>,
+
+    /// Times a failed batch write is retried before its entries are given up
+    /// on and spilled to --dead-letter
+    #[arg(long, default_value = "3")]
+    write_retries: u32,
+
+    /// Wait before the first retry of a failed write, doubled for each
+    /// further one
+    #[arg(long, default_value = "100")]
+    write_retry_backoff_ms: u64,
+
+    /// Append entries that could not be written even after retrying to this
+    /// file as JSON lines, instead of losing them
+    #[arg(long)]
+    dead_letter: Option<PathBuf>,
+
+    /// On startup, write the entries in --dead-letter out again, removing
+    /// them once they are written
+    #[arg(long, requires = "dead_letter")]
+    replay_dead_letter: bool,
+
+    /// Tasks parsing and batching messages from the queue. Each keeps its
+    /// own entries in arrival order, but their batches interleave
+    #[arg(long, default_value = "1")]
+    workers: usize,
+
+    /// Write entries in exactly the order they were queued, by running a
+    /// single worker whatever --workers says
+    #[arg(long)]
+    ordered: bool,
+
+    /// Also relay every received message to this upstream collector
+    #[arg(long, value_name = "HOST:PORT")]
+    forward_to: Option<String>,
+
+    #[arg(long, value_enum, default_value = "udp")]
+    forward_protocol: ForwardProtocol,
+
+    /// Messages held for the relay while it is unreachable
+    #[arg(long, default_value = "10000")]
+    forward_buffer_size: usize,
+
+    /// Longest wait between attempts to reach the relay
+    #[arg(long, default_value = "30000")]
+    forward_max_backoff_ms: u64,
+
+    /// Also produce every entry as JSON to these comma-separated Kafka
+    /// brokers (requires the `kafka` feature)
+    #[arg(long, value_name = "HOST:PORT,...", requires = "kafka_topic")]
+    kafka_brokers: Option<String>,
+
+    /// Topic for --kafka-brokers; messages are keyed by device_ip
+    #[arg(long, requires = "kafka_brokers")]
+    kafka_topic: Option<String>,
+
+    /// Entries waiting for Kafka to acknowledge them before further ones
+    /// are dropped
+    #[arg(long, default_value = "100000")]
+    kafka_max_in_flight: usize,
+
+    /// Also push every entry to Grafana Loki at this push API URL, e.g.
+    /// `http://loki:3100/loki/api/v1/push`, labelled by device_ip, facility
+    /// and severity
+    #[arg(long, value_name = "URL")]
+    loki_url: Option<String>,
+
+    /// Don't write --output, leaving Kafka, Loki, --console and any --sink outputs
+    #[arg(long)]
+    no_file: bool,
+
+    /// Instead of listening, feed the raw syslog lines in this file through
+    /// the pipeline, then flush and exit
+    #[arg(long)]
+    replay: Option<PathBuf>,
+
+    /// Replay at most this many lines per second
+    #[arg(long, requires = "replay", value_parser = clap::value_parser!(u32).range(1..))]
+    replay_rate: Option<u32>,
+
+    /// Format of the server's own logs (not the syslog output)
+    #[arg(long, value_enum, default_value = "text")]
+    log_format: LogFormat,
+
+    /// Log more of the server's own activity: once for debug, twice for
+    /// trace. Without -v or -q, `RUST_LOG` is honoured if set
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Log less: once for warnings and errors only, twice for errors only
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    quiet: u8,
+
+    /// Parse and count messages without writing or forwarding anything;
+    /// entries that would have been written are logged at debug level
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Print the settings in effect, after merging --config and the command
+    /// line, as JSON and exit
+    #[arg(long)]
+    #[serde(skip)]
+    print_config: bool,
+}
+
+/// Without a subcommand, the server runs.
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    /// Validate the settings and exit, without binding any socket or
+    /// touching the output files
+    Check,
+}
+
+impl Args {
+    /// Parses the command line and layers it over `--config`, if given.
+    fn load() -> Result<Self, SyslogError> {
+        let matches = Args::command().get_matches();
+        let mut args = Args::from_arg_matches(&matches).map_err(|e| SyslogError::Config(e.to_string()))?;
+        if let Some(path) = args.config.clone() {
+            config::FileConfig::load(&path)?.apply(&mut args, &matches)?;
+        }
+        if args.no_file && args.kafka_brokers.is_none() && args.loki_url.is_none() && args.sink.is_empty() && !args.console {
+            return Err(SyslogError::Config("--no-file requires --kafka-brokers, --loki-url, --sink or --console".to_string()));
+        }
+        Ok(args)
+    }
+}
+
+/// Serializes values such as CIDR blocks as the strings they were given as.
+fn serialize_displayed<S: serde::Serializer, T: std::fmt::Display>(values: &[T], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(values.iter().map(ToString::to_string))
+}
+
+fn serialize_delimiter<S: serde::Serializer>(delimiter: &u8, serializer: S) -> Result<S::Ok, S::Error> {
+    match delimiter {
+        b'\t' => serializer.serialize_str("\\t"),
+        _ => serializer.collect_str(&char::from(*delimiter)),
+    }
+}
+
+/// Serializes `FACILITY=PATH` routes the way they are given on the command line.
+fn serialize_facility_routes<S: serde::Serializer>(routes: &[(u8, PathBuf)], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(routes.iter().map(|(facility, path)| format!("{}={}", facility, path.display())))
+}
+
+fn serialize_facility_rates<S: serde::Serializer>(rates: &[(u8, f64)], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(rates.iter().map(|(facility, rate)| format!("{}={}", facility, rate)))
+}
+
+fn serialize_severity_remaps<S: serde::Serializer>(remaps: &[(u8, u8)], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(remaps.iter().map(|(from, to)| format!("{}={}", from, to)))
+}
+
+fn serialize_mode<S: serde::Serializer>(mode: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&format_args!("{:o}", mode))
+}
+
+fn parse_mode(mode: &str) -> Result<u32, String> {
+    u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o777)
+        .ok_or_else(|| format!("expected octal permissions such as 660, got `{}`", mode))
+}
+
+fn parse_facility_route(route: &str) -> Result<(u8, PathBuf), String> {
+    let (facility, path) = route
+        .split_once('=')
+        .ok_or_else(|| format!("expected FACILITY=PATH, got `{}`", route))?;
+    let facility: u8 = facility
+        .parse()
+        .ok()
+        .filter(|facility| *facility <= 23)
+        .ok_or_else(|| format!("facility must be between 0 and 23, got `{}`", facility))?;
+    if path.is_empty() {
+        return Err(format!("missing path for facility {}", facility));
+    }
+    Ok((facility, PathBuf::from(path)))
+}
+
+/// Parses a `FROM=TO` severity remap, e.g. `6=4`.
+fn parse_severity_remap(remap: &str) -> Result<(u8, u8), String> {
+    let (from, to) = remap
+        .split_once('=')
+        .ok_or_else(|| format!("expected FROM=TO, got `{}`", remap))?;
+    let severity = |severity: &str| {
+        severity
+            .parse()
+            .ok()
+            .filter(|severity| *severity <= 7)
+            .ok_or_else(|| format!("severity must be between 0 and 7, got `{}`", severity))
+    };
+    Ok((severity(from)?, severity(to)?))
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct SysLogEntry {
+    event_time: String,
+    device_ip: String,
+    syslog: String,
+    severity: u8,
+    facility: u8,
+    /// RFC5424 VERSION; empty for RFC3164 and unrecognised frames
+    version: Option<u8>,
+    log_timestamp: Option<String>,
+    host: Option<String>,
+    /// RFC5424 APP-NAME, or the program part of an RFC3164 `program[pid]:` tag
+    app_name: Option<String>,
+    /// RFC5424 PROCID, or the pid part of an RFC3164 tag
+    procid: Option<String>,
+    msgid: Option<String>,
+    structured_data: Option<String>,
+    /// Set on dedup summary entries: how many times the message repeated
+    /// within the window after it was first written.
+    repeat_count: Option<u64>,
+    /// Local port the message was received on.
+    listen_port: Option<u16>,
+    /// Set with --named-levels. Looked up again rather than read back from
+    /// the dead-letter file.
+    #[serde(skip_deserializing)]
+    severity_name: Option<&'static str>,
+    #[serde(skip_deserializing)]
+    facility_name: Option<&'static str>,
+    /// Reverse DNS name of `device_ip`, with --resolve-hostnames.
+    source_hostname: Option<String>,
+    /// Seconds the message's own timestamp is ahead of when it was received
+    /// (negative if behind); empty when it carried none.
+    clock_skew_secs: Option<i64>,
+    /// Whether `clock_skew_secs` exceeds --max-skew-secs.
+    suspect_time: bool,
+    /// The message exactly as received, base64-encoded, with --keep-raw.
+    raw: Option<String>,
+    /// Header fields and extensions of a CEF or LEEF message as a JSON
+    /// object, with --parse-cef.
+    parsed_fields: Option<String>,
+    /// 64-bit xxHash3 of `syslog` in hex, with --hash-messages.
+    msg_hash: Option<String>,
+    /// The time `event_time` wasn't taken from: the message's own timestamp
+    /// or, with --event-time-source parsed, when it was received.
+    secondary_time: Option<String>,
+    /// Whether --event-time-source parsed fell back to the receive time, as
+    /// the message had no timestamp that could be read.
+    time_fallback: bool,
+    /// The severity the message was sent with, when --severity-remap changed
+    /// it.
+    original_severity: Option<u8>,
+}
+
+/// A message as handed from a receiver to the processor.
+#[derive(Debug)]
+struct RawMessage {
+    source_ip: String,
+    /// `None` for messages that did not arrive over the network.
+    listen_port: Option<u16>,
+    data: String,
+    /// The bytes as received, kept only when they weren't valid UTF-8 and
+    /// so differ from `data`.
+    invalid_utf8: Option<Vec<u8>>,
+}
+
+impl RawMessage {
+    fn decode(source_ip: String, listen_port: Option<u16>, bytes: &[u8]) -> Self {
+        let data = decode_message(bytes);
+        RawMessage {
+            source_ip,
+            listen_port,
+            invalid_utf8: (data.as_bytes() != bytes).then(|| bytes.to_vec()),
+            data,
+        }
+    }
+}
+
+/// Settings that control how `LogHandler` filters and annotates messages.
+#[derive(Debug, Clone)]
+struct HandlerConfig {
+    /// Entries with a numerically larger (less severe) severity are dropped.
+    /// Only the starting value; a reload changes `LogHandler::min_severity`.
+    min_severity: u8,
+    /// Distinct source IPs given their own `syslog_received_by_source` label
+    /// before the rest are counted as `other`.
+    max_source_labels: usize,
+    /// Identical messages from a source within this window are collapsed.
+    dedup_window: Option<Duration>,
+    /// Share of entries kept, unless their facility has its own rate.
+    sample_rate: f64,
+    sample_facility: HashMap<u8, f64>,
+    /// Severities replaced as soon as a message is parsed.
+    severity_remap: HashMap<u8, u8>,
+    on_malformed: OnMalformed,
+    /// PRI of messages that arrive without one.
+    default_priority: u8,
+    /// Fill in `severity_name` and `facility_name`.
+    named_levels: bool,
+    /// Store IPv4-mapped IPv6 sources in dotted-quad form.
+    normalize_v4_mapped: bool,
+    /// Fill in `source_hostname`.
+    resolve_hostnames: bool,
+    /// Entries skewed by more than this are marked `suspect_time`.
+    max_skew_secs: Option<u64>,
+    timestamp_format: TimestampFormat,
+    event_time_source: EventTimeSource,
+    trim: Trim,
+    newline_handling: NewlineHandling,
+    /// Matches in the message are masked before it is stored.
+    redact_patterns: Vec<regex::Regex>,
+    /// Fill in `raw`.
+    keep_raw: bool,
+    /// Fill in `parsed_fields`.
+    parse_cef: bool,
+    /// Fill in `msg_hash`.
+    hash_messages: bool,
+    /// Retries of a failed batch write, and the wait before the first one.
+    write_retries: u32,
+    write_retry_backoff: Duration,
+    /// Where entries go once their write has failed for good.
+    dead_letter: Option<PathBuf>,
+}
+
+impl Default for HandlerConfig {
+    fn default() -> Self {
+        HandlerConfig {
+            min_severity: 7,
+            max_source_labels: 100,
+            dedup_window: None,
+            sample_rate: 1.0,
+            sample_facility: HashMap::new(),
+            severity_remap: HashMap::new(),
+            on_malformed: OnMalformed::Drop,
+            default_priority: 13,
+            named_levels: false,
+            normalize_v4_mapped: true,
+            resolve_hostnames: false,
+            max_skew_secs: None,
+            timestamp_format: TimestampFormat::default(),
+            event_time_source: EventTimeSource::default(),
+            trim: Trim::default(),
+            newline_handling: NewlineHandling::default(),
+            redact_patterns: Vec::new(),
+            keep_raw: false,
+            parse_cef: false,
+            hash_messages: false,
+            write_retries: 3,
+            write_retry_backoff: Duration::from_millis(100),
+            dead_letter: None,
+        }
+    }
+}
+
+struct LogHandler {
+    /// `None` in dry-run mode or with `--no-file`.
+    writer: Option<Mutex<OutputRouter>>,
+    config: HandlerConfig,
+    /// The live severity filter, which a SIGHUP reload may change.
+    min_severity: AtomicU8,
+    /// Feeds the upstream relay, when one is configured.
+    forward: Option<mpsc::Sender<String>>,
+    /// Feeds the Kafka producer, when one is configured.
+    kafka: Option<mpsc::Sender<SysLogEntry>>,
+    /// Feed the --sink writer tasks.
+    sinks: Vec<SinkSender>,
+    /// Recent entries for `/tail`, with --tail-buffer.
+    tail: Option<Arc<tail::TailBuffer>>,
+    /// Per-source counts for --summary-output.
+    summary: Option<Arc<Summarizer>>,
+    /// Source IPs that already have a `syslog_received_by_source` label.
+    source_labels: std::sync::Mutex<HashSet<String>>,
+    dedup: Option<std::sync::Mutex<Deduplicator>>,
+    sampler: Option<Sampler>,
+    redactor: Option<Redactor>,
+    hostnames: Option<Arc<dns::HostnameCache>>,
+}
+
+impl LogHandler {
+    fn new(
+        writer: Option<OutputRouter>,
+        config: HandlerConfig,
+        forward: Option<mpsc::Sender<String>>,
+        kafka: Option<mpsc::Sender<SysLogEntry>>,
+        sinks: Vec<SinkSender>,
+        tail: Option<Arc<tail::TailBuffer>>,
+        summary: Option<Arc<Summarizer>>,
+    ) -> Self {
+        // Initialize metrics descriptions
+        describe_counter!("syslog_received_total", "Total number of logs received");
+        describe_counter!("syslog_received_by_source", "Total number of logs received per source IP");
+        describe_counter!("syslog_time_fallback_total", "Total number of messages without a readable timestamp under --event-time-source parsed");
+        describe_counter!("syslog_received_by_severity", "Total number of logs received per severity, excluding invalid PRIs");
+        describe_counter!("syslog_received_bytes_total", "Total bytes of log messages received");
+        describe_counter!("syslog_written_total", "Total number of logs written");
+        describe_counter!("syslog_filtered_total", "Total number of logs dropped by the severity filter");
+        describe_counter!("syslog_forward_errors_total", "Total number of logs that could not be relayed upstream");
+        describe_counter!("syslog_forward_dropped_total", "Total number of logs dropped because the relay could not keep up");
+        describe_counter!("syslog_kafka_errors_total", "Total number of entries that could not be produced to Kafka");
+        describe_counter!("syslog_loki_errors_total", "Total number of failed pushes to Loki");
+        describe_counter!("syslog_errors_total", "Total number of handled receive, parse, queue and write errors, by kind: parse, io, encoding or send");
+        describe_counter!("syslog_write_failures_total", "Total number of logs that could not be written even after retrying");
+        describe_counter!("syslog_sink_errors_total", "Total number of logs that could not be written to a --sink output");
+        describe_counter!("syslog_sink_dropped_total", "Total number of logs dropped because a --sink output fell behind");
+        describe_counter!("syslog_invalid_utf8_total", "Total number of logs containing invalid UTF-8");
+        describe_counter!("syslog_truncated_total", "Total number of datagrams that filled the receive buffer");
+        describe_counter!("syslog_tls_handshake_errors_total", "Total number of failed TLS handshakes");
+        describe_counter!("syslog_task_panics_total", "Total number of panics in receive, processing and sink tasks, by task");
+        describe_counter!("syslog_tcp_accept_errors_total", "Total number of failed TCP accepts");
+        describe_counter!("syslog_connections_rejected_total", "Total number of TCP connections closed by --tcp-max-connections");
+        describe_counter!("syslog_tcp_idle_timeouts_total", "Total number of TCP connections closed by --tcp-idle-timeout-secs");
+        describe_counter!("syslog_framing_errors_total", "Total number of invalid TCP frames skipped");
+        describe_counter!("syslog_proxy_parse_errors_total", "Total number of TCP connections closed for a missing or invalid PROXY header");
+        describe_counter!("syslog_denied_total", "Total number of datagrams, connections and HTTP requests rejected by the source CIDR rules");
+        describe_counter!("syslog_http_ingest_rejected_total", "Total number of HTTP ingest requests refused as too large or malformed");
+        describe_counter!("syslog_send_timeout_total", "Total number of logs dropped after waiting --send-timeout-ms for queue space");
+        describe_counter!("syslog_dropped_total", "Total number of UDP datagrams dropped because the queue was full");
+        describe_counter!("syslog_rfc5424_total", "Total number of logs parsed as RFC5424");
+        describe_counter!("syslog_rfc3164_total", "Total number of logs parsed as RFC3164");
+        describe_counter!("syslog_no_priority_total", "Total number of logs without a priority, given --default-priority");
+        describe_counter!("syslog_malformed_total", "Total number of logs with an invalid priority");
+        describe_counter!("syslog_files_pruned_total", "Total number of rotated files deleted to stay within --max-disk-mb");
+        describe_counter!("syslog_dns_lookups_total", "Total number of reverse DNS lookups of source IPs");
+        describe_gauge!("syslog_dns_cache_hit_ratio", "Share of source hostname requests answered from the cache");
+        describe_counter!("syslog_sampled_out_total", "Total number of logs dropped by --sample-rate or --sample-facility");
+        describe_counter!("syslog_redactions_total", "Total number of --redact-pattern matches masked in messages");
+        describe_counter!("syslog_deduplicated_total", "Total number of repeated logs suppressed by deduplication");
+        describe_gauge!("syslog_queue_size", "Number of messages waiting in the processing queue");
+        describe_gauge!("syslog_queue_saturation", "Share of the processing queue in use, from 0.0 to 1.0");
+        describe_gauge!("syslog_active_tcp_connections", "Number of TCP connections currently open");
+        describe_gauge!("syslog_open_files", "Number of distinct output files currently held open");
+        describe_histogram!("syslog_parse_duration_seconds", "Time spent parsing a message");
+        describe_histogram!("syslog_write_duration_seconds", "Time spent writing and flushing a batch");
+        describe_histogram!("syslog_fsync_duration_seconds", "Time spent syncing an output file to disk");
+        describe_histogram!("syslog_message_size_bytes", "Size of received log messages");
+        describe_histogram!("syslog_clock_skew_seconds", "Distance between a message's own timestamp and its arrival");
+        
+        LogHandler {
+            writer: writer.map(Mutex::new),
+            sampler: (config.sample_rate < 1.0 || !config.sample_facility.is_empty())
+                .then(|| Sampler::new(config.sample_rate, config.sample_facility.clone())),
+            redactor: (!config.redact_patterns.is_empty()).then(|| Redactor::new(config.redact_patterns.clone())),
+            dedup: config.dedup_window.map(|window| std::sync::Mutex::new(Deduplicator::new(window, config.timestamp_format))),
+            hostnames: config.resolve_hostnames.then(|| Arc::new(dns::HostnameCache::new())),
+            min_severity: AtomicU8::new(config.min_severity),
+            config,
+            forward,
+            kafka,
+            sinks,
+            tail,
+            summary,
+            source_labels: std::sync::Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Parses a raw message into an entry, or `None` if it is filtered out.
+    fn handle_log(&self, source_ip: String, listen_port: Option<u16>, log_data: String) -> Result<Option<SysLogEntry>, SyslogError> {
+        let source_ip = if self.config.normalize_v4_mapped { unmap_ipv4(source_ip) } else { source_ip };
+        increment_counter!("syslog_received_total");
+        increment_counter!("syslog_received_by_source", "ip" => self.source_label(&source_ip));
+        
+        let parse_started = Instant::now();
+        let mut entry = parser::parse(&log_data, &source_ip, Local::now(), self.config.timestamp_format);
+        histogram!("syslog_parse_duration_seconds", parse_started.elapsed().as_secs_f64());
+        entry.syslog = self.config.trim.apply(entry.syslog);
+        if entry.facility == MALFORMED_PRIORITY && !log_data.starts_with('<') {
+            increment_counter!("syslog_no_priority_total");
+            debug!("Message from {} has no priority, using {}", source_ip, self.config.default_priority);
+            entry.facility = self.config.default_priority >> 3;
+            entry.severity = self.config.default_priority & 0x7;
+        }
+        if entry.facility != MALFORMED_PRIORITY {
+            if let Some(&severity) = self.config.severity_remap.get(&entry.severity) {
+                entry.original_severity = Some(std::mem::replace(&mut entry.severity, severity));
+            }
+        }
+        if self.config.event_time_source == EventTimeSource::Parsed {
+            match entry.secondary_time.take() {
+                Some(parsed) => entry.secondary_time = Some(std::mem::replace(&mut entry.event_time, parsed)),
+                None => {
+                    increment_counter!("syslog_time_fallback_total");
+                    entry.time_fallback = true;
+                }
+            }
+        }
+        if let Some(summary) = &self.summary {
+            summary.record(&entry.device_ip, entry.severity);
+        }
+        if entry.facility != MALFORMED_PRIORITY {
+            increment_counter!("syslog_received_by_severity", "severity" => SEVERITY_LABELS[usize::from(entry.severity)]);
+        }
+        if entry.facility == MALFORMED_PRIORITY {
+            increment_counter!("syslog_malformed_total");
+            if self.config.on_malformed == OnMalformed::Drop {
+                // Only the rejected PRI is parsed again, for the error message.
+                let reason = parser::parse_priority(&log_data).err();
+                return Err(reason.unwrap_or_else(|| SyslogError::MalformedPriority("Malformed priority".to_string())));
+            }
+        } else if entry.severity > self.min_severity.load(Ordering::Relaxed) {
+            increment_counter!("syslog_filtered_total");
+            return Ok(None);
+        }
+        if self.sampler.as_ref().is_some_and(|sampler| !sampler.keep(entry.facility)) {
+            increment_counter!("syslog_sampled_out_total");
+            return Ok(None);
+        }
+        entry.listen_port = listen_port;
+        entry.syslog = self.config.newline_handling.apply(entry.syslog);
+        if let Some(redactor) = &self.redactor {
+            let (message, redactions) = redactor.redact(entry.syslog);
+            entry.syslog = message;
+            if redactions > 0 {
+                counter!("syslog_redactions_total", redactions);
+            }
+        }
+        if self.config.parse_cef {
+            entry.parsed_fields = cef::parse(&entry.syslog).map(|fields| fields.to_string());
+        }
+        if self.config.hash_messages {
+            entry.msg_hash = Some(message_hash(&entry.syslog));
+        }
+        if let Some(skew) = entry.clock_skew_secs {
+            histogram!("syslog_clock_skew_seconds", skew.unsigned_abs() as f64);
+            entry.suspect_time = self.config.max_skew_secs.is_some_and(|max| skew.unsigned_abs() > max);
+        }
+        if self.config.named_levels {
+            entry.severity_name = severity_name(entry.severity);
+            entry.facility_name = facility_name(entry.facility);
+        }
+        if let Some(hostnames) = &self.hostnames {
+            entry.source_hostname = hostnames.hostname(&entry.device_ip);
+        }
+
+        if let Some(dedup) = &self.dedup {
+            if dedup.lock().unwrap().is_duplicate(&entry) {
+                increment_counter!("syslog_deduplicated_total");
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(entry))
+    }
+
+    /// The label to count `source_ip` under. Only the first
+    /// `max_source_labels` distinct sources get their own label, which keeps
+    /// the metric's cardinality bounded.
+    fn source_label(&self, source_ip: &str) -> String {
+        let mut labels = self.source_labels.lock().unwrap();
+        if labels.contains(source_ip) {
+            return source_ip.to_string();
+        }
+        if labels.len() < self.config.max_source_labels {
+            labels.insert(source_ip.to_string());
+            return source_ip.to_string();
+        }
+        "other".to_string()
+    }
+
+    /// Writes and flushes every entry in `batch`, leaving it empty, then
+    /// hands the entries to the tail, Kafka and the sinks. If an output
+    /// still fails after the retries, `batch` is left holding just the
+    /// entries routed to the outputs that failed, and those aren't handed on.
+    async fn write_to_csv(&self, batch: &mut Vec<SysLogEntry>) -> Result<(), SyslogError> {
+        let Some(writer) = &self.writer else {
+            if self.kafka.is_none() && self.sinks.is_empty() {
+                for entry in batch.iter() {
+                    debug!("Dry run, would write: {:?}", entry);
+                }
+            }
+            self.hand_on(batch);
+            batch.clear();
+            return Ok(());
+        };
+        let write_started = Instant::now();
+        let mut writer = writer.lock().await;
+        // Outputs that took the batch are skipped on retries.
+        let mut written = HashSet::new();
+        let mut backoff = self.config.write_retry_backoff;
+        for attempt in 1.. {
+            match writer.write_batch(batch, &mut written).await {
+                Ok(()) => break,
+                Err(e) if attempt > self.config.write_retries => {
+                    let (done, failed): (Vec<_>, Vec<_>) =
+                        batch.drain(..).partition(|entry| written.contains(writer.route(entry)));
+                    counter!("syslog_written_total", done.len() as u64);
+                    self.hand_on(&done);
+                    *batch = failed;
+                    return Err(e);
+                }
+                Err(e) => warn!("Error writing logs, retrying in {:?} (attempt {}): {}", backoff, attempt, e),
+            }
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+        drop(writer);
+        histogram!("syslog_write_duration_seconds", write_started.elapsed().as_secs_f64());
+        counter!("syslog_written_total", batch.len() as u64);
+        self.hand_on(batch);
+        batch.clear();
+        Ok(())
+    }
+
+    /// Feeds written entries to the tail, Kafka and the sinks.
+    fn hand_on(&self, entries: &[SysLogEntry]) {
+        if entries.is_empty() {
+            return;
+        }
+        if let Some(tail) = &self.tail {
+            tail.extend(entries);
+        }
+        // Like the relay, Kafka must never hold up local writes.
+        if let Some(kafka) = &self.kafka {
+            for entry in entries {
+                if kafka.try_send(entry.clone()).is_err() {
+                    increment_counter!("syslog_kafka_errors_total");
+                }
+            }
+        }
+        if !self.sinks.is_empty() {
+            let shared: Arc<[SysLogEntry]> = entries.into();
+            for sink in &self.sinks {
+                sink.send(Arc::clone(&shared));
+            }
+        }
+    }
+
+    fn process(&self, message: RawMessage, batch: &mut Vec<SysLogEntry>) {
+        let RawMessage { source_ip, listen_port, data: log_data, invalid_utf8 } = message;
+        let raw = self
+            .config
+            .keep_raw
+            .then(|| BASE64_STANDARD.encode(invalid_utf8.as_deref().unwrap_or(log_data.as_bytes())));
+        // Never wait on the relay; a slow or dead upstream must not hold up
+        // local writes.
+        if let Some(forward) = &self.forward {
+            match forward.try_send(log_data.clone()) {
+                Ok(()) => {}
+                Err(mpsc::error::TrySendError::Full(_)) => increment_counter!("syslog_forward_dropped_total"),
+                Err(mpsc::error::TrySendError::Closed(_)) => increment_counter!("syslog_forward_errors_total"),
+            }
+        }
+
+        match self.handle_log(source_ip, listen_port, log_data) {
+            Ok(Some(entry)) => batch.push(SysLogEntry { raw, ..entry }),
+            Ok(None) => {}
+            Err(e) => {
+                e.count();
+                error!("Error processing log: {}", e);
+            }
+        }
+    }
+
+    /// Applies the settings of a reloaded config that can change while
+    /// running. Routes are checked first, so a bad one leaves both unchanged.
+    async fn reload(&self, min_severity: u8, routes: HashMap<u8, PathBuf>) -> Result<(), SyslogError> {
+        if let Some(writer) = &self.writer {
+            writer.lock().await.set_routes(routes).await?;
+        }
+        self.min_severity.store(min_severity, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Finishes the output files once nothing more will be written.
+    async fn close_output(&self) {
+        if let Some(writer) = &self.writer {
+            if let Err(e) = writer.lock().await.close().await {
+                error!("Failed to close output: {}", e);
+            }
+        }
+    }
+
+    /// Appends summaries for dedup windows that have closed, or for every
+    /// open window when `all` is set.
+    fn take_duplicate_summaries(&self, batch: &mut Vec<SysLogEntry>, all: bool) {
+        if let Some(dedup) = &self.dedup {
+            batch.extend(dedup.lock().unwrap().take_closed(all));
+        }
+    }
+
+    async fn write_batch(&self, batch: &mut Vec<SysLogEntry>) {
+        if batch.is_empty() {
+            return;
+        }
+        if let Err(e) = self.write_to_csv(batch).await {
+            counter!("syslog_write_failures_total", batch.len() as u64);
+            e.count();
+            match &self.config.dead_letter {
+                Some(path) => match dead_letter::spill(path, batch).await {
+                    Ok(()) => error!("Error writing logs, spilled {} entries to {}: {}", batch.len(), path.display(), e),
+                    Err(spill_error) => error!(
+                        "Error writing logs, lost {} entries that could not be spilled to {} either ({}): {}",
+                        batch.len(),
+                        path.display(),
+                        spill_error,
+                        e
+                    ),
+                },
+                None => error!("Error writing logs, lost {} entries: {}", batch.len(), e),
+            }
+            batch.clear();
+        }
+    }
+
+    /// Writes out the entries left in the dead-letter file by an earlier run.
+    /// Any that fail again are spilled into a fresh one.
+    async fn replay_dead_letter(&self, batch_size: usize) -> Result<(), SyslogError> {
+        let Some(path) = &self.config.dead_letter else { return Ok(()) };
+        let mut entries = dead_letter::take(path)
+            .await
+            .map_err(|e| SyslogError::Output(format!("Failed to read dead-letter file {}: {}", path.display(), e)))?;
+        info!("Replaying {} entries from {}", entries.len(), path.display());
+        if self.config.named_levels {
+            for entry in &mut entries {
+                entry.severity_name = severity_name(entry.severity);
+                entry.facility_name = facility_name(entry.facility);
+            }
+        }
+        for chunk in entries.chunks(batch_size) {
+            self.write_batch(&mut chunk.to_vec()).await;
+        }
+        dead_letter::finish(path)
+            .await
+            .map_err(|e| SyslogError::Output(format!("Failed to remove replayed dead-letter entries for {}: {}", path.display(), e)))?;
+        Ok(())
+    }
+}
+
+/// Feeds `messages` through the processing pipeline into a CSV file at
+/// `output` with default settings, returning once all of them are written.
+/// Only meant for the throughput benchmark.
+#[doc(hidden)]
+pub async fn process_messages(
+    output: &Path,
+    messages: Vec<Vec<u8>>,
+    workers: usize,
+    batch_size: usize,
+) -> Result<(), SyslogError> {
+    let writer = OutputRouter::open(output, HashMap::new(), OutputOptions::default(), RotationPolicy::default()).await?;
+    let handler = Arc::new(LogHandler::new(Some(writer), HandlerConfig::default(), None, None, Vec::new(), None, None));
+    let (tx, rx) = mpsc::channel(1000);
+    let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        for bytes in messages {
+            if tx.send(RawMessage::decode("127.0.0.1".to_string(), Some(514), &bytes)).await.is_err() {
+                break;
+            }
+        }
+    });
+    let queue = Arc::new(QueueMonitor::new(None));
+    let settings = ProcessorSettings {
+        workers,
+        batch_size,
+        flush_interval: Duration::from_millis(1000),
+        drain_timeout: None,
+    };
+    run_processor(handler, rx, queue, settings, Arc::new(http::Health::new()), shutdown_rx).await;
+    Ok(())
+}
+
+/// How the processor takes messages off the queue and batches them.
+#[derive(Clone, Copy, Debug)]
+struct ProcessorSettings {
+    workers: usize,
+    batch_size: usize,
+    flush_interval: Duration,
+    /// Longest the queue is drained for once shutdown starts; whatever is
+    /// still queued then is abandoned. Without it the queue is drained fully.
+    drain_timeout: Option<Duration>,
+}
+
+/// Runs `settings.workers` processing tasks that share `rx`, and finishes the
+/// output once all of them have drained it.
+async fn run_processor(
+    handler: Arc<LogHandler>,
+    rx: mpsc::Receiver<RawMessage>,
+    queue: Arc<QueueMonitor>,
+    settings: ProcessorSettings,
+    health: Arc<http::Health>,
+    shutdown: watch::Receiver<bool>,
+) {
+    let rx = Arc::new(Mutex::new(rx));
+    let mut workers = tokio::task::JoinSet::new();
+    for _ in 0..settings.workers {
+        workers.spawn(run_worker(
+            Arc::clone(&handler),
+            Arc::clone(&rx),
+            Arc::clone(&queue),
+            settings,
+            shutdown.clone(),
+        ));
+    }
+
+    // Joined as they finish, so a worker that panics is reported straight
+    // away rather than at shutdown.
+    let mut drained = 0;
+    while let Some(worker) = workers.join_next().await {
+        match worker {
+            Ok(count) => drained += count,
+            Err(e) => {
+                report_task_error("processor worker", e);
+                health.mark_task_down();
+            }
+        }
+    }
+    let mut batch = Vec::new();
+    handler.take_duplicate_summaries(&mut batch, true);
+    handler.write_batch(&mut batch).await;
+    if let Some(summary) = &handler.summary {
+        if let Err(e) = summary.flush().await {
+            error!("Failed to write the last summaries: {}", e);
+        }
+    }
+    let abandoned = rx.lock().await.len();
+    if abandoned > 0 {
+        warn!(
+            "Drained {} queued messages during shutdown and abandoned {} when --shutdown-drain-timeout-secs expired",
+            drained, abandoned
+        );
+    } else {
+        info!("Drained {} queued messages during shutdown", drained);
+    }
+    handler.close_output().await;
+}
+
+/// Parses incoming messages and writes them out in batches, either when
+/// `batch_size` entries have accumulated or `flush_interval` has elapsed.
+/// Entries handled by one worker are written in the order they arrived.
+///
+/// Once `shutdown` fires the receivers stop producing, so whatever is still
+/// queued in `rx` is drained, for up to `settings.drain_timeout`, and the
+/// final partial batch written. Returns the number of messages drained.
+async fn run_worker(
+    handler: Arc<LogHandler>,
+    rx: Arc<Mutex<mpsc::Receiver<RawMessage>>>,
+    queue: Arc<QueueMonitor>,
+    settings: ProcessorSettings,
+    mut shutdown: watch::Receiver<bool>,
+) -> usize {
+    let batch_size = settings.batch_size;
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut flush_timer = tokio::time::interval(settings.flush_interval);
+    loop {
+        tokio::select! {
+            // The lock is only held while waiting, not while processing
+            received = async {
+                let mut rx = rx.lock().await;
+                let received = rx.recv().await;
+                queue.record_received(&rx);
+                received
+            } => {
+                let Some(message) = received else { break };
+                handler.process(message, &mut batch);
+                if batch.len() < batch_size {
+                    continue;
+                }
+            }
+            _ = flush_timer.tick() => {
+                handler.take_duplicate_summaries(&mut batch, false);
+                if batch.is_empty() {
+                    continue;
+                }
+            }
+            _ = shutdown.wait_for(|&stop| stop) => break,
+        }
+
+        handler.write_batch(&mut batch).await;
+    }
+
+    let deadline = settings.drain_timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+    let mut drained = 0;
+    loop {
+        let next = async { rx.lock().await.recv().await };
+        // `timeout_at` still takes a message that is ready, so an expired
+        // deadline is checked first.
+        let received = match deadline {
+            Some(deadline) if deadline <= tokio::time::Instant::now() => break,
+            Some(deadline) => tokio::time::timeout_at(deadline, next).await.unwrap_or(None),
+            None => next.await,
+        };
+        let Some(message) = received else { break };
+        drained += 1;
+        handler.process(message, &mut batch);
+        if batch.len() >= batch_size {
+            handler.write_batch(&mut batch).await;
+        }
+    }
+    handler.write_batch(&mut batch).await;
+    drained
+}
+
+/// Hashes a message body for `msg_hash`. The seedless xxHash3 is stable
+/// across runs and platforms, unlike the standard library's hasher.
+fn message_hash(message: &str) -> String {
+    format!("{:016x}", twox_hash::XxHash3_64::oneshot(message.as_bytes()))
+}
+
+/// Turns an IPv4-mapped IPv6 address such as `::ffff:192.0.2.1`, as IPv4
+/// senders appear on a dual-stack socket, back into `192.0.2.1`. Anything
+/// else is returned as is.
+fn unmap_ipv4(ip: String) -> String {
+    match ip.parse::<IpAddr>() {
+        Ok(IpAddr::V6(v6)) => v6.to_ipv4_mapped().map_or(ip, |v4| v4.to_string()),
+        _ => ip,
+    }
+}
+
+/// The keyword for `severity`, or `None` for the malformed-PRI sentinel.
+fn severity_name(severity: u8) -> Option<&'static str> {
+    SEVERITY_NAMES.get(usize::from(severity)).copied()
+}
+
+/// The keyword for `facility`, or `None` for the malformed-PRI sentinel.
+fn facility_name(facility: u8) -> Option<&'static str> {
+    FACILITY_NAMES.get(usize::from(facility)).copied()
+}
+
+/// Decodes a received message, replacing invalid UTF-8 sequences rather than
+/// dropping the message so Latin-1 or binary-ish payloads still get logged.
+/// The size recorded is that of the raw bytes, truncated or not.
+fn decode_message(bytes: &[u8]) -> String {
+    counter!("syslog_received_bytes_total", bytes.len() as u64);
+    histogram!("syslog_message_size_bytes", bytes.len() as f64);
+    match String::from_utf8_lossy(bytes) {
+        Cow::Borrowed(data) => data.to_string(),
+        Cow::Owned(data) => {
+            increment_counter!("syslog_invalid_utf8_total");
+            error::count_error(error::ErrorKind::Encoding);
+            data
+        }
+    }
+}
+
+/// Hands a datagram to the processor. Without `send_timeout` it is dropped
+/// straight away when the queue is full and counted in
+/// `syslog_dropped_total`; with one it may wait that long for space before
+/// being dropped and counted in `syslog_send_timeout_total`.
+async fn queue_datagram(tx: &mpsc::Sender<RawMessage>, message: RawMessage, send_timeout: Option<Duration>) {
+    let Some(timeout) = send_timeout else {
+        match tx.try_send(message) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(_)) => increment_counter!("syslog_dropped_total"),
+            Err(mpsc::error::TrySendError::Closed(_)) => report_closed_channel(),
+        }
+        return;
+    };
+    let source = message.source_ip.clone();
+    match tx.send_timeout(message, timeout).await {
+        Ok(()) => {}
+        Err(mpsc::error::SendTimeoutError::Timeout(_)) => {
+            increment_counter!("syslog_send_timeout_total");
+            warn!("Dropped message from {}: the queue stayed full for {:?}", source, timeout);
+        }
+        Err(mpsc::error::SendTimeoutError::Closed(_)) => report_closed_channel(),
+    }
+}
+
+fn report_closed_channel() {
+    let e = SyslogError::ChannelSend;
+    e.count();
+    error!("Failed to send to channel: {}", e);
+}
+
+/// Watches a critical task. A panic is logged and counted, and flags the
+/// server unhealthy, as does a task with a `shutdown` receiver returning
+/// before shutdown; a task without one is expected to finish by itself. The
+/// returned handle finishes once the task has.
+fn watch_task(
+    name: String,
+    task: JoinHandle<()>,
+    health: Arc<http::Health>,
+    shutdown: Option<watch::Receiver<bool>>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        match task.await {
+            Ok(()) if shutdown.is_none_or(|shutdown| *shutdown.borrow()) => return,
+            Ok(()) => error!("{} task exited unexpectedly", name),
+            Err(e) => report_task_error(&name, e),
+        }
+        health.mark_task_down();
+    })
+}
+
+/// Logs why a task didn't finish, counting panics in
+/// `syslog_task_panics_total`.
+fn report_task_error(name: &str, e: tokio::task::JoinError) {
+    if !e.is_panic() {
+        error!("{} task was cancelled", name);
+        return;
+    }
+    increment_counter!("syslog_task_panics_total", "task" => name.to_string());
+    let panic = e.into_panic();
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("no message");
+    error!("{} task panicked: {}", name, message);
+}
+
+/// Binds every configured listener and spawns its receiver, all feeding `tx`.
+async fn start_receivers(
+    args: &Args,
+    sources: &Arc<ArcSwap<acl::SourceFilter>>,
+    queue: &Arc<QueueMonitor>,
+    tx: &mpsc::Sender<RawMessage>,
+    health: &Arc<http::Health>,
+    shutdown_rx: &watch::Receiver<bool>,
+) -> Result<(), SyslogError> {
+    let max_message_bytes = args.max_message_bytes.max(1);
+    let send_timeout = args.send_timeout_ms.map(Duration::from_millis);
+
+    let tls = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => Some(tls::load_acceptor(cert, key)?),
+        _ => None,
+    };
+    if tls.is_some() && !args.protocol.tcp() {
+        warn!("TLS is only used for TCP; pass --protocol tcp or both to enable it");
+    }
+    if args.proxy_protocol && !args.protocol.tcp() {
+        warn!("PROXY headers are only read on TCP; pass --protocol tcp or both to enable it");
+    }
+    let transport = if tls.is_some() { "TLS" } else { "TCP" };
+    let tcp_settings = Arc::new(tcp::TcpSettings {
+        tls,
+        max_frame: max_message_bytes,
+        sources: Arc::clone(sources),
+        proxy_protocol: args.proxy_protocol,
+        send_timeout,
+        idle_timeout: args.tcp_idle_timeout_secs.map(Duration::from_secs),
+        connection_limit: args.tcp_max_connections.map(|max| tcp::ConnectionLimit::new(max as usize)),
+        queue: Arc::clone(queue),
+    });
+    let udp_settings = Arc::new(udp::UdpSettings {
+        max_message_bytes,
+        sources: Arc::clone(sources),
+        send_timeout,
+        queue: Arc::clone(queue),
+    });
+
+    // Every port gets its own receivers, all feeding the one processor
+    for &port in &args.port {
+        let bind_addr = SocketAddr::new(args.bind_address, port);
+
+        if args.protocol.tcp() {
+            let socket = bind_with_retry(bind_addr, socket2::Type::STREAM, args.bind_retry).await?;
+            socket.listen(args.tcp_backlog as i32)?;
+            let listener = tokio::net::TcpListener::from_std(socket.into())?;
+            info!("Listening for {} syslog on {}", transport, bind_addr);
+            let receiver = tokio::spawn(tcp::run_tcp_listener(
+                listener,
+                port,
+                Arc::clone(&tcp_settings),
+                tx.clone(),
+                shutdown_rx.clone(),
+            ));
+            watch_task(format!("TCP {} receiver", port), receiver, Arc::clone(health), Some(shutdown_rx.clone()));
+        }
+
+        if args.protocol.udp() {
+            // Set up UDP socket with a larger kernel buffer to absorb bursts
+            let socket = bind_with_retry(bind_addr, socket2::Type::DGRAM, args.bind_retry).await?;
+            socket.set_recv_buffer_size(args.recv_buffer_bytes)?;
+            // The kernel may clamp (or, on Linux, double) the requested size
+            info!(
+                "Listening for UDP syslog on {} (receive buffer {} bytes)",
+                bind_addr,
+                socket.recv_buffer_size()?
+            );
+            let socket = tokio::net::UdpSocket::from_std(socket.into())?;
+            let receiver = tokio::spawn(udp::run_udp_receiver(
+                socket,
+                port,
+                Arc::clone(&udp_settings),
+                tx.clone(),
+                shutdown_rx.clone(),
+            ));
+            watch_task(format!("UDP {} receiver", port), receiver, Arc::clone(health), Some(shutdown_rx.clone()));
+        }
+    }
+
+    if let Some(port) = args.http_ingest_port {
+        let bind_addr = SocketAddr::new(args.bind_address, port);
+        let socket = bind_with_retry(bind_addr, socket2::Type::STREAM, args.bind_retry).await?;
+        socket.listen(args.tcp_backlog as i32)?;
+        let settings = Arc::new(ingest::IngestSettings {
+            port,
+            trust_xff: args.trust_xff,
+            max_message_bytes,
+            sources: Arc::clone(sources),
+            send_timeout,
+            queue: Arc::clone(queue),
+        });
+        ingest::start_ingest_server(socket.into(), settings, tx.clone(), shutdown_rx.clone())?;
+        info!("Accepting syslog over HTTP at http://{}/ingest", bind_addr);
+    }
+
+    if let Some(path) = &args.unix_socket {
+        #[cfg(unix)]
+        {
+            let socket = unix::bind(path, args.unix_socket_mode)?;
+            info!("Listening for syslog on Unix socket {}", path.display());
+            let receiver = tokio::spawn(unix::run_unix_receiver(
+                socket,
+                path.clone(),
+                max_message_bytes,
+                send_timeout,
+                Arc::clone(queue),
+                tx.clone(),
+                shutdown_rx.clone(),
+            ));
+            watch_task("Unix socket receiver".to_string(), receiver, Arc::clone(health), Some(shutdown_rx.clone()));
+        }
+        #[cfg(not(unix))]
+        return Err(SyslogError::Socket(format!("Cannot listen on {}: Unix sockets are not supported on this platform", path.display())));
+    }
+    Ok(())
+}
+
+/// Binds like `bind_socket`, retrying up to `retries` times, with a pause that
+/// doubles from `BIND_RETRY_BACKOFF`, while a previous process still holds
+/// the port.
+async fn bind_with_retry(addr: SocketAddr, ty: socket2::Type, retries: u32) -> Result<socket2::Socket, SyslogError> {
+    let mut backoff = BIND_RETRY_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        match bind_socket(addr, ty) {
+            Err(e) if attempt < retries => {
+                attempt += 1;
+                warn!("{} (retry {} of {} in {:?})", e, attempt, retries, backoff);
+            }
+            result => return result,
+        }
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+    }
+}
+
+/// Creates a non-blocking socket bound to `addr`. Binding the IPv6
+/// unspecified address (`::`) explicitly turns off IPV6_V6ONLY so IPv4 senders
+/// reach the same socket, whatever the OS default is. SO_REUSEADDR lets a
+/// restarted server bind while the old process's socket is still closing.
+fn bind_socket(addr: SocketAddr, ty: socket2::Type) -> Result<socket2::Socket, SyslogError> {
+    let socket = socket2::Socket::new(socket2::Domain::for_address(addr), ty, None)?;
+    if addr.is_ipv6() && addr.ip().is_unspecified() {
+        socket.set_only_v6(false)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket
+        .bind(&addr.into())
+        .map_err(|e| SyslogError::Socket(format!("Failed to bind {}: {}", addr, e)))?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+/// Resolves once the process receives Ctrl-C or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = sigterm.recv() => {}
+                }
+                return;
+            }
+            Err(e) => error!("Failed to install SIGTERM handler: {}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        error!("Failed to listen for Ctrl-C: {}", e);
+    }
+}
+
+/// Picks the level of the server's own logs from -v/-q, falling back to
+/// `RUST_LOG` and then INFO. Extra verbosity only applies to this crate, so
+/// dependencies such as hyper don't flood the output.
+fn log_filter(args: &Args) -> EnvFilter {
+    if args.verbose == 0 && args.quiet == 0 {
+        if let Ok(filter) = EnvFilter::try_from_default_env() {
+            return filter;
+        }
+    }
+    const LEVELS: [LevelFilter; 5] = [LevelFilter::ERROR, LevelFilter::WARN, LevelFilter::INFO, LevelFilter::DEBUG, LevelFilter::TRACE];
+    let index = (2 + isize::from(args.verbose) - isize::from(args.quiet)).clamp(0, 4) as usize;
+    let level = LEVELS[index];
+    // Dry runs report what they would have written at debug level
+    let own_level = if args.dry_run { level.max(LevelFilter::DEBUG) } else { level };
+    EnvFilter::new(format!("{},syslog_server={}", level.min(LevelFilter::INFO), own_level))
+}
+
+/// Parses `--template`, which the template output format requires.
+fn load_template(args: &Args) -> Result<Option<Arc<output::template::Template>>, SyslogError> {
+    match &args.template {
+        Some(template) => Ok(Some(Arc::new(output::template::Template::parse(template)?))),
+        None if args.output_format == OutputFormat::Template => Err(SyslogError::Config("--output-format template requires --template".to_string())),
+        None if args.sink.iter().any(|sink| sink.format == OutputFormat::Template) => {
+            Err(SyslogError::Config("A template sink requires --template".to_string()))
+        }
+        None => Ok(None),
+    }
+}
+
+/// `mb` megabytes in bytes, or an error naming `setting` if that doesn't fit
+/// in a `u64`.
+fn megabytes(setting: &str, mb: u64) -> Result<u64, SyslogError> {
+    mb.checked_mul(1024 * 1024)
+        .ok_or_else(|| SyslogError::Config(format!("{} is too large: {}", setting, mb)))
+}
+
+/// Runs the server with the settings from the command line and `--config`
+/// until a shutdown signal, or until a replay is done.
+pub async fn run() -> Result<(), Box<dyn Error>> {
+    let args = Args::load()?;
+    if args.command == Some(Command::Check) {
+        return check::run(&args).map_err(Into::into);
+    }
+    if args.print_config {
+        println!("{}", serde_json::to_string_pretty(&args)?);
+        return Ok(());
+    }
+
+    // Initialize logging
+    let subscriber = tracing_subscriber::fmt()
+        .with_target(false)
+        .with_thread_ids(true)
+        .with_level(true)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_env_filter(log_filter(&args))
+        // Keeps stdout for the entries printed by --console.
+        .with_writer(if args.console { BoxMakeWriter::new(std::io::stderr) } else { BoxMakeWriter::new(std::io::stdout) });
+    match args.log_format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+
+    let ports: Vec<String> = args.port.iter().map(u16::to_string).collect();
+    info!("Starting SysLog server on port {}", ports.join(", "));
+
+    // Every long-running task watches this to know when to wind down
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutdown signal received, draining queued messages");
+        let _ = shutdown_tx.send(true);
+    });
+
+    // Initialize metrics server
+    let health = Arc::new(http::Health::new());
+    let tail = (args.tail_buffer > 0 && !args.no_metrics).then(|| Arc::new(tail::TailBuffer::new(args.tail_buffer)));
+    if args.no_metrics {
+        // Without a recorder installed, the metric macros do nothing.
+        if args.tail_buffer > 0 {
+            warn!("--tail-buffer is served on the metrics port, which --no-metrics turns off");
+        }
+        info!("Metrics are disabled");
+    } else if let Err(e) = http::start_metrics_server(args.metrics_port, Arc::clone(&health), tail.clone(), shutdown_rx.clone()) {
+        let e = format!("Cannot start the metrics server on port {}: {}", args.metrics_port, e);
+        if args.metrics_required {
+            return Err(e.into());
+        }
+        warn!("{}; running without metrics", e);
+    }
+
+    let rotation = RotationPolicy {
+        max_bytes: args.rotate_size_mb.map(|mb| megabytes("--rotate-size-mb", mb)).transpose()?,
+        interval: args.rotate_interval,
+        compress: args.compress_rotated,
+    };
+    // Parsed even for a dry run, so a bad template is caught before deploying it.
+    let template = load_template(&args)?;
+    let options = OutputOptions {
+        format: args.output_format,
+        template,
+        csv_delimiter: args.csv_delimiter,
+        csv_quote_style: args.csv_quote_style,
+        fields: args.fields.clone(),
+        sd_columns: args.sd_column.clone(),
+        row_group_size: args.parquet_row_group_size.max(1),
+        fsync: args.fsync,
+        fsync_interval: Duration::from_millis(args.fsync_interval_ms),
+    };
+    let writes = |formats: &[OutputFormat]| {
+        (!args.no_file && formats.contains(&args.output_format)) || args.sink.iter().any(|sink| formats.contains(&sink.format))
+    };
+    if args.fields.is_some() && !writes(&[OutputFormat::Csv, OutputFormat::Jsonl]) {
+        warn!("--fields only applies to CSV and JSON lines output, and nothing is written as either");
+    }
+    if !args.sd_column.is_empty() && !writes(&[OutputFormat::Csv]) {
+        warn!("--sd-column only applies to CSV output, and nothing is written as CSV");
+    }
+    let routes: HashMap<u8, PathBuf> = args.facility_route.iter().cloned().collect();
+    let mut outputs = Vec::new();
+    let writer = if args.dry_run {
+        info!("Dry run: messages are parsed and counted but not written or forwarded");
+        None
+    } else if args.no_file {
+        None
+    } else {
+        outputs.extend(std::iter::once(args.output.clone()).chain(routes.values().cloned()));
+        Some(OutputRouter::open(&args.output, routes, options.clone(), rotation).await?)
+    };
+    let mut sinks = Vec::new();
+    let mut sink_writers = Vec::new();
+    for SinkSpec { format, path } in args.sink.iter().filter(|_| !args.dry_run) {
+        let options = OutputOptions { format: *format, ..options.clone() };
+        let writer = OutputWriter::open(path, options, rotation)
+            .await
+            .map_err(|e| format!("Cannot write to sink {}: {}", path.display(), e))?;
+        let (sender, handle) = output::sink::spawn_sink(writer);
+        sinks.push(sender);
+        sink_writers.push(watch_task(format!("sink {}", path.display()), handle, Arc::clone(&health), None));
+        outputs.push(path.clone());
+    }
+    if args.console && !args.dry_run {
+        let (sender, handle) = output::sink::spawn_console(output::console::use_color());
+        sinks.push(sender);
+        sink_writers.push(watch_task("console".to_string(), handle, Arc::clone(&health), None));
+    }
+    if let Some(url) = args.loki_url.as_deref().filter(|_| !args.dry_run) {
+        let url = loki::parse_loki_url(url)?;
+        let flush_interval = Duration::from_millis(args.flush_interval_ms.max(1));
+        let (sender, handle) = output::sink::spawn_loki(loki::LokiSink::new(url, args.batch_size.max(1), flush_interval));
+        sinks.push(sender);
+        sink_writers.push(watch_task("Loki".to_string(), handle, Arc::clone(&health), None));
+    }
+    if args.max_disk_mb.is_some() && outputs.iter().any(|path| output::is_dated(path)) {
+        warn!("--max-disk-mb leaves outputs with date placeholders alone");
+        outputs.retain(|path| !output::is_dated(path));
+    }
+    if let Some(mb) = args.max_disk_mb.filter(|_| !outputs.is_empty()) {
+        let max_bytes = megabytes("--max-disk-mb", mb)?;
+        tokio::spawn(output::janitor::run_janitor(outputs, max_bytes, shutdown_rx.clone()));
+    }
+
+    let mut forwarder = None;
+    let forward = match &args.forward_to {
+        Some(target) if !args.dry_run => {
+            let addr = tokio::net::lookup_host(target)
+                .await?
+                .next()
+                .ok_or_else(|| format!("Could not resolve forward target {}", target))?;
+            let (forward_tx, forward_rx) = mpsc::channel(args.queue_size);
+            forwarder = Some(tokio::spawn(forward::run_forwarder(
+                addr,
+                args.forward_protocol,
+                forward_rx,
+                args.forward_buffer_size.max(1),
+                Duration::from_millis(args.forward_max_backoff_ms),
+            )));
+            Some(forward_tx)
+        }
+        _ => None,
+    };
+
+    let (kafka, kafka_producer): (_, Option<tokio::task::JoinHandle<()>>) = match (&args.kafka_brokers, &args.kafka_topic) {
+        (Some(brokers), Some(topic)) if !args.dry_run => {
+            #[cfg(feature = "kafka")]
+            {
+                let sink = kafka::KafkaSink::new(brokers, topic, args.kafka_max_in_flight.max(1))?;
+                let (kafka_tx, kafka_rx) = mpsc::channel(args.queue_size);
+                (Some(kafka_tx), Some(tokio::spawn(sink.run(kafka_rx))))
+            }
+            #[cfg(not(feature = "kafka"))]
+            return Err(format!("Cannot produce to {} topic {}: built without the `kafka` feature", brokers, topic).into());
+        }
+        _ => (None, None),
+    };
+
+    let config = HandlerConfig {
+        min_severity: args.min_severity,
+        max_source_labels: args.max_source_labels,
+        dedup_window: args.dedup_window_ms.map(Duration::from_millis),
+        sample_rate: args.sample_rate,
+        sample_facility: args.sample_facility.iter().copied().collect(),
+        severity_remap: args.severity_remap.iter().copied().collect(),
+        on_malformed: args.on_malformed,
+        default_priority: args.default_priority,
+        named_levels: args.named_levels,
+        normalize_v4_mapped: args.normalize_v4_mapped,
+        resolve_hostnames: args.resolve_hostnames,
+        max_skew_secs: args.max_skew_secs,
+        timestamp_format: args.timestamp_format,
+        event_time_source: args.event_time_source,
+        trim: args.trim,
+        newline_handling: args.newline_handling,
+        redact_patterns: args.redact_pattern.clone(),
+        keep_raw: args.keep_raw,
+        parse_cef: args.parse_cef,
+        hash_messages: args.hash_messages,
+        write_retries: args.write_retries,
+        write_retry_backoff: Duration::from_millis(args.write_retry_backoff_ms),
+        dead_letter: args.dead_letter.clone(),
+    };
+    if args.keep_raw && !args.redact_pattern.is_empty() {
+        warn!("--keep-raw stores messages before --redact-pattern is applied, so the raw column is not redacted");
+    }
+    let summary = match &args.summary_output {
+        Some(path) if !args.dry_run => {
+            let summary = Arc::new(Summarizer::open(path, args.timestamp_format).await?);
+            tokio::spawn(summary::run_summarizer(
+                Arc::clone(&summary),
+                Duration::from_secs(args.summary_interval),
+                shutdown_rx.clone(),
+            ));
+            Some(summary)
+        }
+        _ => None,
+    };
+    let log_handler = Arc::new(LogHandler::new(writer, config, forward, kafka, sinks, tail, summary));
+    // A dry run writes nothing, so it must not empty the file either.
+    if args.replay_dead_letter && !args.dry_run {
+        log_handler.replay_dead_letter(args.batch_size.max(1)).await?;
+    }
+    
+    let sources = Arc::new(ArcSwap::from_pointee(acl::SourceFilter::new(args.allow_cidr.clone(), args.deny_cidr.clone())));
+    #[cfg(unix)]
+    if let Some(path) = &args.config {
+        tokio::spawn(reload::run_reloader(
+            path.clone(),
+            Arc::downgrade(&log_handler),
+            Arc::clone(&sources),
+            shutdown_rx.clone(),
+        ));
+    }
+
+    // Channel for message passing between the receivers and processor
+    let (tx, rx) = mpsc::channel::<RawMessage>(args.queue_size);
+    let queue = Arc::new(QueueMonitor::new(args.queue_warn_pct));
+
+    match &args.replay {
+        Some(path) => {
+            let file = tokio::fs::File::open(path)
+                .await
+                .map_err(|e| format!("Failed to open replay file {}: {}", path.display(), e))?;
+            info!("Replaying {} instead of listening", path.display());
+            let tx = tx.clone();
+            let shutdown = shutdown_rx.clone();
+            let rate = args.replay_rate;
+            tokio::spawn(async move {
+                if let Err(e) = replay::run_replay(file, rate, tx, shutdown).await {
+                    error!("Replay failed: {}", e);
+                }
+            });
+        }
+        None => start_receivers(&args, &sources, &queue, &tx, &health, &shutdown_rx).await?,
+    }
+    drop(tx);
+
+    // Log processor task
+    if args.ordered && args.workers > 1 {
+        warn!("--ordered runs a single worker, so --workers {} is ignored", args.workers);
+    }
+    let settings = ProcessorSettings {
+        workers: if args.ordered { 1 } else { args.workers.max(1) },
+        batch_size: args.batch_size.max(1),
+        flush_interval: Duration::from_millis(args.flush_interval_ms.max(1)),
+        drain_timeout: args.shutdown_drain_timeout_secs.map(Duration::from_secs),
+    };
+    run_processor(log_handler, rx, queue, settings, health, shutdown_rx).await;
+
+    // The processor owned the last handle to the relay channel, so the
+    // forwarder finishes once it has sent what is left.
+    if let Some(forwarder) = forwarder {
+        if tokio::time::timeout(FORWARD_DRAIN_TIMEOUT, forwarder).await.is_err() {
+            error!("Timed out relaying remaining messages upstream");
+        }
+    }
+    if let Some(producer) = kafka_producer {
+        let _ = producer.await;
+    }
+    for sink_writer in sink_writers {
+        let _ = sink_writer.await;
+    }
+
+    info!("SysLog server stopped");
+
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    impl LogHandler {
+        /// A handler with `config` that writes nowhere and feeds nothing.
+        fn for_test(config: HandlerConfig) -> Self {
+            LogHandler::new(None, config, None, None, Vec::new(), None, None)
+        }
+
+        fn with_writer(mut self, writer: OutputRouter) -> Self {
+            self.writer = Some(Mutex::new(writer));
+            self
+        }
+    }
+
+    async fn test_handler(name: &str) -> (LogHandler, PathBuf) {
+        let path = std::env::temp_dir().join(format!("syslog-server-{}-{}.csv", name, std::process::id()));
+        let writer = OutputRouter::open(&path, HashMap::new(), OutputOptions::default(), RotationPolicy::default())
+            .await
+            .unwrap();
+        (LogHandler::for_test(HandlerConfig::default()).with_writer(writer), path)
+    }
+
+    #[tokio::test]
+    async fn invalid_utf8_is_replaced_not_dropped() {
+        let (handler, path) = test_handler("invalid-utf8").await;
+
+        let data = decode_message(&[b'<', b'1', b'3', b'>', 0xFF, 0xFE]);
+        let entry = handler.handle_log("127.0.0.1".to_string(), Some(514), data).unwrap();
+
+        let entry = entry.expect("message should not be dropped");
+        assert_eq!(entry.syslog, "<13>\u{FFFD}\u{FFFD}");
+        assert_eq!((entry.facility, entry.severity), (1, 5));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_writes_produce_well_formed_csv() {
+        let (handler, path) = test_handler("concurrent-writes").await;
+        let (tx, rx) = mpsc::channel(1000);
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        let senders: Vec<_> = (0..100)
+            .map(|sender| {
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    for row in 0..100 {
+                        let data = format!("<13>Oct 11 22:14:15 host app[{}]: row {}, with \"quotes\", commas", sender, row);
+                        let message = RawMessage {
+                            source_ip: "127.0.0.1".to_string(),
+                            listen_port: Some(514),
+                            data,
+                            invalid_utf8: None,
+                        };
+                        tx.send(message).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+        let settings = ProcessorSettings {
+            workers: 8,
+            batch_size: 50,
+            flush_interval: Duration::from_millis(10),
+            drain_timeout: None,
+        };
+        let health = Arc::new(http::Health::new());
+        run_processor(Arc::new(handler), rx, Arc::new(QueueMonitor::new(None)), settings, health, shutdown_rx).await;
+        for sender in senders {
+            sender.await.unwrap();
+        }
+
+        let mut reader = csv::Reader::from_path(&path).unwrap();
+        let mut seen = HashSet::new();
+        for record in reader.records() {
+            let record = record.expect("every row should parse");
+            assert_eq!(record.len(), 25);
+            let (procid, syslog) = (&record[9], &record[2]);
+            let row = syslog
+                .strip_prefix("row ")
+                .and_then(|rest| rest.strip_suffix(", with \"quotes\", commas"))
+                .unwrap_or_else(|| panic!("unexpected message {:?}", syslog));
+            assert!(seen.insert(format!("{}/{}", procid, row)), "duplicate row {}/{}", procid, row);
+        }
+        assert_eq!(seen.len(), 10_000);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_panicking_task_is_reported_unhealthy() {
+        let health = Arc::new(http::Health::new());
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        // A task expected to finish by itself may, without being flagged.
+        watch_task("quiet".to_string(), tokio::spawn(async {}), Arc::clone(&health), None).await.unwrap();
+        assert!(health.is_healthy());
+
+        let task = tokio::spawn(async { panic!("receive loop broke") });
+        watch_task("test".to_string(), task, Arc::clone(&health), Some(shutdown_rx)).await.unwrap();
+        assert!(!health.is_healthy());
+    }
+
+    #[tokio::test]
+    async fn drain_timeout_bounds_shutdown() {
+        let (handler, path) = test_handler("drain-timeout").await;
+        let (tx, rx) = mpsc::channel(10);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        for _ in 0..3 {
+            let message = RawMessage {
+                source_ip: "127.0.0.1".to_string(),
+                listen_port: Some(514),
+                data: "<13>queued".to_string(),
+                invalid_utf8: None,
+            };
+            tx.send(message).await.unwrap();
+        }
+        shutdown_tx.send(true).unwrap();
+
+        // `tx` stays open, so without a deadline the drain would never end.
+        let settings = ProcessorSettings {
+            workers: 1,
+            batch_size: 10,
+            flush_interval: Duration::from_secs(60),
+            drain_timeout: Some(Duration::from_millis(50)),
+        };
+        let health = Arc::new(http::Health::new());
+        let processor = run_processor(Arc::new(handler), rx, Arc::new(QueueMonitor::new(None)), settings, health, shutdown_rx);
+        tokio::time::timeout(Duration::from_secs(5), processor)
+            .await
+            .expect("shutdown should stop draining at the deadline");
+
+        let rows = csv::Reader::from_path(&path).unwrap().records().count();
+        assert_eq!(rows, 3);
+        drop(tx);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn malformed_priority_is_kept_with_sentinel() {
+        let mut handler = LogHandler::for_test(HandlerConfig { on_malformed: OnMalformed::Keep, ..HandlerConfig::default() });
+
+        let entry = handler
+            .handle_log("127.0.0.1".to_string(), Some(514), "<999>garbage".to_string())
+            .unwrap()
+            .expect("malformed message should be kept");
+        assert_eq!((entry.facility, entry.severity), (255, 255));
+        assert_eq!(entry.syslog, "<999>garbage");
+
+        handler.config.on_malformed = OnMalformed::Drop;
+        let dropped = handler.handle_log("127.0.0.1".to_string(), Some(514), "<999>garbage".to_string());
+        let e = dropped.unwrap_err();
+        assert!(matches!(e, SyslogError::MalformedPriority(_)));
+        assert_eq!(e.kind().as_str(), "parse");
+    }
+
+    #[tokio::test]
+    async fn messages_without_priority_get_the_default() {
+        let handle = |config: HandlerConfig| {
+            let handler = LogHandler::for_test(config);
+            handler.handle_log("127.0.0.1".to_string(), Some(514), "plain text from a device".to_string()).unwrap()
+        };
+        let entry = handle(HandlerConfig::default()).expect("message without PRI should be kept");
+        assert_eq!((entry.facility, entry.severity), (1, 5));
+        assert_eq!(entry.syslog, "plain text from a device");
+
+        // The default priority goes through the severity filter like any other.
+        let config = HandlerConfig { default_priority: 4 * 8 + 7, min_severity: 4, ..HandlerConfig::default() };
+        assert!(handle(config).is_none());
+    }
+
+    #[tokio::test]
+    async fn rfc3164_tag_is_split_into_app_name_and_procid() {
+        let handler = LogHandler::for_test(HandlerConfig::default());
+        let handle = |message: &str| {
+            handler
+                .handle_log("127.0.0.1".to_string(), Some(514), format!("<38>Oct 11 22:14:15 host {}", message))
+                .unwrap()
+                .unwrap()
+        };
+
+        let entry = handle("sshd[4321]: Accepted publickey for root");
+        assert_eq!(entry.app_name.as_deref(), Some("sshd"));
+        assert_eq!(entry.procid.as_deref(), Some("4321"));
+        assert_eq!(entry.syslog, "Accepted publickey for root");
+
+        let entry = handle("cron: run");
+        assert_eq!(entry.app_name.as_deref(), Some("cron"));
+        assert_eq!(entry.procid, None);
+        assert_eq!(entry.syslog, "run");
+
+        let entry = handle("link down on eth0: carrier lost");
+        assert_eq!((entry.app_name, entry.procid), (None, None));
+        assert_eq!(entry.syslog, "link down on eth0: carrier lost");
+    }
+
+    #[test]
+    fn level_names_follow_the_standard_tables() {
+        assert_eq!(facility_name(0), Some("kern"));
+        assert_eq!(facility_name(4), Some("auth"));
+        assert_eq!(facility_name(16), Some("local0"));
+        assert_eq!(facility_name(23), Some("local7"));
+        assert_eq!(facility_name(MALFORMED_PRIORITY), None);
+        assert_eq!(severity_name(0), Some("emergency"));
+        assert_eq!(severity_name(3), Some("error"));
+        assert_eq!(severity_name(4), Some("warning"));
+        assert_eq!(severity_name(7), Some("debug"));
+        assert_eq!(severity_name(MALFORMED_PRIORITY), None);
+    }
+
+    #[tokio::test]
+    async fn named_levels_are_only_filled_when_enabled() {
+        let mut handler = LogHandler::for_test(HandlerConfig::default());
+        let message = || "<34>Oct 11 22:14:15 host su: failed".to_string();
+
+        let entry = handler.handle_log("127.0.0.1".to_string(), Some(514), message()).unwrap().unwrap();
+        assert_eq!((entry.severity_name, entry.facility_name), (None, None));
+
+        handler.config.named_levels = true;
+        let entry = handler.handle_log("127.0.0.1".to_string(), Some(514), message()).unwrap().unwrap();
+        assert_eq!(entry.severity_name, Some("critical"));
+        assert_eq!(entry.facility_name, Some("auth"));
+    }
+
+    #[tokio::test]
+    async fn trailing_spaces_survive_without_trimming() {
+        let mut handler = LogHandler::for_test(HandlerConfig::default());
+        let message = "<13>1 2024-01-15T12:00:00Z host app - - - \tfixed width   ".to_string();
+
+        let entry = handler.handle_log("127.0.0.1".to_string(), Some(514), message.clone()).unwrap().unwrap();
+        assert_eq!(entry.syslog, "fixed width");
+
+        handler.config.trim = Trim::Trailing;
+        let entry = handler.handle_log("127.0.0.1".to_string(), Some(514), message.clone()).unwrap().unwrap();
+        assert_eq!(entry.syslog, "\tfixed width");
+
+        handler.config.trim = Trim::None;
+        let entry = handler.handle_log("127.0.0.1".to_string(), Some(514), message).unwrap().unwrap();
+        assert_eq!(entry.syslog, "\tfixed width   ");
+    }
+
+    #[tokio::test]
+    async fn kept_newlines_round_trip_through_csv() {
+        let (mut handler, path) = test_handler("newlines").await;
+        let trace = "at app.main(Main.java:10)\r\n\tat app.run(Run.java:5)\nCaused by: \"oops\", again";
+        let message = format!("<11>Oct 11 22:14:15 host app: Exception\n{}", trace);
+
+        let entry = handler.handle_log("127.0.0.1".to_string(), Some(514), message.clone()).unwrap().unwrap();
+        assert_eq!(entry.syslog, format!("Exception{}", trace.replace('\n', "")));
+
+        handler.config.newline_handling = NewlineHandling::Escape;
+        let entry = handler.handle_log("127.0.0.1".to_string(), Some(514), message.clone()).unwrap().unwrap();
+        assert_eq!(entry.syslog, format!("Exception\\n{}", trace.replace('\n', "\\n")));
+
+        handler.config.newline_handling = NewlineHandling::Keep;
+        let entry = handler.handle_log("127.0.0.1".to_string(), Some(514), message).unwrap().unwrap();
+        handler.write_to_csv(&mut vec![entry]).await.unwrap();
+        let mut reader = csv::Reader::from_path(&path).unwrap();
+        let records: Vec<_> = reader.records().collect::<Result<_, _>>().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(&records[0][2], format!("Exception\n{}", trace));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_writes_are_spilled_and_replayed() {
+        let dir = std::env::temp_dir().join(format!("syslog-server-dead-letter-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (output, routed, dead_letter) = (dir.join("all.csv"), dir.join("missing").join("kern.csv"), dir.join("dead.jsonl"));
+        let config = HandlerConfig {
+            write_retries: 2,
+            write_retry_backoff: Duration::from_millis(1),
+            dead_letter: Some(dead_letter.clone()),
+            ..HandlerConfig::default()
+        };
+        // The route is only opened once used, so it fails while its directory is gone.
+        std::fs::create_dir_all(routed.parent().unwrap()).unwrap();
+        let routes = HashMap::from([(0, routed.clone())]);
+        let writer = OutputRouter::open(&output, routes, OutputOptions::default(), RotationPolicy::default()).await.unwrap();
+        std::fs::remove_dir_all(routed.parent().unwrap()).unwrap();
+        let handler = LogHandler::for_test(config).with_writer(writer);
+
+        let entry = handler.handle_log("127.0.0.1".to_string(), Some(514), "<2>Oct 11 22:14:15 host kernel: oops".to_string());
+        let mut batch = vec![entry.unwrap().unwrap()];
+        handler.write_batch(&mut batch).await;
+        assert!(batch.is_empty());
+        let spilled = std::fs::read_to_string(&dead_letter).unwrap();
+        assert_eq!(spilled.lines().count(), 1);
+        assert!(spilled.contains(r#""syslog":"oops""#));
+
+        std::fs::create_dir_all(routed.parent().unwrap()).unwrap();
+        handler.replay_dead_letter(100).await.unwrap();
+        assert!(!dead_letter.exists());
+        assert!(!dir.join("dead.jsonl.replaying").exists());
+        let mut reader = csv::Reader::from_path(&routed).unwrap();
+        let records: Vec<_> = reader.records().collect::<Result<_, _>>().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(&records[0][2], "oops");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn retries_skip_the_outputs_that_took_the_batch() {
+        let dir = std::env::temp_dir().join(format!("syslog-server-partial-flush-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (output, routed, dead_letter) = (dir.join("all.csv"), dir.join("missing").join("kern.csv"), dir.join("dead.jsonl"));
+        let config = HandlerConfig {
+            write_retries: 2,
+            write_retry_backoff: Duration::from_millis(1),
+            dead_letter: Some(dead_letter.clone()),
+            ..HandlerConfig::default()
+        };
+        std::fs::create_dir_all(routed.parent().unwrap()).unwrap();
+        let routes = HashMap::from([(0, routed.clone())]);
+        let writer = OutputRouter::open(&output, routes, OutputOptions::default(), RotationPolicy::default()).await.unwrap();
+        std::fs::remove_dir_all(routed.parent().unwrap()).unwrap();
+        let tail = Arc::new(tail::TailBuffer::new(100));
+        let handler = LogHandler { tail: Some(Arc::clone(&tail)), ..LogHandler::for_test(config).with_writer(writer) };
+
+        // The default output flushes its share, then the routed entry fails
+        // on every attempt.
+        let mut batch: Vec<SysLogEntry> = (0..3)
+            .map(|n| SysLogEntry { syslog: format!("entry {}", n), facility: 1, ..Default::default() })
+            .collect();
+        batch.push(SysLogEntry { syslog: "oops".to_string(), ..Default::default() });
+        handler.write_batch(&mut batch).await;
+
+        let mut reader = csv::Reader::from_path(&output).unwrap();
+        let records: Vec<_> = reader.records().map(|record| record.unwrap()[2].to_string()).collect();
+        assert_eq!(records, ["entry 0", "entry 1", "entry 2"]);
+        let spilled = std::fs::read_to_string(&dead_letter).unwrap();
+        assert_eq!(spilled.lines().count(), 1);
+        assert!(spilled.contains(r#""syslog":"oops""#));
+        // Only what was written is handed on; the rest waits for its replay.
+        let recent = tail.recent(100);
+        assert_eq!(recent.len(), 3);
+        assert!(recent.iter().all(|entry| entry.syslog != "oops"));
+
+        std::fs::create_dir_all(routed.parent().unwrap()).unwrap();
+        handler.replay_dead_letter(100).await.unwrap();
+        assert_eq!(csv::Reader::from_path(&output).unwrap().records().count(), 3);
+        assert_eq!(csv::Reader::from_path(&routed).unwrap().records().count(), 1);
+        assert_eq!(tail.recent(100).len(), 4);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn an_interrupted_replay_is_taken_again() {
+        let path = std::env::temp_dir().join(format!("syslog-server-replay-{}.jsonl", std::process::id()));
+        let entry = |syslog: &str| SysLogEntry { syslog: syslog.to_string(), ..Default::default() };
+        dead_letter::spill(&path, &[entry("first")]).await.unwrap();
+        assert_eq!(dead_letter::take(&path).await.unwrap().len(), 1);
+
+        // The replay dies before `finish`, after something else was spilled.
+        dead_letter::spill(&path, &[entry("second")]).await.unwrap();
+        let entries = dead_letter::take(&path).await.unwrap();
+        let syslogs: Vec<&str> = entries.iter().map(|entry| entry.syslog.as_str()).collect();
+        assert_eq!(syslogs, ["first", "second"]);
+        assert!(!path.exists());
+
+        dead_letter::finish(&path).await.unwrap();
+        assert!(dead_letter::take(&path).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reopening_a_csv_file_keeps_its_one_header() {
+        let path = std::env::temp_dir().join(format!("syslog-server-reopen-{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        for row in 0..3 {
+            let mut writer = OutputWriter::open(&path, OutputOptions::default(), RotationPolicy::default()).await.unwrap();
+            writer.write_batch([&SysLogEntry { syslog: format!("row {}", row), ..Default::default() }]).await.unwrap();
+            writer.close().await.unwrap();
+        }
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with("event_time,"));
+        assert_eq!(contents.lines().filter(|line| line.starts_with("event_time,")).count(), 1);
+        let mut reader = csv::Reader::from_path(&path).unwrap();
+        let rows: Vec<String> = reader.records().map(|record| record.unwrap()[2].to_string()).collect();
+        assert_eq!(rows, ["row 0", "row 1", "row 2"]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn coalesced_bursts_keep_their_order() {
+        let path = std::env::temp_dir().join(format!("syslog-server-coalesce-{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut writer = OutputWriter::open(&path, OutputOptions::default(), RotationPolicy::default()).await.unwrap();
+        // Bursts of varying length from three sources, interleaved, with one
+        // burst long enough to span several staged runs.
+        let mut expected = Vec::new();
+        for (burst, len) in [3, 1, 700, 2, 5, 1, 1, 40].into_iter().enumerate() {
+            let device_ip = format!("192.0.2.{}", burst % 3);
+            for line in 0..len {
+                let syslog = format!("burst {} line {} {}", burst, line, "x".repeat(100));
+                expected.push((device_ip.clone(), syslog.clone()));
+                writer.write(&SysLogEntry { device_ip: device_ip.clone(), syslog, ..Default::default() }).await.unwrap();
+            }
+            if burst == 4 {
+                writer.flush().await.unwrap();
+            }
+        }
+        writer.close().await.unwrap();
+
+        let mut reader = csv::Reader::from_path(&path).unwrap();
+        let written: Vec<(String, String)> = reader
+            .records()
+            .map(|record| {
+                let record = record.unwrap();
+                (record[1].to_string(), record[2].to_string())
+            })
+            .collect();
+        assert_eq!(written, expected);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn dated_paths_fill_in_placeholders() {
+        let now = chrono::TimeZone::with_ymd_and_hms(&Local, 2024, 1, 15, 12, 0, 0).unwrap();
+        let dated = output::dated_path(Path::new("/var/log/%Y/syslog-%Y-%m-%d-100%%.csv"), now).unwrap();
+        assert_eq!(dated, Path::new("/var/log/2024/syslog-2024-01-15-100%.csv"));
+        assert_eq!(output::dated_path(Path::new("syslog.csv"), now).unwrap(), Path::new("syslog.csv"));
+        assert!(output::dated_path(Path::new("syslog-%Q.csv"), now).is_err());
+    }
+
+    #[tokio::test]
+    async fn dated_output_moves_to_a_new_file_with_a_header() {
+        // `%f` is the time's nanoseconds, so every batch lands in a new file.
+        let dir = std::env::temp_dir().join(format!("syslog-server-dated-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut writer = OutputWriter::open(&dir.join("out-%f.csv"), OutputOptions::default(), RotationPolicy::default())
+            .await
+            .unwrap();
+        for n in 0..2 {
+            tokio::time::sleep(Duration::from_millis(2)).await;
+            writer.write_batch([&SysLogEntry { syslog: format!("entry {}", n), ..Default::default() }]).await.unwrap();
+        }
+        writer.close().await.unwrap();
+
+        // The file opened first is left empty, then each entry has its own.
+        let mut files: Vec<Vec<String>> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| {
+                let mut reader = csv::Reader::from_path(entry.unwrap().path()).unwrap();
+                assert_eq!(&reader.headers().unwrap()[0], "event_time");
+                reader.records().map(|record| record.unwrap()[2].to_string()).collect()
+            })
+            .collect();
+        files.sort();
+        assert_eq!(files, [vec![], vec!["entry 0".to_string()], vec!["entry 1".to_string()]]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sd_columns_follow_the_usual_ones() {
+        let path = std::env::temp_dir().join(format!("syslog-server-sd-columns-{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let options = OutputOptions {
+            sd_columns: vec![output::sd_column::parse_sd_column("exampleSDID@32473.iut=iut").unwrap()],
+            ..OutputOptions::default()
+        };
+        let mut writer = OutputWriter::open(&path, options, RotationPolicy::default()).await.unwrap();
+        let handler = LogHandler::for_test(HandlerConfig::default());
+        for message in [
+            r#"<165>1 2003-10-11T22:14:15.003Z host app - ID47 [exampleSDID@32473 iut="3" eventSource="App"] hello"#,
+            "<13>Oct 11 22:14:15 host app: no structured data",
+        ] {
+            let entry = handler.handle_log("127.0.0.1".to_string(), Some(514), message.to_string()).unwrap().unwrap();
+            writer.write(&entry).await.unwrap();
+        }
+        writer.close().await.unwrap();
+
+        let mut reader = csv::Reader::from_path(&path).unwrap();
+        let headers = reader.headers().unwrap().clone();
+        assert_eq!(headers.iter().next_back(), Some("iut"));
+        let records: Vec<_> = reader.records().collect::<Result<_, _>>().unwrap();
+        let column = |record: &csv::StringRecord, name| record[headers.iter().position(|header| header == name).unwrap()].to_string();
+        assert_eq!(column(&records[0], "iut"), "3");
+        assert_eq!(column(&records[0], "structured_data"), r#"[exampleSDID@32473 eventSource="App"]"#);
+        assert_eq!(column(&records[1], "iut"), "");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sinks_get_every_entry_alongside_the_output() {
+        let path = std::env::temp_dir().join(format!("syslog-server-sink-{}.jsonl", std::process::id()));
+        let options = OutputOptions { format: OutputFormat::Jsonl, ..OutputOptions::default() };
+        let sink = OutputWriter::open(&path, options, RotationPolicy::default()).await.unwrap();
+        let (sender, sink_writer) = output::sink::spawn_sink(sink);
+        let (output, output_path) = test_handler("sink-output").await;
+        let handler = LogHandler { writer: output.writer, sinks: vec![sender], ..LogHandler::for_test(HandlerConfig::default()) };
+
+        let mut batch: Vec<_> = (0..3)
+            .map(|row| {
+                let message = format!("<13>Oct 11 22:14:15 host app: row {}", row);
+                handler.handle_log("127.0.0.1".to_string(), Some(514), message).unwrap().unwrap()
+            })
+            .collect();
+        handler.write_to_csv(&mut batch).await.unwrap();
+        drop(handler);
+        sink_writer.await.unwrap();
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.iter().map(|line| line["syslog"].as_str().unwrap()).collect::<Vec<_>>(), ["row 0", "row 1", "row 2"]);
+        assert_eq!(csv::Reader::from_path(&output_path).unwrap().records().count(), 3);
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(output_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reload_swaps_severity_filter_and_routes() {
+        let (handler, path) = test_handler("reload").await;
+        let routed = std::env::temp_dir().join(format!("syslog-server-reload-auth-{}.csv", std::process::id()));
+        let handle = |message: &str| handler.handle_log("127.0.0.1".to_string(), Some(514), message.to_string()).unwrap();
+        assert!(handle("<38>Oct 11 22:14:15 host su: ok").is_some());
+
+        handler.reload(4, HashMap::from([(4, routed.clone())])).await.unwrap();
+        assert!(handle("<38>Oct 11 22:14:15 host su: ok").is_none());
+        let mut batch = vec![handle("<36>Oct 11 22:14:15 host su: warn").unwrap()];
+        handler.write_to_csv(&mut batch).await.unwrap();
+        assert_eq!(csv::Reader::from_path(&routed).unwrap().records().count(), 1);
+        assert_eq!(csv::Reader::from_path(&path).unwrap().records().count(), 0);
+
+        // An unwritable route is rejected before anything changes.
+        let missing = std::env::temp_dir().join("syslog-server-missing-dir").join("x.csv");
+        assert!(handler.reload(7, HashMap::from([(4, missing)])).await.is_err());
+        assert!(handle("<38>Oct 11 22:14:15 host su: ok").is_none());
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(routed).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sampling_applies_facility_rates_over_the_default() {
+        let config = HandlerConfig {
+            sample_rate: 0.5,
+            sample_facility: HashMap::from([(7, 0.0), (4, 1.0)]),
+            ..HandlerConfig::default()
+        };
+        let handler = LogHandler::for_test(config);
+        let kept = |pri: u8| {
+            (0..1000)
+                .filter(|_| {
+                    let message = format!("<{}>Oct 11 22:14:15 host app: hello", pri);
+                    handler.handle_log("127.0.0.1".to_string(), Some(514), message).unwrap().is_some()
+                })
+                .count()
+        };
+
+        assert_eq!(kept(7 * 8 + 6), 0);
+        assert_eq!(kept(4 * 8 + 6), 1000);
+        let user = kept(8 + 6);
+        assert!((400..600).contains(&user), "kept {} of 1000 at rate 0.5", user);
+    }
+
+    #[tokio::test]
+    async fn remapped_severity_keeps_the_original() {
+        let config = HandlerConfig {
+            min_severity: 4,
+            severity_remap: HashMap::from([parse_severity_remap("6=4").unwrap()]),
+            named_levels: true,
+            ..HandlerConfig::default()
+        };
+        let handler = LogHandler::for_test(config);
+        let handle = |message: &str| handler.handle_log("127.0.0.1".to_string(), Some(514), message.to_string()).unwrap();
+
+        // Remapped before --min-severity, so the informational message survives it.
+        let entry = handle("<14>Oct 11 22:14:15 host app: disk 91% full").expect("remapped to warning");
+        assert_eq!((entry.severity, entry.original_severity), (4, Some(6)));
+        assert_eq!(entry.severity_name, Some("warning"));
+        let entry = handle("<11>Oct 11 22:14:15 host app: disk full").unwrap();
+        assert_eq!((entry.severity, entry.original_severity), (3, None));
+        assert!(handle("<15>Oct 11 22:14:15 host app: debug").is_none());
+
+        assert!(parse_severity_remap("8=4").is_err());
+        assert!(parse_severity_remap("6=9").is_err());
+        assert!(parse_severity_remap("6").is_err());
+    }
+
+    #[tokio::test]
+    async fn raw_keeps_the_bytes_as_received() {
+        let mut handler = LogHandler::for_test(HandlerConfig { keep_raw: true, ..HandlerConfig::default() });
+        let bytes = b"<13>Oct 11 22:14:15 host app: caf\xE9\nline two ";
+
+        let mut batch = Vec::new();
+        handler.process(RawMessage::decode("127.0.0.1".to_string(), Some(514), bytes), &mut batch);
+        let entry = batch.pop().unwrap();
+        assert_eq!(entry.syslog, "caf\u{FFFD}line two");
+        assert_eq!(BASE64_STANDARD.decode(entry.raw.unwrap()).unwrap(), bytes);
+
+        handler.config.keep_raw = false;
+        handler.process(RawMessage::decode("127.0.0.1".to_string(), Some(514), bytes), &mut batch);
+        assert_eq!(batch.pop().unwrap().raw, None);
+    }
+
+    #[tokio::test]
+    async fn redaction_masks_every_match() {
+        let config = HandlerConfig {
+            redact_patterns: vec![
+                redact::parse_pattern(r"password=\S+").unwrap(),
+                redact::parse_pattern(r"\b\d(?:[ -]?\d){12,15}\b").unwrap(),
+            ],
+            ..HandlerConfig::default()
+        };
+        let handler = LogHandler::for_test(config);
+        let message = "<13>Oct 11 22:14:15 host app: login password=hunter2 card 4111 1111 1111 1111 ok".to_string();
+        let entry = handler.handle_log("127.0.0.1".to_string(), Some(514), message).unwrap().unwrap();
+        assert_eq!(entry.syslog, "login *** card *** ok");
+
+        assert!(redact::parse_pattern("password=(").is_err());
+    }
+
+    #[tokio::test]
+    async fn event_time_can_come_from_the_message() {
+        let config = HandlerConfig { event_time_source: EventTimeSource::Parsed, ..HandlerConfig::default() };
+        let handler = LogHandler::for_test(config);
+        let handle = |message: &str| handler.handle_log("127.0.0.1".to_string(), Some(514), message.to_string()).unwrap().unwrap();
+
+        let entry = handle("<165>1 2003-10-11T22:14:15.003Z host app - - - hello");
+        let sent_at = DateTime::parse_from_rfc3339("2003-10-11T22:14:15.003Z").unwrap().with_timezone(&Local);
+        assert_eq!(entry.event_time, TimestampFormat::Rfc3339.format(sent_at));
+        let just_now = |time: &str| (Local::now() - DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Local)).num_seconds() < 60;
+        assert!(just_now(&entry.secondary_time.unwrap()));
+        assert!(!entry.time_fallback);
+
+        let entry = handle("<13>no timestamp here");
+        assert!(just_now(&entry.event_time));
+        assert_eq!(entry.secondary_time, None);
+        assert!(entry.time_fallback);
+
+        let handler = LogHandler::for_test(HandlerConfig::default());
+        let entry = handler.handle_log("127.0.0.1".to_string(), Some(514), "<165>1 2003-10-11T22:14:15.003Z host app - - - hello".to_string()).unwrap().unwrap();
+        assert_eq!(entry.secondary_time, Some(TimestampFormat::Rfc3339.format(sent_at)));
+        assert!(!entry.time_fallback);
+    }
+
+    #[tokio::test]
+    async fn message_hashes_depend_only_on_the_message() {
+        let config = HandlerConfig { hash_messages: true, ..HandlerConfig::default() };
+        let handler = LogHandler::for_test(config);
+        let hash = |source: &str, message: &str| {
+            let entry = handler.handle_log(source.to_string(), Some(514), message.to_string()).unwrap().unwrap();
+            entry.msg_hash.unwrap()
+        };
+
+        let first = hash("192.0.2.1", "<13>Oct 11 22:14:15 host app: disk full");
+        assert_eq!(first, hash("192.0.2.2", "<13>Oct 12 08:00:00 host app: disk full"));
+        assert_ne!(first, hash("192.0.2.1", "<13>Oct 11 22:14:15 host app: disk fine"));
+        // Pinned so a change of algorithm, which would break joins against
+        // earlier output, shows up here.
+        assert_eq!(first, "e219759a0bde6d1e");
+    }
+
+    #[tokio::test]
+    async fn binding_is_retried_until_the_port_is_free() {
+        let held = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = held.local_addr().unwrap();
+        assert!(bind_with_retry(addr, socket2::Type::DGRAM, 0).await.is_err());
+
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            drop(held);
+        });
+        let socket = bind_with_retry(addr, socket2::Type::DGRAM, 3).await.unwrap();
+        assert_eq!(socket.local_addr().unwrap().as_socket(), Some(addr));
+        release.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn ipv4_mapped_sources_are_stored_as_ipv4() {
+        let message = "<13>Oct 11 22:14:15 host app: hello";
+        let handler = LogHandler::for_test(HandlerConfig::default());
+        let entry = handler.handle_log("::ffff:192.0.2.1".to_string(), Some(514), message.to_string()).unwrap().unwrap();
+        assert_eq!(entry.device_ip, "192.0.2.1");
+        let entry = handler.handle_log("2001:db8::1".to_string(), Some(514), message.to_string()).unwrap().unwrap();
+        assert_eq!(entry.device_ip, "2001:db8::1");
+
+        let config = HandlerConfig { normalize_v4_mapped: false, ..HandlerConfig::default() };
+        let handler = LogHandler::for_test(config);
+        let entry = handler.handle_log("::ffff:192.0.2.1".to_string(), Some(514), message.to_string()).unwrap().unwrap();
+        assert_eq!(entry.device_ip, "::ffff:192.0.2.1");
+    }
+
+    #[tokio::test]
+    async fn summaries_count_every_received_message() {
+        let path = std::env::temp_dir().join(format!("syslog-server-summary-{}.jsonl", std::process::id()));
+        let summary = Arc::new(Summarizer::open(&path, TimestampFormat::default()).await.unwrap());
+        let config = HandlerConfig { min_severity: 4, ..HandlerConfig::default() };
+        let handler = LogHandler { summary: Some(Arc::clone(&summary)), ..LogHandler::for_test(config) };
+        for (ip, message) in [
+            ("10.0.0.1", "<11>Oct 11 22:14:15 host app: error"),
+            ("10.0.0.1", "<11>Oct 11 22:14:15 host app: error"),
+            ("10.0.0.1", "<15>Oct 11 22:14:15 host app: filtered debug"),
+            ("10.0.0.2", "<999>dropped for its bad PRI"),
+        ] {
+            let _ = handler.handle_log(ip.to_string(), Some(514), message.to_string());
+        }
+        summary.flush().await.unwrap();
+        summary.flush().await.unwrap();
+
+        let counts: Vec<(String, u64, u64)> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let record: serde_json::Value = serde_json::from_str(line).unwrap();
+                let ip = record["device_ip"].as_str().unwrap().to_string();
+                (ip, record["severity"].as_u64().unwrap(), record["count"].as_u64().unwrap())
+            })
+            .collect();
+        assert_eq!(
+            counts,
+            [("10.0.0.1".to_string(), 3, 2), ("10.0.0.1".to_string(), 7, 1), ("10.0.0.2".to_string(), 255, 1)]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn printed_config_uses_the_command_line_forms() {
+        let args = Args::try_parse_from([
+            "syslog-server",
+            "--allow-cidr",
+            "10.0.0.0/8",
+            "--csv-delimiter",
+            "|",
+            "--facility-route",
+            "4=auth.csv",
+            "--print-config",
+        ])
+        .unwrap();
+        let printed = serde_json::to_value(&args).unwrap();
+        assert_eq!(printed["allow_cidr"], serde_json::json!(["10.0.0.0/8"]));
+        assert_eq!(printed["csv_delimiter"], "|");
+        assert_eq!(printed["facility_route"], serde_json::json!(["4=auth.csv"]));
+        assert_eq!(printed["unix_socket_mode"], "666");
+        assert!(printed.get("print_config").is_none());
+    }
+}
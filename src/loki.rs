@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use hyper::body::Bytes;
+use hyper::client::HttpConnector;
+use hyper::{header, Body, Client, Method, Request, StatusCode, Uri};
+use metrics::increment_counter;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::{SysLogEntry, LEGACY_TIME_FORMAT};
+
+/// Attempts at pushing a batch before it is given up on.
+const PUSH_ATTEMPTS: u32 = 4;
+
+/// Delay before the first retry of a failed push; doubled on each further one.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Longest a single push may take before it counts as failed.
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Parses `--loki-url`, Loki's push endpoint, such as
+/// `http://loki:3100/loki/api/v1/push`.
+pub fn parse_loki_url(url: &str) -> Result<Uri, String> {
+    let uri: Uri = url.parse().map_err(|e| format!("Loki URL `{}` is invalid: {}", url, e))?;
+    match uri.scheme_str() {
+        Some("http") if uri.host().is_some() => Ok(uri),
+        Some("https") => Err(format!(
+            "Loki URL `{}` uses https, which isn't supported; push through a TLS-terminating proxy instead",
+            url
+        )),
+        _ => Err(format!("Loki URL `{}` is not http://HOST[:PORT]/PATH", url)),
+    }
+}
+
+/// Pushes entries to Grafana Loki, labelled by `device_ip`, `facility` and
+/// `severity`, with each entry as a JSON line.
+pub struct LokiSink {
+    client: Client<HttpConnector>,
+    url: Uri,
+    batch_size: usize,
+    flush_interval: Duration,
+}
+
+impl LokiSink {
+    /// Entries are pushed `batch_size` at a time, or whatever has arrived
+    /// every `flush_interval`.
+    pub fn new(url: Uri, batch_size: usize, flush_interval: Duration) -> Self {
+        LokiSink {
+            client: Client::new(),
+            url,
+            batch_size,
+            flush_interval,
+        }
+    }
+
+    /// Pushes everything received on `rx`, then whatever is left once it
+    /// closes.
+    pub async fn run(self, mut rx: mpsc::Receiver<Arc<[SysLogEntry]>>) {
+        info!("Pushing entries to Loki at {}", self.url);
+        let mut pending: Vec<Arc<[SysLogEntry]>> = Vec::new();
+        let mut count = 0;
+        let mut flush_timer = tokio::time::interval(self.flush_interval);
+        loop {
+            tokio::select! {
+                received = rx.recv() => {
+                    let Some(batch) = received else { break };
+                    count += batch.len();
+                    pending.push(batch);
+                    if count < self.batch_size {
+                        continue;
+                    }
+                }
+                _ = flush_timer.tick() => {
+                    if count == 0 {
+                        continue;
+                    }
+                }
+            }
+            self.push(&pending, count).await;
+            pending.clear();
+            count = 0;
+        }
+        if count > 0 {
+            self.push(&pending, count).await;
+        }
+    }
+
+    /// Pushes `count` entries, retrying with backoff while Loki is
+    /// unreachable or overloaded. Each failed attempt is counted in
+    /// `syslog_loki_errors_total`.
+    async fn push(&self, batches: &[Arc<[SysLogEntry]>], count: usize) {
+        let body = Bytes::from(push_body(batches.iter().flat_map(|batch| batch.iter()), Local::now()).to_string());
+        let mut backoff = INITIAL_BACKOFF;
+        for attempt in 1.. {
+            let Err((e, retryable)) = self.send(body.clone()).await else { return };
+            increment_counter!("syslog_loki_errors_total");
+            if !retryable || attempt == PUSH_ATTEMPTS {
+                error!("Gave up pushing {} entries to Loki: {}", count, e);
+                return;
+            }
+            warn!("Failed to push to Loki, retrying in {:?} (attempt {}): {}", backoff, attempt, e);
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+
+    /// Sends one push request. On failure, also says whether trying again
+    /// could help: Loki rejecting the body as a bad request won't change.
+    async fn send(&self, body: Bytes) -> Result<(), (String, bool)> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(self.url.clone())
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .expect("push request is valid");
+        let response = match tokio::time::timeout(PUSH_TIMEOUT, self.client.request(request)).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => return Err((e.to_string(), true)),
+            Err(_) => return Err((format!("no response within {:?}", PUSH_TIMEOUT), true)),
+        };
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let detail = hyper::body::to_bytes(response.into_body()).await.unwrap_or_default();
+        let retryable = status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS;
+        Err((format!("{}: {}", status, String::from_utf8_lossy(&detail).trim()), retryable))
+    }
+}
+
+/// A stream's labels: device_ip, facility and severity.
+type Labels<'a> = (&'a str, u8, u8);
+
+/// The body of a push: one stream per set of labels, holding its entries
+/// in the order given.
+fn push_body<'a>(entries: impl Iterator<Item = &'a SysLogEntry>, now: DateTime<Local>) -> serde_json::Value {
+    let mut streams: Vec<(Labels, Vec<[String; 2]>)> = Vec::new();
+    let mut index = HashMap::new();
+    for entry in entries {
+        let labels = (entry.device_ip.as_str(), entry.facility, entry.severity);
+        let stream = *index.entry(labels).or_insert_with(|| {
+            streams.push((labels, Vec::new()));
+            streams.len() - 1
+        });
+        let line = serde_json::to_string(entry).unwrap_or_else(|_| entry.syslog.clone());
+        streams[stream].1.push([timestamp_nanos(&entry.event_time, now), line]);
+    }
+    let streams: Vec<serde_json::Value> = streams
+        .into_iter()
+        .map(|((device_ip, facility, severity), values)| {
+            serde_json::json!({
+                "stream": {"device_ip": device_ip, "facility": facility.to_string(), "severity": severity.to_string()},
+                "values": values,
+            })
+        })
+        .collect();
+    serde_json::json!({ "streams": streams })
+}
+
+/// `event_time`, in any `--timestamp-format`, as the nanoseconds since the
+/// Unix epoch Loki expects; `now` if it can't be read.
+fn timestamp_nanos(event_time: &str, now: DateTime<Local>) -> String {
+    let time = DateTime::parse_from_rfc3339(event_time)
+        .map(|time| time.with_timezone(&Local))
+        .ok()
+        .or_else(|| event_time.parse().ok().and_then(|millis| Local.timestamp_millis_opt(millis).single()))
+        .or_else(|| {
+            let naive = NaiveDateTime::parse_from_str(event_time, LEGACY_TIME_FORMAT).ok()?;
+            Local.from_local_datetime(&naive).earliest()
+        })
+        .unwrap_or(now);
+    time.timestamp_nanos_opt().unwrap_or_default().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_grouped_into_labelled_streams() {
+        let entry = |device_ip: &str, severity, event_time: &str| SysLogEntry {
+            event_time: event_time.to_string(),
+            device_ip: device_ip.to_string(),
+            syslog: "hello".to_string(),
+            severity,
+            facility: 1,
+            ..Default::default()
+        };
+        let entries = [
+            entry("192.0.2.1", 3, "2024-01-15T12:00:00.000000001+00:00"),
+            entry("192.0.2.2", 3, "1705320000000"),
+            entry("192.0.2.1", 3, "2024-01-15T12:00:01+00:00"),
+        ];
+        let now = Local::now();
+        let body = push_body(entries.iter(), now);
+
+        let streams = body["streams"].as_array().unwrap();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0]["stream"], serde_json::json!({"device_ip": "192.0.2.1", "facility": "1", "severity": "3"}));
+        assert_eq!(streams[0]["values"][0][0], "1705320000000000001");
+        assert_eq!(streams[0]["values"][1][0], "1705320001000000000");
+        assert_eq!(streams[1]["values"][0][0], "1705320000000000000");
+        let line: serde_json::Value = serde_json::from_str(streams[0]["values"][0][1].as_str().unwrap()).unwrap();
+        assert_eq!(line["syslog"], "hello");
+
+        assert_eq!(timestamp_nanos("not a time", now), now.timestamp_nanos_opt().unwrap().to_string());
+    }
+
+    #[test]
+    fn only_plain_http_urls_are_accepted() {
+        assert!(parse_loki_url("http://loki:3100/loki/api/v1/push").is_ok());
+        assert!(parse_loki_url("https://loki/loki/api/v1/push").unwrap_err().contains("https"));
+        assert!(parse_loki_url("loki:3100").is_err());
+    }
+}
@@ -0,0 +1,972 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+use metrics::{decrement_gauge, histogram, increment_gauge};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tracing::{error, info, warn};
+
+use crate::{SysLogEntry, SyslogError};
+
+/// Largest run of records from one source handed to a text file's buffer in
+/// a single write.
+const COALESCE_BYTES: usize = 64 * 1024;
+
+pub mod console;
+pub mod fields;
+pub mod janitor;
+pub mod sd_column;
+pub mod sink;
+pub mod template;
+#[cfg(feature = "parquet")]
+mod parquet;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[derive(clap::ValueEnum, serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Comma-separated values with a header row
+    Csv,
+    /// One JSON object per line
+    Jsonl,
+    /// One line per entry, formatted by --template
+    Template,
+    /// Columnar Parquet row groups (requires the `parquet` feature)
+    Parquet,
+    /// Rows in an indexed SQLite table (requires the `sqlite` feature)
+    Sqlite,
+}
+
+#[derive(clap::ValueEnum, serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CsvQuoteStyle {
+    /// Quote every field
+    Always,
+    /// Quote fields containing the delimiter, a quote or a line break
+    Necessary,
+    /// Never quote; fields containing the delimiter will break the row
+    Never,
+}
+
+impl From<CsvQuoteStyle> for csv::QuoteStyle {
+    fn from(style: CsvQuoteStyle) -> Self {
+        match style {
+            CsvQuoteStyle::Always => csv::QuoteStyle::Always,
+            CsvQuoteStyle::Necessary => csv::QuoteStyle::Necessary,
+            CsvQuoteStyle::Never => csv::QuoteStyle::Never,
+        }
+    }
+}
+
+/// When written text is forced from the OS page cache onto the disk.
+#[derive(clap::ValueEnum, serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FsyncPolicy {
+    /// After every flushed batch
+    Always,
+    /// On the first flush once --fsync-interval-ms has passed
+    Interval,
+    /// Leave it to the OS
+    #[default]
+    Never,
+}
+
+/// Parses a CSV delimiter: a single ASCII character, or `\t` for a tab.
+pub fn parse_csv_delimiter(delimiter: &str) -> Result<u8, String> {
+    match delimiter.as_bytes() {
+        b"\\t" => Ok(b'\t'),
+        &[byte] if byte.is_ascii() => Ok(byte),
+        _ => Err(format!("expected a single ASCII character, got `{}`", delimiter)),
+    }
+}
+
+/// How entries are encoded in, and synced to, every output file.
+#[derive(Clone, Debug)]
+pub struct OutputOptions {
+    pub format: OutputFormat,
+    /// Line format for `OutputFormat::Template`.
+    pub template: Option<Arc<template::Template>>,
+    pub csv_delimiter: u8,
+    pub csv_quote_style: CsvQuoteStyle,
+    /// Fields written to CSV and JSON lines output, in order; all of them
+    /// without it.
+    pub fields: Option<fields::FieldSelection>,
+    /// SD-PARAMs moved out of `structured_data` into columns of their own,
+    /// appended after the usual ones. Only CSV output has them.
+    pub sd_columns: Vec<sd_column::SdColumn>,
+    /// Rows buffered per Parquet row group.
+    #[cfg_attr(not(feature = "parquet"), allow(dead_code))]
+    pub row_group_size: usize,
+    /// Only applies to text outputs; SQLite syncs its own commits.
+    pub fsync: FsyncPolicy,
+    /// Shortest gap between syncs under `FsyncPolicy::Interval`.
+    pub fsync_interval: Duration,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        OutputOptions {
+            format: OutputFormat::Csv,
+            template: None,
+            csv_delimiter: b',',
+            csv_quote_style: CsvQuoteStyle::Necessary,
+            fields: None,
+            sd_columns: Vec::new(),
+            row_group_size: 10_000,
+            fsync: FsyncPolicy::Never,
+            fsync_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RotateInterval {
+    Hourly,
+    Daily,
+}
+
+impl RotateInterval {
+    /// A key that changes exactly when a new rotation period starts.
+    fn period(self, time: DateTime<Local>) -> String {
+        match self {
+            RotateInterval::Hourly => time.format("%Y%m%d%H").to_string(),
+            RotateInterval::Daily => time.format("%Y%m%d").to_string(),
+        }
+    }
+}
+
+/// When the active output file should be rotated out, and what happens to it
+/// afterwards. Either limit (or both) may be unset.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RotationPolicy {
+    pub max_bytes: Option<u64>,
+    pub interval: Option<RotateInterval>,
+    /// Gzip rotated files in the background, removing the uncompressed copy.
+    pub compress: bool,
+}
+
+/// Sends each entry to the output file for its facility, falling back to the
+/// default output. Routed files are opened lazily on their first entry, and
+/// routes sharing a path share a single writer.
+pub struct OutputRouter {
+    default_path: PathBuf,
+    routes: HashMap<u8, PathBuf>,
+    options: OutputOptions,
+    rotation: RotationPolicy,
+    writers: HashMap<PathBuf, OutputWriter>,
+}
+
+impl OutputRouter {
+    pub async fn open(
+        default_path: &Path,
+        routes: HashMap<u8, PathBuf>,
+        options: OutputOptions,
+        rotation: RotationPolicy,
+    ) -> Result<Self, SyslogError> {
+        // Outputs are checked eagerly so a bad path fails at startup, not on
+        // the first message. Facility routes are only opened once used.
+        let writer = OutputWriter::open(default_path, options.clone(), rotation)
+            .await
+            .map_err(|e| SyslogError::Output(format!("Cannot write to output {}: {}", default_path.display(), e)))?;
+        for path in routes.values() {
+            let current = current_path(path).await?;
+            check_writable(&current)
+                .await
+                .map_err(|e| SyslogError::Output(format!("Cannot write to facility output {}: {}", path.display(), e)))?;
+        }
+        info!(
+            "Writing output to {}",
+            std::path::absolute(default_path).unwrap_or_else(|_| default_path.to_path_buf()).display()
+        );
+        Ok(OutputRouter {
+            default_path: default_path.to_path_buf(),
+            routes,
+            options,
+            rotation,
+            writers: HashMap::from([(default_path.to_path_buf(), writer)]),
+        })
+    }
+
+    /// Replaces the facility routes, checking new paths the way `open` does.
+    /// Writers for files that are no longer routed to are closed.
+    pub async fn set_routes(&mut self, routes: HashMap<u8, PathBuf>) -> Result<(), SyslogError> {
+        let added: Vec<&PathBuf> = routes.values().filter(|path| !self.writers.contains_key(*path)).collect();
+        for path in added {
+            let current = current_path(path).await?;
+            check_writable(&current)
+                .await
+                .map_err(|e| SyslogError::Output(format!("Cannot write to facility output {}: {}", path.display(), e)))?;
+        }
+        self.routes = routes;
+        let unused: Vec<PathBuf> = self
+            .writers
+            .keys()
+            .filter(|path| **path != self.default_path && !self.routes.values().any(|route| route == *path))
+            .cloned()
+            .collect();
+        for path in unused {
+            if let Some(writer) = self.writers.remove(&path) {
+                writer.close().await?;
+                info!("Closed output {}, which is no longer routed to", path.display());
+            }
+        }
+        Ok(())
+    }
+
+    /// The configured output path `entry` is routed to.
+    pub fn route(&self, entry: &SysLogEntry) -> &Path {
+        self.routes.get(&entry.facility).unwrap_or(&self.default_path)
+    }
+
+    /// Writes and flushes `batch`, one output at a time, adding each output
+    /// that took its share to `written`. Outputs already in `written` are
+    /// skipped, so a batch that failed part way can be passed again and only
+    /// goes to the outputs that didn't take it.
+    pub async fn write_batch(&mut self, batch: &[SysLogEntry], written: &mut HashSet<PathBuf>) -> Result<(), SyslogError> {
+        let mut shares: Vec<(&Path, Vec<&SysLogEntry>)> = Vec::new();
+        for entry in batch {
+            let path = self.route(entry);
+            if written.contains(path) {
+                continue;
+            }
+            match shares.iter_mut().find(|(share, _)| *share == path) {
+                Some((_, entries)) => entries.push(entry),
+                None => shares.push((path, vec![entry])),
+            }
+        }
+        let shares: Vec<(PathBuf, Vec<&SysLogEntry>)> =
+            shares.into_iter().map(|(path, entries)| (path.to_path_buf(), entries)).collect();
+        for (path, entries) in shares {
+            if !self.writers.contains_key(&path) {
+                let writer = OutputWriter::open(&path, self.options.clone(), self.rotation).await?;
+                info!("Opened output {} for facility {}", path.display(), entries[0].facility);
+                self.writers.insert(path.clone(), writer);
+            }
+            self.writers
+                .get_mut(&path)
+                .expect("writer was just inserted")
+                .write_batch(entries)
+                .await?;
+            written.insert(path);
+        }
+        Ok(())
+    }
+
+    /// Finishes every output file; nothing may be written afterwards.
+    pub async fn close(&mut self) -> Result<(), SyslogError> {
+        for (_, writer) in self.writers.drain() {
+            writer.close().await?;
+        }
+        Ok(())
+    }
+}
+
+enum Sink {
+    Text(BufWriter<File>),
+    #[cfg(feature = "parquet")]
+    Parquet(parquet::ParquetSink),
+    #[cfg(feature = "sqlite")]
+    Sqlite(sqlite::SqliteSink),
+}
+
+/// An output file that stays open for the lifetime of the server.
+///
+/// Records are serialized in memory and appended to the file through an async
+/// buffer that is pushed to disk on `flush`, though a large run can reach the
+/// file before then. A batch that fails is cut back off the file, so retrying
+/// it never duplicates rows. A CSV file that is empty
+/// when opened gets its header row there and then, so writes never emit one.
+///
+/// Parquet output is buffered into row groups instead, and files can't be
+/// appended to once their footer is written, so an existing file is rotated
+/// out of the way on open. SQLite output is inserted into the database's
+/// `syslog` table in one transaction per flushed batch.
+///
+/// Consecutive text records from the same `device_ip`, as a bursty source
+/// sends them, are staged and handed to the file buffer in one write once
+/// another source's record, `COALESCE_BYTES` or the batch's flush ends the
+/// run. A run larger than the buffer goes to the file in one call rather than
+/// one per buffer's worth, and records still reach the file in the order
+/// they were written.
+///
+/// A path with strftime placeholders, such as `syslog-%Y-%m-%d.csv`, is
+/// filled in with the local time. Whenever a batch starts and finds that
+/// the result has changed, the writer finishes the file and moves on to the
+/// new path, compressing the old file if the rotation policy asks.
+///
+/// When the rotation policy triggers, checked before each batch, the active
+/// file is renamed with a timestamp suffix and a fresh one is opened in its
+/// place. Callers share the writer behind a mutex, so rotation never races
+/// with a write.
+pub struct OutputWriter {
+    /// The path as configured, if it has placeholders to fill in.
+    dated: Option<PathBuf>,
+    path: PathBuf,
+    sink: Sink,
+    options: OutputOptions,
+    rotation: RotationPolicy,
+    size: u64,
+    /// Length of the text file as of the last successful flush; `discard`
+    /// cuts it back to this.
+    committed: u64,
+    /// Length of the CSV header row; a file no bigger holds no entries.
+    header_size: u64,
+    period: Option<String>,
+    last_sync: Instant,
+    /// The current run of records, not yet in the file buffer.
+    staged: Vec<u8>,
+    /// The `device_ip` of the staged run.
+    staged_source: String,
+    _open: OpenFile,
+}
+
+/// Counts an output file in `syslog_open_files` for as long as it is held,
+/// however its writer goes away.
+struct OpenFile;
+
+impl OpenFile {
+    fn new() -> Self {
+        increment_gauge!("syslog_open_files", 1.0);
+        OpenFile
+    }
+}
+
+impl Drop for OpenFile {
+    fn drop(&mut self) {
+        decrement_gauge!("syslog_open_files", 1.0);
+    }
+}
+
+impl OutputWriter {
+    pub async fn open(path: &Path, options: OutputOptions, rotation: RotationPolicy) -> Result<Self, SyslogError> {
+        let dated = is_dated(path).then(|| path.to_path_buf());
+        let path = &current_path(path).await?;
+        if options.format == OutputFormat::Parquet && fs::metadata(path).await.is_ok_and(|m| m.len() > 0) {
+            let rotated = rotated_path(path, Local::now()).await;
+            fs::rename(path, &rotated).await?;
+            info!("Moved existing {} to {}", path.display(), rotated.display());
+        }
+        let header_size = match options.format {
+            OutputFormat::Csv => csv_header(&options)?.len() as u64,
+            _ => 0,
+        };
+        let (sink, size, modified) = Self::open_sink(path, &options).await?;
+        Ok(OutputWriter {
+            dated,
+            path: path.to_path_buf(),
+            sink,
+            rotation,
+            options,
+            size,
+            committed: size,
+            header_size,
+            period: rotation.interval.map(|interval| interval.period(modified)),
+            last_sync: Instant::now(),
+            staged: Vec::new(),
+            staged_source: String::new(),
+            _open: OpenFile::new(),
+        })
+    }
+
+    async fn open_sink(path: &Path, options: &OutputOptions) -> Result<(Sink, u64, DateTime<Local>), SyslogError> {
+        match options.format {
+            OutputFormat::Csv => {
+                let (mut file, mut size, modified) = Self::open_file(path).await?;
+                if size == 0 {
+                    let header = csv_header(options)?;
+                    file.write_all(&header).await?;
+                    file.flush().await?;
+                    size = header.len() as u64;
+                }
+                Ok((Sink::Text(file), size, modified))
+            }
+            OutputFormat::Jsonl | OutputFormat::Template => {
+                let (file, size, modified) = Self::open_file(path).await?;
+                Ok((Sink::Text(file), size, modified))
+            }
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => {
+                let sink = parquet::ParquetSink::create(path, options.row_group_size)?;
+                Ok((Sink::Parquet(sink), 0, Local::now()))
+            }
+            #[cfg(not(feature = "parquet"))]
+            OutputFormat::Parquet => Err(SyslogError::Output("Parquet output requires building with `--features parquet`".to_string())),
+            #[cfg(feature = "sqlite")]
+            OutputFormat::Sqlite => {
+                let sink = sqlite::SqliteSink::open(path)?;
+                let size = sink.bytes_written();
+                Ok((Sink::Sqlite(sink), size, Local::now()))
+            }
+            #[cfg(not(feature = "sqlite"))]
+            OutputFormat::Sqlite => Err(SyslogError::Output("SQLite output requires building with `--features sqlite`".to_string())),
+        }
+    }
+
+    async fn open_file(path: &Path) -> Result<(BufWriter<File>, u64, DateTime<Local>), SyslogError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+
+        // An existing file belongs to the period it was last written in, so a
+        // restart the next day still rotates yesterday's data out.
+        let metadata = file.metadata().await?;
+        let modified = match metadata.modified() {
+            Ok(time) if metadata.len() > 0 => time.into(),
+            _ => Local::now(),
+        };
+        Ok((BufWriter::with_capacity(8192, file), metadata.len(), modified))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes and flushes `entries`, first moving to a new file if the dated
+    /// path or the rotation policy calls for it. If any of it fails, the part
+    /// of the batch already written is discarded, so the batch can be written
+    /// again without duplicating its start.
+    pub async fn write_batch<'a>(&mut self, entries: impl IntoIterator<Item = &'a SysLogEntry>) -> Result<(), SyslogError> {
+        let result = self.try_write_batch(entries).await;
+        if result.is_err() {
+            if let Err(e) = self.discard().await {
+                warn!("Failed to discard partly written batch for {}: {}", self.path.display(), e);
+            }
+        }
+        result
+    }
+
+    async fn try_write_batch<'a>(&mut self, entries: impl IntoIterator<Item = &'a SysLogEntry>) -> Result<(), SyslogError> {
+        self.start_batch().await?;
+        for entry in entries {
+            self.write(entry).await?;
+        }
+        self.flush().await
+    }
+
+    /// Moves on to a new file if the dated path now gives another one or
+    /// the rotation policy triggers. This only happens between batches, so
+    /// a failed batch never leaves part of itself in a finished file.
+    async fn start_batch(&mut self) -> Result<(), SyslogError> {
+        if let Some(dated) = &self.dated {
+            if dated_path(dated, Local::now()).map_err(SyslogError::Output)? != self.path {
+                let path = current_path(dated).await?;
+                self.switch_to(path).await?;
+            }
+        }
+        if self.should_rotate() {
+            self.rotate().await?;
+        }
+        Ok(())
+    }
+
+    pub async fn write(&mut self, entry: &SysLogEntry) -> Result<(), SyslogError> {
+        match &mut self.sink {
+            Sink::Text(_) => {}
+            #[cfg(feature = "parquet")]
+            Sink::Parquet(sink) => {
+                sink.write(entry);
+                return Ok(());
+            }
+            #[cfg(feature = "sqlite")]
+            Sink::Sqlite(sink) => return sink.write(entry),
+        }
+        let record = match self.options.format {
+            OutputFormat::Csv => {
+                let mut csv = csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .double_quote(true)
+                    .delimiter(self.options.csv_delimiter)
+                    .quote_style(self.options.csv_quote_style.into())
+                    .from_writer(Vec::new());
+                let (entry, values) = if self.options.sd_columns.is_empty() {
+                    (Cow::Borrowed(entry), Vec::new())
+                } else {
+                    let (structured_data, values) = sd_column::split(entry.structured_data.as_deref(), &self.options.sd_columns);
+                    (Cow::Owned(SysLogEntry { structured_data, ..entry.clone() }), values)
+                };
+                match &self.options.fields {
+                    Some(fields) => csv.serialize((fields.select(&entry), values))?,
+                    None => csv.serialize((&*entry, values))?,
+                }
+                csv.into_inner().map_err(|e| e.into_error())?
+            }
+            OutputFormat::Jsonl => {
+                let mut line = Vec::new();
+                match &self.options.fields {
+                    Some(fields) => serde_json::to_writer(&mut line, &fields.select(entry))?,
+                    None => serde_json::to_writer(&mut line, entry)?,
+                }
+                line.push(b'\n');
+                line
+            }
+            OutputFormat::Template => {
+                let template = self.options.template.as_ref().expect("template output is opened with a template");
+                let mut line = template.render(entry)?.into_bytes();
+                line.push(b'\n');
+                line
+            }
+            OutputFormat::Parquet | OutputFormat::Sqlite => unreachable!("{:?} output never opens a text sink", self.options.format),
+        };
+        if entry.device_ip != self.staged_source || self.staged.len() + record.len() > COALESCE_BYTES {
+            self.write_staged().await?;
+            self.staged_source.clone_from(&entry.device_ip);
+        }
+        self.staged.extend_from_slice(&record);
+        self.size += record.len() as u64;
+        Ok(())
+    }
+
+    /// Hands the staged run to the file buffer.
+    async fn write_staged(&mut self) -> io::Result<()> {
+        if self.staged.is_empty() {
+            return Ok(());
+        }
+        match &mut self.sink {
+            Sink::Text(file) => file.write_all(&self.staged).await?,
+            #[cfg(any(feature = "parquet", feature = "sqlite"))]
+            _ => {}
+        }
+        self.staged.clear();
+        Ok(())
+    }
+
+    /// Pushes buffered text to disk, syncing it as the fsync policy asks,
+    /// and commits SQLite rows. Parquet rows stay buffered until there are
+    /// enough for a row group.
+    pub async fn flush(&mut self) -> Result<(), SyslogError> {
+        self.write_staged().await?;
+        match &mut self.sink {
+            Sink::Text(file) => {
+                file.flush().await?;
+                let due = match self.options.fsync {
+                    FsyncPolicy::Always => true,
+                    FsyncPolicy::Interval => self.last_sync.elapsed() >= self.options.fsync_interval,
+                    FsyncPolicy::Never => false,
+                };
+                if due {
+                    sync_file(file.get_ref()).await?;
+                    self.last_sync = Instant::now();
+                }
+                self.committed = self.size;
+            }
+            #[cfg(feature = "parquet")]
+            Sink::Parquet(sink) => {
+                sink.commit()?;
+                self.size = sink.bytes_written();
+            }
+            #[cfg(feature = "sqlite")]
+            Sink::Sqlite(sink) => {
+                sink.commit()?;
+                self.size = sink.bytes_written();
+            }
+        }
+        Ok(())
+    }
+
+    /// Finishes the active file and opens `path` in its place, for a dated
+    /// path whose placeholders now give another file.
+    async fn switch_to(&mut self, path: PathBuf) -> Result<(), SyslogError> {
+        let (sink, size, modified) = Self::open_sink(&path, &self.options).await?;
+        close_sink(std::mem::replace(&mut self.sink, sink), self.options.fsync != FsyncPolicy::Never).await?;
+        let finished = std::mem::replace(&mut self.path, path);
+        info!("Finished {}, now writing {}", finished.display(), self.path.display());
+        self.size = size;
+        self.committed = size;
+        self.period = self.rotation.interval.map(|interval| interval.period(modified));
+        if self.rotation.compress {
+            compress_in_background(finished);
+        }
+        Ok(())
+    }
+
+    /// Drops what was written since the last successful flush. Text files
+    /// are cut back to their flushed length, taking back whatever part of
+    /// the batch had already reached the file, and reopened without their
+    /// unflushed buffer. Parquet drops the rows buffered since the last
+    /// flush, and SQLite rolls back.
+    async fn discard(&mut self) -> Result<(), SyslogError> {
+        match &mut self.sink {
+            Sink::Text(file) => {
+                self.staged.clear();
+                // Cut back through the same handle: its `set_len` waits for
+                // any write still in flight, which could otherwise land after
+                // the cut and put discarded rows back.
+                file.get_mut().set_len(self.committed).await?;
+                let (sink, size, _) = Self::open_sink(&self.path, &self.options).await?;
+                self.sink = sink;
+                self.size = size;
+                self.committed = size;
+            }
+            #[cfg(feature = "parquet")]
+            Sink::Parquet(sink) => {
+                sink.rollback();
+                self.size = sink.bytes_written();
+            }
+            #[cfg(feature = "sqlite")]
+            Sink::Sqlite(sink) => sink.rollback()?,
+        }
+        Ok(())
+    }
+
+    /// Flushes the file and, for Parquet, writes the last row group and footer.
+    /// SQLite commits its last transaction.
+    pub async fn close(mut self) -> Result<(), SyslogError> {
+        self.write_staged().await?;
+        let sync = self.options.fsync != FsyncPolicy::Never;
+        close_sink(self.sink, sync).await
+    }
+
+    fn should_rotate(&self) -> bool {
+        if self.size <= self.header_size {
+            return false;
+        }
+        let too_big = self.rotation.max_bytes.is_some_and(|max| self.size >= max);
+        let new_period = match (self.rotation.interval, &self.period) {
+            (Some(interval), Some(period)) => interval.period(Local::now()) != *period,
+            _ => false,
+        };
+        too_big || new_period
+    }
+
+    /// Renames the active file out of the way and starts a new one, returning
+    /// the path the old file was moved to.
+    async fn rotate(&mut self) -> Result<PathBuf, SyslogError> {
+        self.flush().await?;
+        let now = Local::now();
+        let rotated = rotated_path(&self.path, now).await;
+        fs::rename(&self.path, &rotated).await?;
+        info!("Rotated {} to {}", self.path.display(), rotated.display());
+
+        // The old file is finished through its still-open handle.
+        let (sink, size, _) = Self::open_sink(&self.path, &self.options).await?;
+        close_sink(std::mem::replace(&mut self.sink, sink), self.options.fsync != FsyncPolicy::Never).await?;
+        self.size = size;
+        self.committed = size;
+        self.period = self.rotation.interval.map(|interval| interval.period(now));
+
+        if self.rotation.compress {
+            compress_in_background(rotated.clone());
+        }
+        Ok(rotated)
+    }
+}
+
+/// The CSV header row, with its line terminator, as `options` would write it.
+fn csv_header(options: &OutputOptions) -> Result<Vec<u8>, SyslogError> {
+    let mut csv = csv::WriterBuilder::new()
+        .double_quote(true)
+        .delimiter(options.csv_delimiter)
+        .quote_style(options.csv_quote_style.into())
+        .from_writer(Vec::new());
+    let empty = SysLogEntry::default();
+    match &options.fields {
+        Some(fields) => csv.serialize(fields.select(&empty))?,
+        None => csv.serialize(&empty)?,
+    }
+    let mut header = csv.into_inner().map_err(|e| e.into_error())?;
+    let end = header.iter().position(|&b| b == b'\n').unwrap_or(header.len());
+    header.truncate(end);
+    if !options.sd_columns.is_empty() {
+        let mut csv = csv::WriterBuilder::new()
+            .delimiter(options.csv_delimiter)
+            .quote_style(options.csv_quote_style.into())
+            .from_writer(Vec::new());
+        csv.write_record(options.sd_columns.iter().map(|column| &column.column))?;
+        header.push(options.csv_delimiter);
+        header.extend(csv.into_inner().map_err(|e| e.into_error())?);
+    } else {
+        header.push(b'\n');
+    }
+    Ok(header)
+}
+
+/// Opens `path` for appending and closes it again, creating it if missing.
+async fn check_writable(path: &Path) -> io::Result<()> {
+    OpenOptions::new().create(true).append(true).open(path).await?;
+    Ok(())
+}
+
+/// Finishes `sink`, syncing a text file to disk first with `sync`.
+async fn close_sink(sink: Sink, sync: bool) -> Result<(), SyslogError> {
+    match sink {
+        Sink::Text(mut file) => {
+            file.flush().await?;
+            if sync {
+                sync_file(file.get_ref()).await?;
+            }
+        }
+        #[cfg(feature = "parquet")]
+        Sink::Parquet(sink) => sink.close()?,
+        #[cfg(feature = "sqlite")]
+        Sink::Sqlite(sink) => sink.close()?,
+    }
+    Ok(())
+}
+
+/// Waits for the file's data to reach the disk. Tokio runs the `fdatasync`
+/// on its blocking pool, so other tasks keep running meanwhile.
+async fn sync_file(file: &File) -> io::Result<()> {
+    let started = Instant::now();
+    file.sync_data().await?;
+    histogram!("syslog_fsync_duration_seconds", started.elapsed().as_secs_f64());
+    Ok(())
+}
+
+/// Whether `path` has strftime placeholders to fill in.
+pub fn is_dated(path: &Path) -> bool {
+    path.as_os_str().to_string_lossy().contains('%')
+}
+
+/// `path` with its strftime placeholders, if any, filled in for `now`, e.g.
+/// `/var/log/syslog-%Y-%m-%d.csv` becomes `/var/log/syslog-2024-01-15.csv`.
+/// `%%` stands for a literal `%`.
+pub fn dated_path(path: &Path, now: DateTime<Local>) -> Result<PathBuf, String> {
+    if !is_dated(path) {
+        return Ok(path.to_path_buf());
+    }
+    let pattern = path
+        .to_str()
+        .ok_or_else(|| format!("{} has placeholders but isn't valid UTF-8", path.display()))?;
+    let items: Vec<Item> = StrftimeItems::new(pattern).collect();
+    if items.contains(&Item::Error) {
+        return Err(format!("{} has an invalid strftime placeholder", path.display()));
+    }
+    Ok(PathBuf::from(now.format_with_items(items.into_iter()).to_string()))
+}
+
+/// The file `path` names right now, creating its directory if placeholders
+/// put it in one that doesn't exist yet.
+async fn current_path(path: &Path) -> Result<PathBuf, SyslogError> {
+    let current = dated_path(path, Local::now()).map_err(SyslogError::Output)?;
+    if current != path {
+        if let Some(dir) = current.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).await?;
+        }
+    }
+    Ok(current)
+}
+
+/// Gzips a finished file on the blocking pool, logging the outcome.
+fn compress_in_background(path: PathBuf) {
+    tokio::spawn(async move {
+        match tokio::task::spawn_blocking(move || compress_file(&path)).await {
+            Ok(Ok(compressed)) => info!("Compressed rotated file to {}", compressed.display()),
+            Ok(Err(e)) => error!("Failed to compress rotated file: {}", e),
+            Err(e) => error!("Compression task failed: {}", e),
+        }
+    });
+}
+
+/// `syslog.csv` becomes `syslog-20240115-1200.csv`, with a counter added if
+/// several rotations happen within the same minute.
+async fn rotated_path(path: &Path, now: DateTime<Local>) -> PathBuf {
+    // A name is also taken if an earlier rotation has been compressed.
+    async fn taken(path: &Path) -> bool {
+        fs::try_exists(path).await.unwrap_or(false) || fs::try_exists(gz_path(path)).await.unwrap_or(false)
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let stamp = now.format("%Y%m%d-%H%M");
+
+    let mut candidate = path.with_file_name(format!("{}-{}{}", stem, stamp, extension));
+    let mut counter = 1;
+    while taken(&candidate).await {
+        candidate = path.with_file_name(format!("{}-{}-{}{}", stem, stamp, counter, extension));
+        counter += 1;
+    }
+    candidate
+}
+
+fn gz_path(path: &Path) -> PathBuf {
+    let mut compressed = path.as_os_str().to_owned();
+    compressed.push(".gz");
+    PathBuf::from(compressed)
+}
+
+/// Gzips `path` to `<path>.gz` and removes the original once the compressed
+/// copy is complete. On failure the original is left untouched.
+fn compress_file(path: &Path) -> io::Result<PathBuf> {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    let compressed = gz_path(path);
+
+    let result = (|| {
+        let mut input = std::fs::File::open(path)?;
+        let output = std::io::BufWriter::new(std::fs::File::create(&compressed)?);
+        let mut encoder = GzEncoder::new(output, Compression::default());
+        io::copy(&mut input, &mut encoder)?;
+        encoder.finish()?.into_inner().map_err(|e| e.into_error())?.sync_all()
+    })();
+
+    match result {
+        Ok(()) => {
+            std::fs::remove_file(path)?;
+            Ok(compressed)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&compressed);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn open_files_are_counted_until_their_writer_is_dropped() {
+        crate::test_metrics::install();
+        let path = std::env::temp_dir().join(format!("syslog-server-open-files-{}.csv", std::process::id()));
+        let open_files = || crate::test_metrics::gauge("syslog_open_files", &[]);
+        let writer = OutputWriter::open(&path, OutputOptions::default(), RotationPolicy::default()).await.unwrap();
+        let other = OutputWriter::open(&path, OutputOptions::default(), RotationPolicy::default()).await.unwrap();
+        assert_eq!(open_files(), Some(2.0));
+
+        // Dropped without being closed, as when a sink task is aborted.
+        drop(writer);
+        assert_eq!(open_files(), Some(1.0));
+        other.close().await.unwrap();
+        assert_eq!(open_files(), Some(0.0));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn discard_cuts_back_what_reached_the_file() {
+        let path = std::env::temp_dir().join(format!("syslog-server-discard-{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut writer = OutputWriter::open(&path, OutputOptions::default(), RotationPolicy::default()).await.unwrap();
+        writer.write_batch([&SysLogEntry { syslog: "kept".to_string(), ..Default::default() }]).await.unwrap();
+        let flushed = fs::metadata(&path).await.unwrap().len();
+
+        // Runs from alternating sources overflow the file buffer long before
+        // any flush, and the last of those writes may still be in flight
+        // when the batch is discarded.
+        for n in 0..40 {
+            let entry = SysLogEntry { device_ip: format!("192.0.2.{}", n % 2), syslog: "x".repeat(1000), ..Default::default() };
+            writer.write(&entry).await.unwrap();
+        }
+        writer.discard().await.unwrap();
+        assert_eq!(fs::metadata(&path).await.unwrap().len(), flushed);
+
+        writer.write_batch([&SysLogEntry { syslog: "retried".to_string(), ..Default::default() }]).await.unwrap();
+        writer.close().await.unwrap();
+        let mut reader = csv::Reader::from_path(&path).unwrap();
+        let records: Vec<String> = reader.records().map(|record| record.unwrap()[2].to_string()).collect();
+        assert_eq!(records, ["kept", "retried"]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// The names in `dir` and their contents, in name order.
+    fn read_dir(dir: &Path) -> Vec<(String, String)> {
+        let mut files: Vec<(String, String)> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                (path.file_name().unwrap().to_string_lossy().into_owned(), std::fs::read_to_string(&path).unwrap())
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[tokio::test]
+    async fn size_rotation_starts_a_new_file_with_its_own_header() {
+        let dir = std::env::temp_dir().join(format!("syslog-server-rotate-size-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("syslog.csv");
+        let rotation = RotationPolicy { max_bytes: Some(1), ..Default::default() };
+        let mut writer = OutputWriter::open(&path, OutputOptions::default(), rotation).await.unwrap();
+        for syslog in ["first", "second", "third"] {
+            writer.write_batch([&SysLogEntry { syslog: syslog.to_string(), ..Default::default() }]).await.unwrap();
+        }
+        writer.close().await.unwrap();
+
+        let header = String::from_utf8(csv_header(&OutputOptions::default()).unwrap()).unwrap();
+        let files = read_dir(&dir);
+        assert_eq!(files.len(), 3, "{:?}", files);
+        for (name, contents) in &files {
+            assert!(contents.starts_with(&header), "{} has no header", name);
+            assert_eq!(contents.lines().count(), 2, "{}", name);
+        }
+        // The active file keeps its name and holds the latest batch
+        let active = files.iter().find(|(name, _)| name == "syslog.csv").unwrap();
+        assert!(active.1.contains("third"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_file_from_an_earlier_period_is_rotated_out() {
+        let dir = std::env::temp_dir().join(format!("syslog-server-rotate-interval-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("syslog.jsonl");
+        std::fs::write(&path, "{\"syslog\":\"yesterday\"}\n").unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(std::time::SystemTime::now() - Duration::from_secs(2 * 86400)).unwrap();
+
+        let options = OutputOptions { format: OutputFormat::Jsonl, ..Default::default() };
+        let rotation = RotationPolicy { interval: Some(RotateInterval::Daily), ..Default::default() };
+        let mut writer = OutputWriter::open(&path, options, rotation).await.unwrap();
+        writer.write_batch([&SysLogEntry { syslog: "today".to_string(), ..Default::default() }]).await.unwrap();
+        // Still in the same period, so no second rotation
+        writer.write_batch([&SysLogEntry { syslog: "later".to_string(), ..Default::default() }]).await.unwrap();
+        writer.close().await.unwrap();
+
+        let files = read_dir(&dir);
+        assert_eq!(files.len(), 2, "{:?}", files);
+        let (rotated, active) = (&files[0], &files[1]);
+        assert_eq!(active.0, "syslog.jsonl");
+        assert!(active.1.contains("today") && active.1.contains("later") && !active.1.contains("yesterday"));
+        assert!(rotated.0.starts_with("syslog-") && rotated.1.contains("yesterday"), "{:?}", rotated);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rotated_names_count_past_taken_ones() {
+        let dir = std::env::temp_dir().join(format!("syslog-server-rotated-path-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("syslog.csv");
+        let now = Local::now();
+        let stamp = now.format("%Y%m%d-%H%M");
+
+        assert_eq!(rotated_path(&path, now).await, dir.join(format!("syslog-{}.csv", stamp)));
+        // Compressed copies take their name too
+        std::fs::write(dir.join(format!("syslog-{}.csv", stamp)), "").unwrap();
+        std::fs::write(dir.join(format!("syslog-{}-1.csv.gz", stamp)), "").unwrap();
+        assert_eq!(rotated_path(&path, now).await, dir.join(format!("syslog-{}-2.csv", stamp)));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn compressing_replaces_the_rotated_file() {
+        let path = std::env::temp_dir().join(format!("syslog-server-compress-{}.csv", std::process::id()));
+        std::fs::write(&path, "rotated rows\n").unwrap();
+
+        let compressed = compress_file(&path).unwrap();
+        assert_eq!(compressed, gz_path(&path));
+        assert!(!path.exists());
+        let mut decoder = flate2::read::GzDecoder::new(std::fs::File::open(&compressed).unwrap());
+        let mut contents = String::new();
+        io::Read::read_to_string(&mut decoder, &mut contents).unwrap();
+        assert_eq!(contents, "rotated rows\n");
+        std::fs::remove_file(compressed).unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt::Write;
+use std::io::IsTerminal;
+use std::sync::Arc;
+
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::error;
+
+use crate::{severity_name, SysLogEntry};
+
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// Whether console lines should be colored: only when stdout is a terminal,
+/// and `NO_COLOR` isn't set.
+pub fn use_color() -> bool {
+    std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
+
+/// Formats `entry` as one aligned line, without the trailing newline. With
+/// `color`, errors and worse are red, warnings yellow and debug dimmed.
+pub fn format_entry(entry: &SysLogEntry, color: bool) -> String {
+    let mut line = String::new();
+    let style = match entry.severity {
+        0..=3 => RED,
+        4 => YELLOW,
+        7 => DIM,
+        _ => "",
+    };
+    if color {
+        line.push_str(style);
+    }
+    let _ = write!(
+        line,
+        "{} {:<15} {:<9} {:<15} {}",
+        entry.event_time,
+        entry.device_ip,
+        severity_name(entry.severity).unwrap_or("-"),
+        entry.app_name.as_deref().or(entry.host.as_deref()).unwrap_or("-"),
+        entry.syslog
+    );
+    if color && !style.is_empty() {
+        line.push_str(RESET);
+    }
+    line
+}
+
+/// Prints each batch received on `rx` to stdout until every sender is gone.
+pub async fn run_console(mut rx: mpsc::Receiver<Arc<[SysLogEntry]>>, color: bool) {
+    let mut stdout = tokio::io::stdout();
+    while let Some(batch) = rx.recv().await {
+        let mut lines = String::new();
+        for entry in batch.iter() {
+            lines.push_str(&format_entry(entry, color));
+            lines.push('\n');
+        }
+        let printed = async {
+            stdout.write_all(lines.as_bytes()).await?;
+            stdout.flush().await
+        };
+        if let Err(e) = printed.await {
+            error!("Failed to print to the console: {}", e);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn severity_picks_the_color() {
+        let entry = |severity| SysLogEntry {
+            event_time: "2024-01-15T12:00:00Z".to_string(),
+            device_ip: "192.0.2.1".to_string(),
+            severity,
+            app_name: Some("sshd".to_string()),
+            syslog: "hello".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_entry(&entry(3), false),
+            "2024-01-15T12:00:00Z 192.0.2.1       error     sshd            hello"
+        );
+        assert!(format_entry(&entry(3), true).starts_with(RED));
+        assert!(format_entry(&entry(4), true).starts_with(YELLOW));
+        assert_eq!(format_entry(&entry(6), true), format_entry(&entry(6), false));
+    }
+}
@@ -0,0 +1,173 @@
+use std::fmt;
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::SysLogEntry;
+
+/// A field's value, serialized the way the `SysLogEntry` derive would.
+enum Value<'a> {
+    Str(Option<&'a str>),
+    UInt(Option<u64>),
+    Int(Option<i64>),
+    Bool(bool),
+}
+
+impl Serialize for Value<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Value::Str(value) => value.serialize(serializer),
+            Value::UInt(value) => value.serialize(serializer),
+            Value::Int(value) => value.serialize(serializer),
+            Value::Bool(value) => value.serialize(serializer),
+        }
+    }
+}
+
+type Getter = fn(&SysLogEntry) -> Value<'_>;
+
+/// Every `SysLogEntry` field, in declaration order.
+const FIELDS: &[(&str, Getter)] = &[
+    ("event_time", |e| Value::Str(Some(&e.event_time))),
+    ("device_ip", |e| Value::Str(Some(&e.device_ip))),
+    ("syslog", |e| Value::Str(Some(&e.syslog))),
+    ("severity", |e| Value::UInt(Some(e.severity.into()))),
+    ("facility", |e| Value::UInt(Some(e.facility.into()))),
+    ("version", |e| Value::UInt(e.version.map(Into::into))),
+    ("log_timestamp", |e| Value::Str(e.log_timestamp.as_deref())),
+    ("host", |e| Value::Str(e.host.as_deref())),
+    ("app_name", |e| Value::Str(e.app_name.as_deref())),
+    ("procid", |e| Value::Str(e.procid.as_deref())),
+    ("msgid", |e| Value::Str(e.msgid.as_deref())),
+    ("structured_data", |e| Value::Str(e.structured_data.as_deref())),
+    ("repeat_count", |e| Value::UInt(e.repeat_count)),
+    ("listen_port", |e| Value::UInt(e.listen_port.map(Into::into))),
+    ("severity_name", |e| Value::Str(e.severity_name)),
+    ("facility_name", |e| Value::Str(e.facility_name)),
+    ("source_hostname", |e| Value::Str(e.source_hostname.as_deref())),
+    ("clock_skew_secs", |e| Value::Int(e.clock_skew_secs)),
+    ("suspect_time", |e| Value::Bool(e.suspect_time)),
+    ("raw", |e| Value::Str(e.raw.as_deref())),
+    ("parsed_fields", |e| Value::Str(e.parsed_fields.as_deref())),
+    ("msg_hash", |e| Value::Str(e.msg_hash.as_deref())),
+    ("secondary_time", |e| Value::Str(e.secondary_time.as_deref())),
+    ("time_fallback", |e| Value::Bool(e.time_fallback)),
+    ("original_severity", |e| Value::UInt(e.original_severity.map(Into::into))),
+];
+
+/// The fields written with `--fields`, as indexes into `FIELDS`, in the
+/// order given.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldSelection(Vec<usize>);
+
+impl FieldSelection {
+    /// Pairs `entry` with this selection, for serializing just those fields.
+    pub fn select<'a>(&'a self, entry: &'a SysLogEntry) -> Selected<'a> {
+        Selected { entry, fields: self }
+    }
+
+    fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.0.iter().map(|&field| FIELDS[field].0)
+    }
+}
+
+impl fmt::Display for FieldSelection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.names().collect::<Vec<_>>().join(","))
+    }
+}
+
+impl Serialize for FieldSelection {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.names())
+    }
+}
+
+/// Parses a comma-separated `--fields` list such as
+/// `event_time,device_ip,syslog`.
+pub fn parse_fields(list: &str) -> Result<FieldSelection, String> {
+    select_fields(list.split(','))
+}
+
+/// Checks `names` against the `SysLogEntry` fields, rejecting unknown and
+/// repeated ones.
+pub fn select_fields<'a>(names: impl IntoIterator<Item = &'a str>) -> Result<FieldSelection, String> {
+    let mut fields = Vec::new();
+    for name in names {
+        let name = name.trim();
+        let Some(field) = FIELDS.iter().position(|(known, _)| *known == name) else {
+            let known: Vec<&str> = FIELDS.iter().map(|(known, _)| *known).collect();
+            return Err(format!("unknown field `{}`; expected one of {}", name, known.join(", ")));
+        };
+        if fields.contains(&field) {
+            return Err(format!("field `{}` is listed more than once", name));
+        }
+        fields.push(field);
+    }
+    if fields.is_empty() {
+        return Err("expected at least one field".to_string());
+    }
+    Ok(FieldSelection(fields))
+}
+
+/// An entry serialized as a struct holding only the selected fields.
+pub struct Selected<'a> {
+    entry: &'a SysLogEntry,
+    fields: &'a FieldSelection,
+}
+
+impl Serialize for Selected<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut row = serializer.serialize_struct("SysLogEntry", self.fields.0.len())?;
+        for &field in &self.fields.0 {
+            let (name, get) = FIELDS[field];
+            row.serialize_field(name, &get(self.entry))?;
+        }
+        row.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selecting_every_field_matches_the_derive() {
+        let entry = SysLogEntry {
+            event_time: "2024-01-15T12:00:00Z".to_string(),
+            device_ip: "192.0.2.1".to_string(),
+            syslog: "hello".to_string(),
+            severity: 3,
+            facility: 4,
+            version: Some(1),
+            host: Some("host".to_string()),
+            repeat_count: Some(2),
+            listen_port: Some(514),
+            severity_name: Some("error"),
+            clock_skew_secs: Some(-5),
+            suspect_time: true,
+            msg_hash: Some("e219759a0bde6d1e".to_string()),
+            ..Default::default()
+        };
+        let all = select_fields(FIELDS.iter().map(|(name, _)| *name)).unwrap();
+        assert_eq!(
+            serde_json::to_string(&all.select(&entry)).unwrap(),
+            serde_json::to_string(&entry).unwrap()
+        );
+    }
+
+    #[test]
+    fn fields_are_written_in_the_order_given() {
+        let entry = SysLogEntry {
+            device_ip: "192.0.2.1".to_string(),
+            syslog: "hello".to_string(),
+            ..Default::default()
+        };
+        let fields = parse_fields("syslog,device_ip").unwrap();
+        assert_eq!(serde_json::to_string(&fields.select(&entry)).unwrap(), r#"{"syslog":"hello","device_ip":"192.0.2.1"}"#);
+        assert_eq!(fields.to_string(), "syslog,device_ip");
+
+        assert!(parse_fields("syslog,nope").unwrap_err().contains("`nope`"));
+        assert!(parse_fields("syslog,syslog").is_err());
+        assert!(parse_fields("").is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use metrics::increment_counter;
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+/// How often disk usage is checked against the budget.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Keeps the output files and their rotated copies within `max_bytes` until
+/// `shutdown` fires, deleting the oldest rotated copies first. The active
+/// `outputs` count towards the budget but are never deleted.
+pub async fn run_janitor(outputs: Vec<PathBuf>, max_bytes: u64, mut shutdown: watch::Receiver<bool>) {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.wait_for(|&stop| stop) => return,
+        }
+        let outputs = outputs.clone();
+        match tokio::task::spawn_blocking(move || prune(&outputs, max_bytes)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!("Failed to check output disk usage: {}", e),
+            Err(e) => error!("Disk usage check failed: {}", e),
+        }
+    }
+}
+
+fn prune(outputs: &[PathBuf], max_bytes: u64) -> io::Result<()> {
+    let mut total = 0;
+    let mut rotated: Vec<(SystemTime, u64, PathBuf)> = Vec::new();
+    for dir in directories(outputs) {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let path = entry.path();
+            if !metadata.is_file() {
+                continue;
+            }
+            let name = entry.file_name();
+            let is_active = outputs.iter().any(|output| output.file_name() == Some(&name) && parent(output) == dir);
+            if is_active {
+                total += metadata.len();
+            } else if outputs.iter().any(|output| parent(output) == dir && is_rotated_copy(output, &path)) {
+                total += metadata.len();
+                rotated.push((metadata.modified()?, metadata.len(), path));
+            }
+        }
+    }
+
+    rotated.sort();
+    for (_, len, path) in rotated {
+        if total <= max_bytes {
+            return Ok(());
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => {
+                total -= len;
+                increment_counter!("syslog_files_pruned_total");
+                info!("Deleted {} ({} bytes) to stay within the disk budget", path.display(), len);
+            }
+            Err(e) => warn!("Failed to delete {}: {}", path.display(), e),
+        }
+    }
+    if total > max_bytes {
+        warn!("Output files use {} bytes, over the {}-byte budget, with no rotated files left to delete", total, max_bytes);
+    }
+    Ok(())
+}
+
+/// The distinct directories holding `outputs`.
+fn directories(outputs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = outputs.iter().map(|output| parent(output)).collect();
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+fn parent(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Whether `candidate` is a rotated copy of `active` as named by
+/// `rotated_path`, such as `syslog-20240115-1200.csv` or
+/// `syslog-20240115-1200-2.csv.gz` for `syslog.csv`.
+fn is_rotated_copy(active: &Path, candidate: &Path) -> bool {
+    let (Some(stem), Some(name)) = (
+        active.file_stem().and_then(|stem| stem.to_str()),
+        candidate.file_name().and_then(|name| name.to_str()),
+    ) else {
+        return false;
+    };
+    let extension = active
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+    let Some(stamp) = name
+        .strip_prefix(stem)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .and_then(|rest| rest.strip_suffix(extension.as_str()))
+    else {
+        return false;
+    };
+
+    let digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    let mut parts = stamp.split('-');
+    matches!(
+        (parts.next(), parts.next(), parts.next(), parts.next()),
+        (Some(date), Some(time), counter, None)
+            if date.len() == 8 && digits(date) && time.len() == 4 && digits(time) && counter.is_none_or(digits)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotated_copies_are_recognised_by_name() {
+        let active = Path::new("/var/log/syslog.csv");
+        for name in ["syslog-20240115-1200.csv", "syslog-20240115-1200-2.csv", "syslog-20240115-1200.csv.gz"] {
+            assert!(is_rotated_copy(active, &Path::new("/var/log").join(name)), "{}", name);
+        }
+        for name in [
+            "syslog.csv",
+            "syslog-other.csv",
+            "syslog-20240115.csv",
+            "syslog-20240115-1200-x.csv",
+            "syslog-20240115-1200.jsonl",
+            "auth-20240115-1200.csv",
+            "syslogd-20240115-1200.csv",
+        ] {
+            assert!(!is_rotated_copy(active, &Path::new("/var/log").join(name)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn prune_deletes_the_oldest_rotated_copy_first() {
+        let dir = std::env::temp_dir().join(format!("syslog-server-janitor-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let active = dir.join("syslog.csv");
+        let now = SystemTime::now();
+        for (name, age_secs) in [
+            ("syslog.csv", 0),
+            ("syslog-20240115-1200.csv", 7200),
+            ("syslog-20240115-1300.csv.gz", 3600),
+            ("auth-20240115-1100.csv", 10800),
+        ] {
+            let file = std::fs::File::create(dir.join(name)).unwrap();
+            file.set_len(100).unwrap();
+            file.set_modified(now - Duration::from_secs(age_secs)).unwrap();
+        }
+
+        // 300 bytes count towards the budget; the other stem's file doesn't
+        prune(std::slice::from_ref(&active), 250).unwrap();
+        assert!(!dir.join("syslog-20240115-1200.csv").exists());
+        assert!(dir.join("syslog-20240115-1300.csv.gz").exists());
+
+        prune(std::slice::from_ref(&active), 0).unwrap();
+        assert!(!dir.join("syslog-20240115-1300.csv.gz").exists());
+        assert!(active.exists());
+        assert!(dir.join("auth-20240115-1100.csv").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
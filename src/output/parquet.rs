@@ -0,0 +1,168 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use parquet::basic::Compression;
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, Int32Type, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+
+use crate::{SysLogEntry, SyslogError};
+
+enum Value<'a> {
+    Int32(Option<i32>),
+    Int64(Option<i64>),
+    Str(Option<&'a str>),
+    Bool(Option<bool>),
+}
+
+type Getter = fn(&SysLogEntry) -> Value<'_>;
+
+/// One Parquet column per `SysLogEntry` field, in schema order. A column's
+/// physical type follows from the `Value` variant its getter returns.
+const COLUMNS: &[(&str, Getter)] = &[
+    ("event_time", |e| Value::Str(Some(&e.event_time))),
+    ("device_ip", |e| Value::Str(Some(&e.device_ip))),
+    ("syslog", |e| Value::Str(Some(&e.syslog))),
+    ("severity", |e| Value::Int32(Some(e.severity.into()))),
+    ("facility", |e| Value::Int32(Some(e.facility.into()))),
+    ("version", |e| Value::Int32(e.version.map(Into::into))),
+    ("log_timestamp", |e| Value::Str(e.log_timestamp.as_deref())),
+    ("host", |e| Value::Str(e.host.as_deref())),
+    ("app_name", |e| Value::Str(e.app_name.as_deref())),
+    ("procid", |e| Value::Str(e.procid.as_deref())),
+    ("msgid", |e| Value::Str(e.msgid.as_deref())),
+    ("structured_data", |e| Value::Str(e.structured_data.as_deref())),
+    ("repeat_count", |e| Value::Int64(e.repeat_count.map(|n| n as i64))),
+    ("listen_port", |e| Value::Int32(e.listen_port.map(Into::into))),
+    ("severity_name", |e| Value::Str(e.severity_name)),
+    ("facility_name", |e| Value::Str(e.facility_name)),
+    ("source_hostname", |e| Value::Str(e.source_hostname.as_deref())),
+    ("clock_skew_secs", |e| Value::Int64(e.clock_skew_secs)),
+    ("suspect_time", |e| Value::Bool(Some(e.suspect_time))),
+    ("raw", |e| Value::Str(e.raw.as_deref())),
+    ("parsed_fields", |e| Value::Str(e.parsed_fields.as_deref())),
+    ("msg_hash", |e| Value::Str(e.msg_hash.as_deref())),
+    ("secondary_time", |e| Value::Str(e.secondary_time.as_deref())),
+    ("time_fallback", |e| Value::Bool(Some(e.time_fallback))),
+    ("original_severity", |e| Value::Int32(e.original_severity.map(Into::into))),
+];
+
+fn schema() -> String {
+    let empty = SysLogEntry::default();
+    let fields: String = COLUMNS
+        .iter()
+        .map(|(name, value)| match value(&empty) {
+            Value::Int32(_) => format!("OPTIONAL INT32 {};", name),
+            Value::Int64(_) => format!("OPTIONAL INT64 {};", name),
+            Value::Str(_) => format!("OPTIONAL BYTE_ARRAY {} (STRING);", name),
+            Value::Bool(_) => format!("OPTIONAL BOOLEAN {};", name),
+        })
+        .collect();
+    format!("message syslog_entry {{ {} }}", fields)
+}
+
+/// Buffers entries in memory and writes them out as Parquet row groups of
+/// `row_group_size` rows. Row groups are only written on `commit`, so the
+/// rows of a failed batch can still be dropped with `rollback`. The file is only readable once `close` has written
+/// the footer, so the last partial row group must be closed at shutdown.
+pub struct ParquetSink {
+    writer: SerializedFileWriter<File>,
+    rows: Vec<SysLogEntry>,
+    /// Rows buffered as of the last commit.
+    committed_rows: usize,
+    row_group_size: usize,
+}
+
+impl ParquetSink {
+    /// Creates (or truncates) the file at `path`.
+    pub fn create(path: &Path, row_group_size: usize) -> Result<Self, SyslogError> {
+        let schema = Arc::new(parse_message_type(&schema())?);
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer = SerializedFileWriter::new(File::create(path)?, schema, Arc::new(properties))?;
+        Ok(ParquetSink {
+            writer,
+            rows: Vec::with_capacity(row_group_size),
+            committed_rows: 0,
+            row_group_size: row_group_size.max(1),
+        })
+    }
+
+    /// Bytes written to the file so far, not counting buffered rows.
+    pub fn bytes_written(&self) -> u64 {
+        self.writer.bytes_written() as u64
+    }
+
+    pub fn write(&mut self, entry: &SysLogEntry) {
+        self.rows.push(entry.clone());
+    }
+
+    /// Writes out a row group for every `row_group_size` rows buffered,
+    /// keeping the rest for the next one.
+    pub fn commit(&mut self) -> Result<(), SyslogError> {
+        while self.rows.len() >= self.row_group_size {
+            self.write_row_group(self.row_group_size)?;
+        }
+        self.committed_rows = self.rows.len();
+        Ok(())
+    }
+
+    /// Drops the rows written since the last commit.
+    pub fn rollback(&mut self) {
+        self.rows.truncate(self.committed_rows);
+    }
+
+    /// Writes any buffered rows and the file footer.
+    pub fn close(mut self) -> Result<(), SyslogError> {
+        self.write_row_group(self.rows.len())?;
+        self.writer.into_inner()?.sync_all()?;
+        Ok(())
+    }
+
+    /// Writes the first `count` buffered rows as a row group.
+    fn write_row_group(&mut self, count: usize) -> Result<(), SyslogError> {
+        if count == 0 {
+            return Ok(());
+        }
+        let mut row_group = self.writer.next_row_group()?;
+        for (_, value) in COLUMNS {
+            let mut column = row_group.next_column()?.ok_or_else(|| SyslogError::Output("Parquet schema has fewer columns than expected".to_string()))?;
+            let mut levels = Vec::with_capacity(count);
+            let (mut ints, mut longs, mut strings, mut bools) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+            for row in &self.rows[..count] {
+                let present = match value(row) {
+                    Value::Int32(v) => {
+                        ints.extend(v);
+                        v.is_some()
+                    }
+                    Value::Int64(v) => {
+                        longs.extend(v);
+                        v.is_some()
+                    }
+                    Value::Str(v) => {
+                        strings.extend(v.map(ByteArray::from));
+                        v.is_some()
+                    }
+                    Value::Bool(v) => {
+                        bools.extend(v);
+                        v.is_some()
+                    }
+                };
+                levels.push(present as i16);
+            }
+            match value(&SysLogEntry::default()) {
+                Value::Int32(_) => column.typed::<Int32Type>().write_batch(&ints, Some(&levels), None)?,
+                Value::Int64(_) => column.typed::<Int64Type>().write_batch(&longs, Some(&levels), None)?,
+                Value::Str(_) => column.typed::<ByteArrayType>().write_batch(&strings, Some(&levels), None)?,
+                Value::Bool(_) => column.typed::<BoolType>().write_batch(&bools, Some(&levels), None)?,
+            };
+            column.close()?;
+        }
+        row_group.close()?;
+        self.rows.drain(..count);
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use std::fmt;
+
+/// An RFC5424 SD-PARAM given its own CSV column with `--sd-column`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SdColumn {
+    pub sd_id: String,
+    pub param: String,
+    pub column: String,
+}
+
+impl fmt::Display for SdColumn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}={}", self.sd_id, self.param, self.column)
+    }
+}
+
+/// Parses `SD-ID.PARAM=COLUMN`, e.g. `exampleSDID@32473.iut=iut_col`. The
+/// SD-ID may itself contain dots, so the param name follows the last one.
+pub fn parse_sd_column(mapping: &str) -> Result<SdColumn, String> {
+    let (param, column) = mapping
+        .split_once('=')
+        .ok_or_else(|| format!("expected SD-ID.PARAM=COLUMN, got `{}`", mapping))?;
+    let (sd_id, param) = param
+        .rsplit_once('.')
+        .ok_or_else(|| format!("expected SD-ID.PARAM=COLUMN, got `{}`", mapping))?;
+    if sd_id.is_empty() || param.is_empty() || column.is_empty() {
+        return Err(format!("expected SD-ID.PARAM=COLUMN, got `{}`", mapping));
+    }
+    Ok(SdColumn {
+        sd_id: sd_id.to_string(),
+        param: param.to_string(),
+        column: column.to_string(),
+    })
+}
+
+/// One `[SD-ID name="value" ...]` element, with each param's text kept as
+/// received so unmapped ones can be written back unchanged.
+struct Element<'a> {
+    sd_id: &'a str,
+    /// (name, escaped value, the whole `name="value"`)
+    params: Vec<(&'a str, &'a str, &'a str)>,
+}
+
+/// Moves the params named by `columns` out of `structured_data`. Returns the
+/// structured data left over, `None` once nothing is, and each column's
+/// unescaped value in the order of `columns`. Structured data that doesn't
+/// parse is left whole, with every column empty.
+pub fn split(structured_data: Option<&str>, columns: &[SdColumn]) -> (Option<String>, Vec<Option<String>>) {
+    let mut values = vec![None; columns.len()];
+    let Some(data) = structured_data else {
+        return (None, values);
+    };
+    let Some(elements) = parse_elements(data) else {
+        return (Some(data.to_string()), values);
+    };
+
+    let mut rest = String::new();
+    for element in elements {
+        let mut kept = Vec::new();
+        for &(name, value, param) in &element.params {
+            let column = columns.iter().position(|c| c.sd_id == element.sd_id && c.param == name);
+            match column {
+                Some(column) => values[column] = Some(unescape(value)),
+                None => kept.push(param),
+            }
+        }
+        // An element whose params were all moved out is dropped with them.
+        if kept.is_empty() && !element.params.is_empty() {
+            continue;
+        }
+        rest.push('[');
+        rest.push_str(element.sd_id);
+        for param in kept {
+            rest.push(' ');
+            rest.push_str(param);
+        }
+        rest.push(']');
+    }
+    ((!rest.is_empty()).then_some(rest), values)
+}
+
+fn parse_elements(data: &str) -> Option<Vec<Element<'_>>> {
+    let mut elements = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        rest = rest.strip_prefix('[')?;
+        let id_end = rest.find([' ', ']'])?;
+        let sd_id = &rest[..id_end];
+        rest = &rest[id_end..];
+        let mut params = Vec::new();
+        while let Some(after_space) = rest.strip_prefix(' ') {
+            let (name, after_name) = after_space.split_once("=\"")?;
+            let value_end = closing_quote(after_name)?;
+            let value = &after_name[..value_end];
+            let param_len = name.len() + 2 + value_end + 1;
+            params.push((name, value, &after_space[..param_len]));
+            rest = &after_space[param_len..];
+        }
+        rest = rest.strip_prefix(']')?;
+        elements.push(Element { sd_id, params });
+    }
+    Some(elements)
+}
+
+/// Byte offset of the `"` that ends a PARAM-VALUE, skipping escaped ones.
+fn closing_quote(value: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, b) in value.bytes().enumerate() {
+        match b {
+            _ if escaped => escaped = false,
+            b'\\' => escaped = true,
+            b'"' => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Undoes the `\"`, `\\` and `\]` escapes RFC5424 requires in a PARAM-VALUE;
+/// a backslash before anything else is kept, as the RFC says.
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(escaped @ ('"' | '\\' | ']')) => unescaped.push(escaped),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapped_params_move_to_their_columns() {
+        let columns = [
+            parse_sd_column("exampleSDID@32473.iut=iut").unwrap(),
+            parse_sd_column("exampleSDID@32473.eventSource=source").unwrap(),
+            parse_sd_column("origin.ip=origin_ip").unwrap(),
+        ];
+        let data = r#"[exampleSDID@32473 iut="3" eventSource="App \"X\" \\ [1\]" eventID="1011"][examplePriority@32473 class="high"]"#;
+
+        let (rest, values) = split(Some(data), &columns);
+        assert_eq!(rest.as_deref(), Some(r#"[exampleSDID@32473 eventID="1011"][examplePriority@32473 class="high"]"#));
+        assert_eq!(values, [Some("3".to_string()), Some(r#"App "X" \ [1]"#.to_string()), None]);
+
+        let (rest, values) = split(Some(r#"[origin ip="192.0.2.1"]"#), &columns);
+        assert_eq!(rest, None);
+        assert_eq!(values, [None, None, Some("192.0.2.1".to_string())]);
+    }
+
+    #[test]
+    fn unparseable_structured_data_is_left_whole() {
+        let columns = [parse_sd_column("a@1.x=x").unwrap()];
+        assert_eq!(split(Some(r#"[a@1 x="open]"#), &columns), (Some(r#"[a@1 x="open]"#.to_string()), vec![None]));
+        assert_eq!(split(None, &columns), (None, vec![None]));
+
+        assert_eq!(parse_sd_column("a.b.c=d").unwrap().sd_id, "a.b");
+        assert!(parse_sd_column("a.b").is_err());
+        assert!(parse_sd_column("ab=c").is_err());
+    }
+}
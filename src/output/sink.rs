@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use clap::ValueEnum;
+use metrics::counter;
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+use super::{OutputFormat, OutputWriter};
+use crate::SysLogEntry;
+
+/// Batches a sink may fall behind by before further ones are dropped.
+pub const SINK_BACKLOG_BATCHES: usize = 64;
+
+/// An extra output given as `FORMAT:PATH` with `--sink`.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct SinkSpec {
+    pub format: OutputFormat,
+    pub path: PathBuf,
+}
+
+/// Parses a `FORMAT:PATH` sink, e.g. `jsonl:/mnt/ship/syslog.jsonl`.
+pub fn parse_sink(sink: &str) -> Result<SinkSpec, String> {
+    let (format, path) = sink
+        .split_once(':')
+        .ok_or_else(|| format!("expected FORMAT:PATH, got `{}`", sink))?;
+    if path.is_empty() {
+        return Err(format!("missing path for {} sink", format));
+    }
+    Ok(SinkSpec {
+        format: OutputFormat::from_str(format, true).map_err(|_| format!("unknown output format `{}`", format))?,
+        path: PathBuf::from(path),
+    })
+}
+
+/// The processing side of a sink: hands batches to its writer task without
+/// ever waiting on it.
+pub struct SinkSender {
+    label: String,
+    tx: mpsc::Sender<Arc<[SysLogEntry]>>,
+}
+
+impl SinkSender {
+    /// Queues `batch` for the sink. If the sink has fallen
+    /// `SINK_BACKLOG_BATCHES` behind, the batch is dropped and counted in
+    /// `syslog_sink_dropped_total`.
+    pub fn send(&self, batch: Arc<[SysLogEntry]>) {
+        let count = batch.len() as u64;
+        if self.tx.try_send(batch).is_err() {
+            counter!("syslog_sink_dropped_total", count, "sink" => self.label.clone());
+        }
+    }
+}
+
+/// Spawns the task that writes everything sent to the returned sender to
+/// `writer`, finishing the file once every sender is gone.
+pub fn spawn_sink(writer: OutputWriter) -> (SinkSender, tokio::task::JoinHandle<()>) {
+    let (tx, rx) = mpsc::channel(SINK_BACKLOG_BATCHES);
+    let label = writer.path().display().to_string();
+    (SinkSender { label, tx }, tokio::spawn(run_sink(writer, rx)))
+}
+
+/// Spawns the task that prints everything sent to the returned sender to
+/// stdout for `--console`. Like any sink, it drops batches rather than hold
+/// up the pipeline when the terminal can't keep up.
+pub fn spawn_console(color: bool) -> (SinkSender, tokio::task::JoinHandle<()>) {
+    let (tx, rx) = mpsc::channel(SINK_BACKLOG_BATCHES);
+    let label = "stdout".to_string();
+    (SinkSender { label, tx }, tokio::spawn(super::console::run_console(rx, color)))
+}
+
+/// Spawns the task that pushes everything sent to the returned sender to
+/// Loki for `--loki-url`.
+pub fn spawn_loki(loki: crate::loki::LokiSink) -> (SinkSender, tokio::task::JoinHandle<()>) {
+    let (tx, rx) = mpsc::channel(SINK_BACKLOG_BATCHES);
+    let label = "loki".to_string();
+    (SinkSender { label, tx }, tokio::spawn(loki.run(rx)))
+}
+
+/// Writes and flushes each batch received on `rx`. A batch that fails is
+/// counted in `syslog_sink_errors_total` and given up on, so one broken sink
+/// never holds up the rest of the pipeline.
+async fn run_sink(mut writer: OutputWriter, mut rx: mpsc::Receiver<Arc<[SysLogEntry]>>) {
+    let label = writer.path().display().to_string();
+    info!("Writing sink {}", label);
+    while let Some(batch) = rx.recv().await {
+        if let Err(e) = writer.write_batch(batch.iter()).await {
+            counter!("syslog_sink_errors_total", batch.len() as u64, "sink" => label.clone());
+            e.count();
+            error!("Error writing {} entries to sink {}: {}", batch.len(), label, e);
+        }
+    }
+    if let Err(e) = writer.close().await {
+        error!("Failed to close sink {}: {}", label, e);
+    }
+}
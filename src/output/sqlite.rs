@@ -0,0 +1,137 @@
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection};
+
+use crate::{SysLogEntry, SyslogError};
+
+/// One column per `SysLogEntry` field, in struct order, plus indexes for the
+/// usual lookups by source, level and time.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS syslog (
+        event_time TEXT NOT NULL,
+        device_ip TEXT NOT NULL,
+        syslog TEXT NOT NULL,
+        severity INTEGER NOT NULL,
+        facility INTEGER NOT NULL,
+        version INTEGER,
+        log_timestamp TEXT,
+        host TEXT,
+        app_name TEXT,
+        procid TEXT,
+        msgid TEXT,
+        structured_data TEXT,
+        repeat_count INTEGER,
+        listen_port INTEGER,
+        severity_name TEXT,
+        facility_name TEXT,
+        source_hostname TEXT,
+        clock_skew_secs INTEGER,
+        suspect_time INTEGER NOT NULL,
+        raw TEXT,
+        parsed_fields TEXT,
+        msg_hash TEXT,
+        secondary_time TEXT,
+        time_fallback INTEGER NOT NULL,
+        original_severity INTEGER
+    );
+    CREATE INDEX IF NOT EXISTS syslog_device_ip ON syslog (device_ip);
+    CREATE INDEX IF NOT EXISTS syslog_severity ON syslog (severity);
+    CREATE INDEX IF NOT EXISTS syslog_event_time ON syslog (event_time);
+";
+
+const INSERT: &str = "
+    INSERT INTO syslog (
+        event_time, device_ip, syslog, severity, facility, version, log_timestamp, host, app_name,
+        procid, msgid, structured_data, repeat_count, listen_port, severity_name, facility_name,
+        source_hostname, clock_skew_secs, suspect_time, raw, parsed_fields, msg_hash,
+        secondary_time, time_fallback, original_severity
+    ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25)
+";
+
+/// Inserts entries into the `syslog` table of a SQLite database, creating
+/// the table if it doesn't exist. Rows are inserted inside a transaction
+/// that `commit` ends, so each written batch costs one fsync rather than
+/// one per row.
+pub struct SqliteSink {
+    connection: Connection,
+    path: PathBuf,
+    in_transaction: bool,
+}
+
+impl SqliteSink {
+    /// Opens the database at `path`, appending to any existing table.
+    pub fn open(path: &Path) -> Result<Self, SyslogError> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(SqliteSink {
+            connection,
+            path: path.to_path_buf(),
+            in_transaction: false,
+        })
+    }
+
+    /// Size of the database file as of the last commit.
+    pub fn bytes_written(&self) -> u64 {
+        std::fs::metadata(&self.path).map_or(0, |m| m.len())
+    }
+
+    pub fn write(&mut self, entry: &SysLogEntry) -> Result<(), SyslogError> {
+        if !self.in_transaction {
+            self.connection.execute_batch("BEGIN")?;
+            self.in_transaction = true;
+        }
+        self.connection.prepare_cached(INSERT)?.execute(params![
+            entry.event_time,
+            entry.device_ip,
+            entry.syslog,
+            entry.severity,
+            entry.facility,
+            entry.version,
+            entry.log_timestamp,
+            entry.host,
+            entry.app_name,
+            entry.procid,
+            entry.msgid,
+            entry.structured_data,
+            entry.repeat_count.map(|n| n as i64),
+            entry.listen_port,
+            entry.severity_name,
+            entry.facility_name,
+            entry.source_hostname,
+            entry.clock_skew_secs,
+            entry.suspect_time,
+            entry.raw,
+            entry.parsed_fields,
+            entry.msg_hash,
+            entry.secondary_time,
+            entry.time_fallback,
+            entry.original_severity,
+        ])?;
+        Ok(())
+    }
+
+    /// Commits the rows written since the last commit, if any.
+    pub fn commit(&mut self) -> Result<(), SyslogError> {
+        if self.in_transaction {
+            self.connection.execute_batch("COMMIT")?;
+            self.in_transaction = false;
+        }
+        Ok(())
+    }
+
+    /// Abandons the rows written since the last commit.
+    pub fn rollback(&mut self) -> Result<(), SyslogError> {
+        if self.in_transaction {
+            self.connection.execute_batch("ROLLBACK")?;
+            self.in_transaction = false;
+        }
+        Ok(())
+    }
+
+    /// Commits any open transaction and closes the database.
+    pub fn close(mut self) -> Result<(), SyslogError> {
+        self.commit()?;
+        self.connection.close().map_err(|(_, e)| e)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+use serde_json::Value;
+
+use crate::{SysLogEntry, SyslogError};
+
+#[derive(Debug)]
+enum Segment {
+    Literal(String),
+    Field(String),
+}
+
+/// A line format such as `{event_time} {device_ip} [{severity}] {syslog}`.
+/// Placeholders name `SysLogEntry` fields, and `{{` and `}}` are literal
+/// braces. Empty fields render as nothing.
+#[derive(Debug)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Parses `template`, rejecting unbalanced braces and unknown fields.
+    pub fn parse(template: &str) -> Result<Self, SyslogError> {
+        let Value::Object(known) = serde_json::to_value(SysLogEntry::default())? else {
+            unreachable!("entries serialize as objects");
+        };
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(SyslogError::Config(format!("Unclosed `{{{}` in template", name))),
+                        }
+                    }
+                    if !known.contains_key(&name) {
+                        let fields: Vec<&str> = known.keys().map(String::as_str).collect();
+                        return Err(SyslogError::Config(format!("Unknown template field `{{{}}}`; expected one of {}", name, fields.join(", "))));
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field(name));
+                }
+                '}' => return Err(SyslogError::Config("Unmatched `}` in template; write `}}` for a literal brace".to_string())),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Template { segments })
+    }
+
+    /// Formats `entry` as one line, without the trailing newline.
+    pub fn render(&self, entry: &SysLogEntry) -> Result<String, SyslogError> {
+        let Value::Object(fields) = serde_json::to_value(entry)? else {
+            unreachable!("entries serialize as objects");
+        };
+        let mut line = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => line.push_str(text),
+                Segment::Field(name) => match &fields[name] {
+                    Value::Null => {}
+                    Value::String(text) => line.push_str(text),
+                    value => line.push_str(&value.to_string()),
+                },
+            }
+        }
+        Ok(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_and_escaped_braces_are_rendered() {
+        let template = Template::parse("{event_time} {device_ip} [{severity}] {{{app_name}}} {host}: {syslog}").unwrap();
+        let entry = SysLogEntry {
+            event_time: "2024-01-15 12:00:00.000".to_string(),
+            device_ip: "10.0.0.1".to_string(),
+            syslog: "disk full".to_string(),
+            severity: 2,
+            app_name: Some("kernel".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(template.render(&entry).unwrap(), "2024-01-15 12:00:00.000 10.0.0.1 [2] {kernel} : disk full");
+    }
+
+    #[test]
+    fn bad_templates_are_rejected() {
+        for template in ["{sev}", "{syslog", "a } b", "{}"] {
+            assert!(matches!(Template::parse(template), Err(SyslogError::Config(_))), "{:?} should be rejected", template);
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, Local, NaiveDateTime, TimeZone};
+use metrics::increment_counter;
+
+use crate::{SysLogEntry, SyslogError, TimestampFormat, MALFORMED_PRIORITY};
+
+/// Largest valid PRI value: facility 23 (local7), severity 7 (debug).
+const MAX_PRIORITY: u8 = 191;
+
+/// The header fields and message body of a syslog frame. Fields the sender
+/// didn't provide (or sent as the RFC5424 NILVALUE `-`) are `None`; `version`
+/// is only set for RFC5424 frames.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct ParsedMessage {
+    version: Option<u8>,
+    timestamp: Option<String>,
+    /// `timestamp` as a point in time, when it could be read as one.
+    sent_at: Option<DateTime<Local>>,
+    hostname: Option<String>,
+    app_name: Option<String>,
+    procid: Option<String>,
+    msgid: Option<String>,
+    structured_data: Option<String>,
+    message: String,
+}
+
+/// Parses a raw message received from `received_ip` at `received_at` into an
+/// entry. RFC5424 is tried first, then RFC3164, and each match is counted
+/// in `syslog_rfc5424_total` or `syslog_rfc3164_total`. Senders that match
+/// neither keep the whole line as the message.
+///
+/// A missing or invalid PRI gives an entry with facility and severity set to
+/// `MALFORMED_PRIORITY` holding the raw line. Fields that depend on where the
+/// message arrived or on the server's settings, like `listen_port` and the
+/// level names, are left for the caller, as is trimming the message.
+pub fn parse(raw: &str, received_ip: &str, received_at: DateTime<Local>, timestamp_format: TimestampFormat) -> SysLogEntry {
+    let (facility, severity, parsed) = match parse_priority(raw) {
+        Ok((facility, severity)) => {
+            let parsed = if let Ok(parsed) = parse_rfc5424(raw) {
+                increment_counter!("syslog_rfc5424_total");
+                parsed
+            } else if let Ok(parsed) = parse_rfc3164(raw, received_at) {
+                increment_counter!("syslog_rfc3164_total");
+                parsed
+            } else {
+                ParsedMessage {
+                    message: raw.to_string(),
+                    ..Default::default()
+                }
+            };
+            (facility, severity, parsed)
+        }
+        Err(_) => {
+            let raw = ParsedMessage {
+                message: raw.to_string(),
+                ..Default::default()
+            };
+            (MALFORMED_PRIORITY, MALFORMED_PRIORITY, raw)
+        }
+    };
+
+    SysLogEntry {
+        event_time: timestamp_format.format(received_at),
+        device_ip: received_ip.to_string(),
+        syslog: parsed.message,
+        severity,
+        facility,
+        version: parsed.version,
+        log_timestamp: parsed.timestamp,
+        host: parsed.hostname,
+        app_name: parsed.app_name,
+        procid: parsed.procid,
+        msgid: parsed.msgid,
+        structured_data: parsed.structured_data,
+        clock_skew_secs: parsed.sent_at.map(|sent_at| (sent_at - received_at).num_seconds()),
+        secondary_time: parsed.sent_at.map(|sent_at| timestamp_format.format(sent_at)),
+        ..Default::default()
+    }
+}
+
+/// Parses the `<PRI>` header that must open every message into
+/// `(facility, severity)`. PRI is one to three digits in the range 0-191.
+pub fn parse_priority(raw: &str) -> Result<(u8, u8), SyslogError> {
+    let rest = raw.strip_prefix('<').ok_or_else(|| SyslogError::MalformedPriority("No priority found".to_string()))?;
+    let pri_end = rest.find('>').ok_or_else(|| SyslogError::MalformedPriority("Malformed priority".to_string()))?;
+    let digits = &rest[..pri_end];
+    if digits.is_empty() || digits.len() > 3 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(SyslogError::MalformedPriority(format!("Malformed priority <{}>", digits)));
+    }
+    let priority: u8 = digits
+        .parse()
+        .ok()
+        .filter(|priority| *priority <= MAX_PRIORITY)
+        .ok_or_else(|| SyslogError::MalformedPriority(format!("Priority <{}> out of range", digits)))?;
+    Ok((priority >> 3, priority & 0x7))
+}
+
+fn parse_rfc5424(raw: &str) -> Result<ParsedMessage, SyslogError> {
+    let pri_end = raw.find('>').ok_or_else(|| malformed_header("Malformed priority"))?;
+    let rest = &raw[pri_end + 1..];
+
+    let (version, rest) = rest.split_once(' ').ok_or_else(|| malformed_header("Not an RFC5424 message"))?;
+    if version.is_empty() || !version.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(malformed_header("Not an RFC5424 message"));
+    }
+    let version: u8 = version.parse().map_err(|_| malformed_header("RFC5424 version out of range"))?;
+
+    let mut fields = rest.splitn(6, ' ');
+    let mut next_field = || -> Result<Option<String>, SyslogError> {
+        let field = fields.next().ok_or_else(|| malformed_header("Truncated RFC5424 header"))?;
+        Ok((field != "-").then(|| field.to_string()))
+    };
+    let timestamp = next_field()?;
+    let hostname = next_field()?;
+    let app_name = next_field()?;
+    let procid = next_field()?;
+    let msgid = next_field()?;
+    let rest = fields.next().unwrap_or("");
+
+    let (structured_data, message) = split_structured_data(rest)?;
+    // MSG may be prefixed with a UTF-8 byte order mark.
+    let message = message.trim_start_matches('\u{feff}').to_string();
+
+    let sent_at = timestamp
+        .as_deref()
+        .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+        .map(|timestamp| timestamp.with_timezone(&Local));
+    Ok(ParsedMessage {
+        version: Some(version),
+        timestamp,
+        sent_at,
+        hostname,
+        app_name,
+        procid,
+        msgid,
+        structured_data,
+        message,
+    })
+}
+
+/// Parses a BSD-style `<PRI>Mmm dd hh:mm:ss HOSTNAME MSG` frame. The
+/// timestamp carries no year, so the one of `received_at` is assumed unless
+/// that would put the message in the future (e.g. December logs read in
+/// January).
+fn parse_rfc3164(raw: &str, received_at: DateTime<Local>) -> Result<ParsedMessage, SyslogError> {
+    let pri_end = raw.find('>').ok_or_else(|| malformed_header("Malformed priority"))?;
+    let rest = &raw[pri_end + 1..];
+
+    // Single-digit days are space padded ("Oct  1"), so the timestamp is
+    // always exactly 15 characters.
+    let raw_timestamp = rest.get(..15).ok_or_else(|| malformed_header("Missing RFC3164 timestamp"))?;
+    let now = received_at.naive_local();
+    let parse_with_year = |year: i32| {
+        NaiveDateTime::parse_from_str(&format!("{} {}", year, raw_timestamp), "%Y %b %e %H:%M:%S")
+            .map_err(|e| SyslogError::MalformedHeader(format!("Invalid RFC3164 timestamp: {}", e)))
+    };
+    let mut timestamp = parse_with_year(now.year())?;
+    if timestamp > now + ChronoDuration::days(1) {
+        timestamp = parse_with_year(now.year() - 1)?;
+    }
+
+    let rest = rest[15..].strip_prefix(' ').ok_or_else(|| malformed_header("Missing RFC3164 hostname"))?;
+    let (hostname, message) = rest.split_once(' ').unwrap_or((rest, ""));
+    if hostname.is_empty() {
+        return Err(malformed_header("Missing RFC3164 hostname"));
+    }
+
+    let (app_name, procid, message) = split_tag(message);
+    Ok(ParsedMessage {
+        timestamp: Some(timestamp.format("%Y-%m-%d %H:%M:%S").to_string()),
+        // Without an offset, the sender is assumed to share our time zone.
+        sent_at: Local.from_local_datetime(&timestamp).earliest(),
+        hostname: Some(hostname.to_string()),
+        app_name,
+        procid,
+        message: message.to_string(),
+        ..Default::default()
+    })
+}
+
+/// Splits a `program[pid]: ` or `program: ` tag off the front of an
+/// RFC3164 message. Without a PID the tag has to end in a colon followed
+/// by a space, so ordinary text (or `http://...`) is never taken for one.
+fn split_tag(message: &str) -> (Option<String>, Option<String>, &str) {
+    let untagged = (None, None, message);
+    let name_end = match message.find([' ', '[', ':']) {
+        Some(end) if end > 0 => end,
+        _ => return untagged,
+    };
+    let (name, rest) = message.split_at(name_end);
+
+    let (procid, rest) = match rest.strip_prefix('[') {
+        Some(bracketed) => match bracketed.split_once(']') {
+            Some((pid, rest)) if !pid.is_empty() && !pid.contains(' ') => (Some(pid.to_string()), rest),
+            _ => return untagged,
+        },
+        None => (None, rest),
+    };
+    let rest = match rest.strip_prefix(':') {
+        Some(rest) if rest.is_empty() || rest.starts_with(' ') => rest,
+        // `program[pid] message` is still clearly tagged.
+        _ if procid.is_some() => rest,
+        _ => return untagged,
+    };
+    (Some(name.to_string()), procid, rest.trim_start())
+}
+
+/// Splits the STRUCTURED-DATA part off the front of `data`, honouring
+/// quoted param values and the `\]`, `\"` and `\\` escapes.
+fn split_structured_data(data: &str) -> Result<(Option<String>, &str), SyslogError> {
+    if let Some(message) = data.strip_prefix('-') {
+        return Ok((None, message.strip_prefix(' ').unwrap_or(message)));
+    }
+
+    let bytes = data.as_bytes();
+    let mut end = 0;
+    while bytes.get(end) == Some(&b'[') {
+        let mut in_quotes = false;
+        let mut escaped = false;
+        let mut closed = false;
+        end += 1;
+        while let Some(&b) = bytes.get(end) {
+            end += 1;
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' if in_quotes => escaped = true,
+                b'"' => in_quotes = !in_quotes,
+                b']' if !in_quotes => {
+                    closed = true;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        if !closed {
+            return Err(malformed_header("Unterminated structured data element"));
+        }
+    }
+    if end == 0 {
+        return Err(malformed_header("Missing structured data"));
+    }
+
+    let message = &data[end..];
+    Ok((
+        Some(data[..end].to_string()),
+        message.strip_prefix(' ').unwrap_or(message),
+    ))
+}
+
+fn malformed_header(reason: &str) -> SyslogError {
+    SyslogError::MalformedHeader(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn received_at() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap()
+    }
+
+    fn parse_at(raw: &str) -> SysLogEntry {
+        parse(raw, "192.0.2.1", received_at(), TimestampFormat::Legacy)
+    }
+
+    #[test]
+    fn parse_priority_validates_pri() {
+        assert_eq!(parse_priority("<13>msg").unwrap(), (1, 5));
+        assert_eq!(parse_priority("<0>msg").unwrap(), (0, 0));
+        assert_eq!(parse_priority("<191>msg").unwrap(), (23, 7));
+        for raw in ["<192>msg", "<999>msg", "<>msg", "13 msg", "<13 msg", " <13>msg", "foo"] {
+            assert!(matches!(parse_priority(raw), Err(SyslogError::MalformedPriority(_))), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn rfc5424_header_fields_are_split_out() {
+        let entry = parse_at(
+            "<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 \
+             [exampleSDID@32473 iut=\"3\" eventSource=\"Application\"] An application event",
+        );
+        assert_eq!((entry.facility, entry.severity), (20, 5));
+        assert_eq!(entry.version, Some(1));
+        assert_eq!(entry.log_timestamp.as_deref(), Some("2003-10-11T22:14:15.003Z"));
+        assert_eq!(entry.host.as_deref(), Some("mymachine.example.com"));
+        assert_eq!(entry.app_name.as_deref(), Some("evntslog"));
+        assert_eq!(entry.procid, None);
+        assert_eq!(entry.msgid.as_deref(), Some("ID47"));
+        assert_eq!(
+            entry.structured_data.as_deref(),
+            Some("[exampleSDID@32473 iut=\"3\" eventSource=\"Application\"]")
+        );
+        assert_eq!(entry.syslog, "An application event");
+        assert_eq!(entry.device_ip, "192.0.2.1");
+        assert_eq!(entry.event_time, "2024-03-01 12:00:00.000");
+    }
+
+    #[test]
+    fn rfc5424_structured_data_honours_escapes() {
+        let entry = parse_at(r#"<14>1 - - - - - [a@1 x="q\]\"uote"][b@2] body"#);
+        assert_eq!(entry.structured_data.as_deref(), Some(r#"[a@1 x="q\]\"uote"][b@2]"#));
+        assert_eq!(entry.syslog, "body");
+
+        let entry = parse_at("<14>1 - - - - - - \u{feff}bom");
+        assert_eq!(entry.structured_data, None);
+        assert_eq!(entry.syslog, "bom");
+    }
+
+    #[test]
+    fn rfc3164_timestamp_host_and_tag_are_parsed() {
+        let entry = parse_at("<38>Feb  5 22:14:15 myhost sshd[4321]: Accepted publickey");
+        assert_eq!(entry.version, None);
+        assert_eq!(entry.log_timestamp.as_deref(), Some("2024-02-05 22:14:15"));
+        assert_eq!(entry.host.as_deref(), Some("myhost"));
+        assert_eq!(entry.app_name.as_deref(), Some("sshd"));
+        assert_eq!(entry.procid.as_deref(), Some("4321"));
+        assert_eq!(entry.syslog, "Accepted publickey");
+    }
+
+    #[test]
+    fn rfc3164_timestamp_in_the_future_is_last_year() {
+        let entry = parse_at("<13>Dec 31 23:59:59 host late message");
+        assert_eq!(entry.log_timestamp.as_deref(), Some("2023-12-31 23:59:59"));
+    }
+
+    #[test]
+    fn split_tag_only_takes_real_tags() {
+        assert_eq!(split_tag("cron: run"), (Some("cron".to_string()), None, "run"));
+        assert_eq!(split_tag("kernel[0] boot"), (Some("kernel".to_string()), Some("0".to_string()), "boot"));
+        assert_eq!(split_tag("see http://example.com"), (None, None, "see http://example.com"));
+        assert_eq!(split_tag("http://example.com down"), (None, None, "http://example.com down"));
+    }
+
+    #[test]
+    fn unrecognised_frames_keep_the_whole_line() {
+        let entry = parse_at("<13>just some text\n");
+        assert_eq!((entry.facility, entry.severity), (1, 5));
+        assert_eq!(entry.log_timestamp, None);
+        assert_eq!(entry.syslog, "<13>just some text\n");
+    }
+
+    #[test]
+    fn malformed_priority_gives_the_sentinel() {
+        let entry = parse_at("no pri here");
+        assert_eq!((entry.facility, entry.severity), (MALFORMED_PRIORITY, MALFORMED_PRIORITY));
+        assert_eq!(entry.syslog, "no pri here");
+    }
+
+    #[test]
+    fn clock_skew_is_measured_from_the_embedded_timestamp() {
+        let ahead = (received_at() + ChronoDuration::hours(1)).to_rfc3339();
+        let entry = parse_at(&format!("<13>1 {} host app - - - late", ahead));
+        assert_eq!(entry.clock_skew_secs, Some(3600));
+
+        let entry = parse_at("<13>Mar  1 11:58:00 host app: early");
+        assert_eq!(entry.clock_skew_secs, Some(-120));
+
+        assert_eq!(parse_at("<13>1 - host app - - - none").clock_skew_secs, None);
+        assert_eq!(parse_at("<13>just some text").clock_skew_secs, None);
+    }
+}
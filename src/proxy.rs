@@ -0,0 +1,93 @@
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::SyslogError;
+
+/// Opening bytes of a binary (v2) PROXY header.
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Longest possible text (v1) header, including the trailing CRLF.
+const V1_MAX_LEN: usize = 107;
+
+/// Reads a PROXY protocol v1 or v2 header from the front of `stream` and
+/// returns the original client address. `None` means the proxy opened the
+/// connection itself (v2 `LOCAL`, v1 `UNKNOWN`, or a non-IP family), so the
+/// peer address should be kept.
+///
+/// Exactly the header is consumed, so whatever follows is left for the
+/// syslog (or TLS) layer.
+pub async fn read_header<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Option<SocketAddr>, SyslogError> {
+    let mut start = [0; 12];
+    stream.read_exact(&mut start).await?;
+    if start == V2_SIGNATURE {
+        read_v2(stream).await
+    } else if start.starts_with(b"PROXY ") {
+        read_v1(stream, &start).await
+    } else {
+        Err(malformed("missing PROXY header"))
+    }
+}
+
+async fn read_v1<S: AsyncRead + Unpin>(stream: &mut S, start: &[u8]) -> Result<Option<SocketAddr>, SyslogError> {
+    // Read byte by byte: anything past the CRLF belongs to the next layer.
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LEN {
+            return Err(malformed("PROXY v1 header is too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+    let line = std::str::from_utf8(&line[..line.len() - 2]).map_err(malformed)?;
+
+    let mut fields = line.split(' ').skip(1);
+    match fields.next() {
+        Some("UNKNOWN") => return Ok(None),
+        Some("TCP4" | "TCP6") => {}
+        _ => return Err(malformed(format!("unsupported PROXY v1 header `{}`", line))),
+    }
+    let (Some(source), Some(_), Some(port), Some(_), None) =
+        (fields.next(), fields.next(), fields.next(), fields.next(), fields.next())
+    else {
+        return Err(malformed(format!("malformed PROXY v1 header `{}`", line)));
+    };
+    let source: IpAddr = source.parse().map_err(malformed)?;
+    Ok(Some(SocketAddr::new(source, port.parse().map_err(malformed)?)))
+}
+
+async fn read_v2<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Option<SocketAddr>, SyslogError> {
+    let version_command = stream.read_u8().await?;
+    let family = stream.read_u8().await?;
+    let len = stream.read_u16().await?;
+    let mut addresses = vec![0; usize::from(len)];
+    stream.read_exact(&mut addresses).await?;
+
+    if version_command >> 4 != 2 {
+        return Err(malformed(format!("unsupported PROXY version {}", version_command >> 4)));
+    }
+    match version_command & 0x0f {
+        0 => return Ok(None),
+        1 => {}
+        command => return Err(malformed(format!("unknown PROXY v2 command {}", command))),
+    }
+    // Addresses are source, destination, source port, destination port.
+    let source = match family >> 4 {
+        1 => {
+            let bytes: [u8; 12] = addresses.get(..12).ok_or_else(|| malformed("truncated PROXY v2 IPv4 addresses"))?.try_into().map_err(malformed)?;
+            let ip = Ipv4Addr::from(<[u8; 4]>::try_from(&bytes[..4]).map_err(malformed)?);
+            SocketAddr::new(ip.into(), u16::from_be_bytes([bytes[8], bytes[9]]))
+        }
+        2 => {
+            let bytes: [u8; 36] = addresses.get(..36).ok_or_else(|| malformed("truncated PROXY v2 IPv6 addresses"))?.try_into().map_err(malformed)?;
+            let ip = Ipv6Addr::from(<[u8; 16]>::try_from(&bytes[..16]).map_err(malformed)?);
+            SocketAddr::new(ip.into(), u16::from_be_bytes([bytes[32], bytes[33]]))
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(source))
+}
+
+fn malformed(e: impl Display) -> SyslogError {
+    SyslogError::MalformedProxyHeader(e.to_string())
+}
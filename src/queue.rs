@@ -0,0 +1,100 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use metrics::gauge;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// Shortest gap between two high-watermark warnings.
+const WARN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Reports how full the processing queue is, and warns once it is fuller
+/// than `--queue-warn-pct`.
+#[derive(Debug)]
+pub struct QueueMonitor {
+    warn_pct: Option<u8>,
+    last_warning: Mutex<Option<Instant>>,
+}
+
+impl QueueMonitor {
+    pub fn new(warn_pct: Option<u8>) -> Self {
+        QueueMonitor {
+            warn_pct,
+            last_warning: Mutex::new(None),
+        }
+    }
+
+    /// Records the fill level of `tx`'s channel; see `update`.
+    pub fn record<T>(&self, tx: &mpsc::Sender<T>) {
+        self.update(depth(tx), tx.max_capacity());
+    }
+
+    /// Records the fill level of `rx`'s channel once a message is taken, so
+    /// the gauges fall as the processor catches up.
+    pub fn record_received<T>(&self, rx: &mpsc::Receiver<T>) {
+        self.update(rx.len(), rx.max_capacity());
+    }
+
+    /// Sets `syslog_queue_size` to the `depth` messages waiting and
+    /// `syslog_queue_saturation` to the share of `max_capacity` they fill.
+    /// Past the high watermark this also warns, at most once per
+    /// `WARN_INTERVAL`.
+    fn update(&self, depth: usize, max_capacity: usize) {
+        let saturation = depth as f64 / max_capacity as f64;
+        gauge!("syslog_queue_size", depth as f64);
+        gauge!("syslog_queue_saturation", saturation);
+
+        let Some(warn_pct) = self.warn_pct else { return };
+        if saturation * 100.0 < f64::from(warn_pct) {
+            return;
+        }
+        let mut last_warning = self.last_warning.lock().unwrap();
+        if last_warning.is_some_and(|at| at.elapsed() < WARN_INTERVAL) {
+            return;
+        }
+        *last_warning = Some(Instant::now());
+        warn!(
+            "Queue is {:.0}% full ({} of {} messages); processing is falling behind",
+            saturation * 100.0,
+            depth,
+            max_capacity
+        );
+    }
+}
+
+/// Messages waiting in the channel behind `tx`. `Sender::capacity` is the
+/// space left, which shrinks as the queue fills.
+fn depth<T>(tx: &mpsc::Sender<T>) -> usize {
+    tx.max_capacity() - tx.capacity()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn depth_rises_as_messages_queue_and_falls_as_they_are_taken() {
+        crate::test_metrics::install();
+        let monitor = QueueMonitor::new(None);
+        let gauges = || {
+            (
+                crate::test_metrics::gauge("syslog_queue_size", &[]),
+                crate::test_metrics::gauge("syslog_queue_saturation", &[]),
+            )
+        };
+        let (tx, mut rx) = mpsc::channel(4);
+        assert_eq!(depth(&tx), 0);
+        for message in 1..=3 {
+            tx.try_send(message).unwrap();
+            assert_eq!(depth(&tx), message);
+            monitor.record(&tx);
+            assert_eq!(gauges(), (Some(message as f64), Some(message as f64 / 4.0)));
+        }
+        for left in (0..3).rev() {
+            rx.try_recv().unwrap();
+            assert_eq!((depth(&tx), rx.len()), (left, left));
+            monitor.record_received(&rx);
+            assert_eq!(gauges(), (Some(left as f64), Some(left as f64 / 4.0)));
+        }
+    }
+}
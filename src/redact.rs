@@ -0,0 +1,42 @@
+use std::borrow::Cow;
+
+use regex::Regex;
+
+/// What each match of a `--redact-pattern` is replaced with.
+const REDACTED: &str = "***";
+
+/// Masks every match of a set of patterns, e.g. card numbers or
+/// `password=...`, before a message is stored.
+#[derive(Debug)]
+pub struct Redactor {
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    pub fn new(patterns: Vec<Regex>) -> Self {
+        Redactor { patterns }
+    }
+
+    /// Replaces every match in `message`, returning the result and the
+    /// number of matches replaced. Patterns apply in order, each to the
+    /// output of the one before.
+    pub fn redact(&self, mut message: String) -> (String, u64) {
+        let mut redactions = 0;
+        for pattern in &self.patterns {
+            let matches = pattern.find_iter(&message).count();
+            if matches == 0 {
+                continue;
+            }
+            redactions += matches as u64;
+            if let Cow::Owned(redacted) = pattern.replace_all(&message, REDACTED) {
+                message = redacted;
+            }
+        }
+        (message, redactions)
+    }
+}
+
+/// Compiles a `--redact-pattern`, so a bad one fails at startup.
+pub fn parse_pattern(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| format!("invalid redaction pattern `{}`: {}", pattern, e))
+}
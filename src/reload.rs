@@ -0,0 +1,77 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+
+use arc_swap::ArcSwap;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+use crate::acl::SourceFilter;
+use crate::{Args, LogHandler, SyslogError};
+
+/// Config keys a reload applies; changing any other key needs a restart.
+const LIVE_KEYS: &[&str] = &["min_severity", "facility_route", "allow_cidr", "deny_cidr"];
+
+/// Re-reads the config file at `path` on every SIGHUP until `shutdown` fires,
+/// applying the severity filter, facility routes and source CIDR rules while
+/// the sockets stay bound. Changes to any other key are logged and ignored,
+/// and a config that fails to load leaves every setting as it was.
+pub async fn run_reloader(
+    path: PathBuf,
+    handler: Weak<LogHandler>,
+    sources: Arc<ArcSwap<SourceFilter>>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            error!("Failed to install SIGHUP handler: {}", e);
+            return;
+        }
+    };
+    // What the settings that need a restart are compared against.
+    let initial = read_table(&path).await.unwrap_or_default();
+    loop {
+        tokio::select! {
+            received = hangup.recv() => if received.is_none() { return },
+            _ = shutdown.wait_for(|&stop| stop) => return,
+        }
+        let Some(handler) = handler.upgrade() else { return };
+        info!("SIGHUP received, reloading {}", path.display());
+        match reload(&path, &initial, &handler, &sources).await {
+            Ok(()) => info!("Reloaded {}", path.display()),
+            Err(e) => error!("Failed to reload {}, keeping the current settings: {}", path.display(), e),
+        }
+    }
+}
+
+async fn reload(
+    path: &Path,
+    initial: &toml::Table,
+    handler: &LogHandler,
+    sources: &ArcSwap<SourceFilter>,
+) -> Result<(), SyslogError> {
+    let table = read_table(path).await?;
+    // Loaded the same way as at startup, so flags given on the command line
+    // still win over the file.
+    let args = Args::load()?;
+    let ignored: BTreeSet<&str> = initial
+        .keys()
+        .chain(table.keys())
+        .map(String::as_str)
+        .filter(|key| !LIVE_KEYS.contains(key) && initial.get(*key) != table.get(*key))
+        .collect();
+    for key in ignored {
+        warn!("Ignoring the change to `{}` in {} until the server is restarted", key, path.display());
+    }
+
+    handler.reload(args.min_severity, args.facility_route.into_iter().collect()).await?;
+    sources.store(Arc::new(SourceFilter::new(args.allow_cidr, args.deny_cidr)));
+    Ok(())
+}
+
+async fn read_table(path: &Path) -> Result<toml::Table, SyslogError> {
+    let contents = tokio::fs::read_to_string(path).await?;
+    contents.parse().map_err(|e| SyslogError::Config(format!("Invalid config file {}: {}", path.display(), e)))
+}
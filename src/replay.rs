@@ -0,0 +1,61 @@
+use std::io;
+use std::time::Duration;
+
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::{mpsc, watch};
+use tokio::time::MissedTickBehavior;
+use tracing::info;
+
+use crate::RawMessage;
+
+/// Source IP recorded for replayed lines.
+const REPLAY_SOURCE: &str = "127.0.0.1";
+
+/// Feeds each line of `file` into the processing channel as if it had just
+/// been received, at most `rate` lines per second when set. Unlike the
+/// network receivers it waits for queue space, so no line is ever dropped.
+/// Returns once the file is exhausted or `shutdown` fires.
+pub async fn run_replay(
+    file: File,
+    rate: Option<u32>,
+    tx: mpsc::Sender<RawMessage>,
+    mut shutdown: watch::Receiver<bool>,
+) -> io::Result<()> {
+    let mut throttle = rate.map(|rate| {
+        let mut interval = tokio::time::interval(Duration::from_secs(1) / rate.max(1));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval
+    });
+
+    let mut reader = BufReader::new(file);
+    let mut line = Vec::new();
+    let mut replayed = 0;
+    loop {
+        line.clear();
+        let read = tokio::select! {
+            read = reader.read_until(b'\n', &mut line) => read?,
+            _ = shutdown.wait_for(|&stop| stop) => break,
+        };
+        if read == 0 {
+            break;
+        }
+        while matches!(line.last(), Some(b'\r' | b'\n')) {
+            line.pop();
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(throttle) = &mut throttle {
+            throttle.tick().await;
+        }
+        let message = RawMessage::decode(REPLAY_SOURCE.to_string(), None, &line);
+        if tx.send(message).await.is_err() {
+            break;
+        }
+        replayed += 1;
+    }
+    info!("Replayed {} lines", replayed);
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Increment of the SplitMix64 generator.
+const GOLDEN_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Keeps a random fraction of entries, overall or per facility.
+///
+/// Randomness comes from SplitMix64 over a single atomic counter, so workers
+/// share the generator without a lock and a draw costs one `fetch_add` and a
+/// few multiplications.
+#[derive(Debug)]
+pub struct Sampler {
+    /// Share kept of facilities without a rate of their own.
+    rate: f64,
+    facility_rates: HashMap<u8, f64>,
+    state: AtomicU64,
+}
+
+impl Sampler {
+    pub fn new(rate: f64, facility_rates: HashMap<u8, f64>) -> Self {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_nanos() as u64);
+        Sampler {
+            rate,
+            facility_rates,
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    /// Whether to keep an entry of `facility`.
+    pub fn keep(&self, facility: u8) -> bool {
+        let rate = self.facility_rates.get(&facility).copied().unwrap_or(self.rate);
+        if rate >= 1.0 {
+            return true;
+        }
+        // The top 53 bits make an evenly spread float in [0, 1).
+        let draw = (self.next() >> 11) as f64 / (1u64 << 53) as f64;
+        draw < rate
+    }
+
+    fn next(&self) -> u64 {
+        let mut z = self.state.fetch_add(GOLDEN_GAMMA, Ordering::Relaxed).wrapping_add(GOLDEN_GAMMA);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// Parses a sampling rate between 0.0 (keep nothing) and 1.0 (keep all).
+pub fn parse_rate(rate: &str) -> Result<f64, String> {
+    rate.parse::<f64>()
+        .ok()
+        .filter(|rate| (0.0..=1.0).contains(rate))
+        .ok_or_else(|| format!("expected a rate between 0.0 and 1.0, got `{}`", rate))
+}
+
+/// Parses a `FACILITY=RATE` sampling rule, e.g. `7=0.1`.
+pub fn parse_facility_rate(rule: &str) -> Result<(u8, f64), String> {
+    let (facility, rate) = rule
+        .split_once('=')
+        .ok_or_else(|| format!("expected FACILITY=RATE, got `{}`", rule))?;
+    let facility: u8 = facility
+        .parse()
+        .ok()
+        .filter(|facility| *facility <= 23)
+        .ok_or_else(|| format!("facility must be between 0 and 23, got `{}`", facility))?;
+    Ok((facility, parse_rate(rate)?))
+}
@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Counts shown by `/status`, each the sum of these Prometheus counters.
+const COUNTS: [(&str, &[&str]); 4] = [
+    ("received", &["syslog_received_total"]),
+    ("written", &["syslog_written_total"]),
+    ("dropped", &["syslog_dropped_total", "syslog_send_timeout_total"]),
+    ("filtered", &["syslog_filtered_total", "syslog_sampled_out_total"]),
+];
+
+/// Span the rates on `/status` are averaged over.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// How often the counters are sampled for the rates.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+type Counts = [u64; COUNTS.len()];
+
+/// Recent samples of the counters, from which `/status` works out rates.
+pub struct StatusHistory {
+    samples: Mutex<VecDeque<(Instant, Counts)>>,
+}
+
+impl StatusHistory {
+    pub fn new() -> Self {
+        StatusHistory {
+            samples: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Records the counters in `rendered` Prometheus text as of `at`, and
+    /// forgets samples that have left the rate window.
+    pub fn record(&self, at: Instant, rendered: &str) {
+        let mut samples = self.samples.lock().unwrap();
+        samples.push_back((at, read_counts(rendered)));
+        while samples.front().is_some_and(|&(sampled, _)| at.duration_since(sampled) > RATE_WINDOW) {
+            samples.pop_front();
+        }
+    }
+
+    /// A plain-text summary of the counters in `rendered` as of `at`, with
+    /// per-second rates since the oldest sample in the rate window.
+    pub fn report(&self, at: Instant, rendered: &str, uptime: Duration, healthy: bool) -> String {
+        let counts = read_counts(rendered);
+        let oldest = self
+            .samples
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|&&(sampled, _)| at.duration_since(sampled) <= RATE_WINDOW)
+            .copied();
+
+        let uptime = uptime.as_secs();
+        let mut report = format!(
+            "status: {}\nuptime: {}h {}m {}s\n\n",
+            if healthy { "ok" } else { "unhealthy" },
+            uptime / 3600,
+            uptime / 60 % 60,
+            uptime % 60
+        );
+        let _ = writeln!(report, "{:<10} {:>12} {:>12}", "", "total", "per second");
+        for (n, (name, _)) in COUNTS.iter().enumerate() {
+            let rate = match oldest {
+                Some((sampled, then)) if at > sampled => {
+                    counts[n].saturating_sub(then[n]) as f64 / at.duration_since(sampled).as_secs_f64()
+                }
+                _ => 0.0,
+            };
+            let _ = writeln!(report, "{:<10} {:>12} {:>12.1}", name, counts[n], rate);
+        }
+        let window = oldest.map_or(Duration::ZERO, |(sampled, _)| at.duration_since(sampled));
+        let _ = writeln!(report, "\nRates are averaged over the last {}s.", window.as_secs());
+        report
+    }
+}
+
+fn read_counts(rendered: &str) -> Counts {
+    COUNTS.map(|(_, names)| names.iter().map(|name| counter(rendered, name)).sum())
+}
+
+/// The value of counter `name` in Prometheus text, summed over its labels;
+/// 0 until it is first incremented.
+fn counter(rendered: &str, name: &str) -> u64 {
+    rendered
+        .lines()
+        .filter(|line| line.strip_prefix(name).is_some_and(|rest| rest.starts_with([' ', '{'])))
+        .filter_map(|line| line.rsplit(' ').next()?.parse::<f64>().ok())
+        .sum::<f64>() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RENDERED: &str = "# TYPE syslog_received_total counter
+syslog_received_total 150
+syslog_received_by_source{ip=\"127.0.0.1\"} 150
+syslog_dropped_total 4
+syslog_send_timeout_total 1
+";
+
+    #[test]
+    fn counters_are_read_from_prometheus_text() {
+        assert_eq!(counter(RENDERED, "syslog_received_total"), 150);
+        assert_eq!(counter(RENDERED, "syslog_received_by_source"), 150);
+        assert_eq!(counter(RENDERED, "syslog_written_total"), 0);
+        assert_eq!(read_counts(RENDERED), [150, 0, 5, 0]);
+    }
+
+    #[test]
+    fn rates_cover_the_window() {
+        let history = StatusHistory::new();
+        let start = Instant::now();
+        history.record(start, "syslog_received_total 0\n");
+        history.record(start + Duration::from_secs(30), "syslog_received_total 10\n");
+        history.record(start + Duration::from_secs(70), "syslog_received_total 20\n");
+
+        // The first sample has left the window, so the rate is (150 - 10) / 40s.
+        let report = history.report(start + Duration::from_secs(70), RENDERED, Duration::from_secs(3725), true);
+        assert!(report.starts_with("status: ok\nuptime: 1h 2m 5s\n"), "{}", report);
+        assert!(report.contains(&format!("{:<10} {:>12} {:>12}", "received", 150, "3.5")), "{}", report);
+        assert!(report.contains("averaged over the last 40s"), "{}", report);
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::{watch, Mutex};
+use tokio::time::MissedTickBehavior;
+use tracing::{error, info};
+
+use crate::{SyslogError, TimestampFormat};
+
+/// Upper bound on the distinct (device_ip, severity) pairs counted in one
+/// window; further sources are counted under `other`.
+const SUMMARY_CAPACITY: usize = 10_000;
+
+/// Stands in for sources beyond `SUMMARY_CAPACITY`.
+const OTHER_SOURCE: &str = "other";
+
+/// One line of the summary output: how many messages a source sent at one
+/// severity during a window.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct SummaryRecord {
+    window_start: String,
+    window_end: String,
+    device_ip: String,
+    severity: u8,
+    count: u64,
+}
+
+struct Window {
+    started: DateTime<Local>,
+    counts: HashMap<(String, u8), u64>,
+}
+
+impl Window {
+    fn new() -> Self {
+        Window {
+            started: Local::now(),
+            counts: HashMap::new(),
+        }
+    }
+}
+
+/// Counts received messages per (device_ip, severity) and appends the counts
+/// to a JSON lines file once per window. Every message counted in
+/// `syslog_received_total` is counted here as well, so the two reconcile.
+pub struct Summarizer {
+    window: std::sync::Mutex<Window>,
+    file: Mutex<BufWriter<File>>,
+    timestamp_format: TimestampFormat,
+}
+
+impl Summarizer {
+    pub async fn open(path: &Path, timestamp_format: TimestampFormat) -> Result<Self, SyslogError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .map_err(|e| SyslogError::Output(format!("Cannot write to summary output {}: {}", path.display(), e)))?;
+        info!("Writing summaries to {}", path.display());
+        Ok(Summarizer {
+            window: std::sync::Mutex::new(Window::new()),
+            file: Mutex::new(BufWriter::new(file)),
+            timestamp_format,
+        })
+    }
+
+    pub fn record(&self, device_ip: &str, severity: u8) {
+        let mut window = self.window.lock().unwrap();
+        let key = (device_ip.to_string(), severity);
+        let key = if window.counts.len() < SUMMARY_CAPACITY || window.counts.contains_key(&key) {
+            key
+        } else {
+            (OTHER_SOURCE.to_string(), severity)
+        };
+        *window.counts.entry(key).or_default() += 1;
+    }
+
+    /// Ends the current window, writing one record per pair counted in it,
+    /// and starts the next.
+    pub async fn flush(&self) -> Result<(), SyslogError> {
+        let window = std::mem::replace(&mut *self.window.lock().unwrap(), Window::new());
+        let records = self.records(window, Local::now());
+        if records.is_empty() {
+            return Ok(());
+        }
+        let mut lines = Vec::new();
+        for record in &records {
+            serde_json::to_writer(&mut lines, record)?;
+            lines.push(b'\n');
+        }
+        let mut file = self.file.lock().await;
+        file.write_all(&lines).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    fn records(&self, window: Window, ended: DateTime<Local>) -> Vec<SummaryRecord> {
+        let window_start = self.timestamp_format.format(window.started);
+        let window_end = self.timestamp_format.format(ended);
+        let mut records: Vec<SummaryRecord> = window
+            .counts
+            .into_iter()
+            .map(|((device_ip, severity), count)| SummaryRecord {
+                window_start: window_start.clone(),
+                window_end: window_end.clone(),
+                device_ip,
+                severity,
+                count,
+            })
+            .collect();
+        records.sort_by(|a, b| (&a.device_ip, a.severity).cmp(&(&b.device_ip, b.severity)));
+        records
+    }
+}
+
+/// Flushes `summarizer` every `interval` until `shutdown` fires. The last,
+/// partial window is left for the processor to flush once it has drained the
+/// queue.
+pub async fn run_summarizer(summarizer: Arc<Summarizer>, interval: Duration, mut shutdown: watch::Receiver<bool>) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // The first tick completes straight away.
+    ticker.tick().await;
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown.wait_for(|&stop| stop) => return,
+        }
+        if let Err(e) = summarizer.flush().await {
+            error!("Failed to write summaries: {}", e);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::SysLogEntry;
+
+/// The most recent entries, for `/tail`. Once `capacity` entries are held
+/// the oldest are dropped. The lock is taken once per written batch rather
+/// than per message, so ingest barely notices it.
+pub struct TailBuffer {
+    entries: Mutex<VecDeque<SysLogEntry>>,
+    capacity: usize,
+}
+
+impl TailBuffer {
+    pub fn new(capacity: usize) -> Self {
+        TailBuffer {
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    pub fn extend(&self, batch: &[SysLogEntry]) {
+        // Only the newest `capacity` entries of a large batch can survive,
+        // and they are cloned before taking the lock.
+        let batch = batch[batch.len().saturating_sub(self.capacity)..].to_vec();
+        let mut entries = self.entries.lock().unwrap();
+        let overflow = (entries.len() + batch.len()).saturating_sub(self.capacity);
+        entries.drain(..overflow);
+        entries.extend(batch);
+    }
+
+    /// Up to `limit` of the newest entries, oldest first.
+    pub fn recent(&self, limit: usize) -> Vec<SysLogEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries.range(entries.len().saturating_sub(limit)..).cloned().collect()
+    }
+}
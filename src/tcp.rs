@@ -0,0 +1,772 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use arc_swap::ArcSwap;
+use metrics::{decrement_gauge, increment_counter, increment_gauge};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch, OwnedSemaphorePermit, Semaphore};
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, error, info, warn};
+
+use crate::acl::SourceFilter;
+use crate::error::{count_error, ErrorKind};
+use crate::queue::QueueMonitor;
+use crate::{proxy, RawMessage, SyslogError};
+
+/// Longest wait for the PROXY header once a connection is accepted.
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest wait for a TLS handshake when `idle_timeout` isn't set.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause after an accept error that would otherwise repeat straight away,
+/// such as running out of file descriptors.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Shortest gap between two warnings about connections rejected at the cap.
+const REJECTION_WARN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Settings shared by every connection a TCP listener accepts.
+pub struct TcpSettings {
+    /// Wraps each connection in TLS before any frames are read.
+    pub tls: Option<TlsAcceptor>,
+    /// Frames longer than this many bytes are rejected.
+    pub max_frame: usize,
+    /// Swapped out when the config is reloaded.
+    pub sources: Arc<ArcSwap<SourceFilter>>,
+    /// Expect a PROXY protocol header, ahead of any TLS, naming the real client.
+    pub proxy_protocol: bool,
+    /// Longest wait for queue space before a frame is dropped; without it
+    /// the connection waits, pushing back on the sender.
+    pub send_timeout: Option<Duration>,
+    /// Connections that send nothing for this long are closed. Also the
+    /// longest a TLS handshake may take.
+    pub idle_timeout: Option<Duration>,
+    /// Caps the connections open at once, across every listener.
+    pub connection_limit: Option<ConnectionLimit>,
+    pub queue: Arc<QueueMonitor>,
+}
+
+/// A cap on open TCP connections. Each connection holds a slot until it
+/// closes; connections accepted while none is free are closed straight away.
+pub struct ConnectionLimit {
+    max: usize,
+    slots: Arc<Semaphore>,
+    last_warning: Mutex<Option<Instant>>,
+}
+
+impl ConnectionLimit {
+    pub fn new(max: usize) -> Self {
+        ConnectionLimit {
+            max,
+            slots: Arc::new(Semaphore::new(max)),
+            last_warning: Mutex::new(None),
+        }
+    }
+
+    /// Takes a slot for a connection from `peer`, or counts and, at most
+    /// once per `REJECTION_WARN_INTERVAL`, warns about its rejection.
+    fn acquire(&self, peer: SocketAddr) -> Option<OwnedSemaphorePermit> {
+        if let Ok(slot) = Arc::clone(&self.slots).try_acquire_owned() {
+            return Some(slot);
+        }
+        increment_counter!("syslog_connections_rejected_total");
+        let mut last_warning = self.last_warning.lock().unwrap();
+        if !last_warning.is_some_and(|at| at.elapsed() < REJECTION_WARN_INTERVAL) {
+            *last_warning = Some(Instant::now());
+            warn!("Rejected TCP connection from {}: {} connections are already open", peer, self.max);
+        }
+        None
+    }
+}
+
+/// Accepts syslog-over-TCP connections and feeds every framed message into
+/// the same channel the UDP receiver uses, until `shutdown` fires.
+/// Connections from sources rejected by `settings.sources` are closed
+/// straight away. Each connection is served on its own task, so accepting
+/// never waits on a slow client.
+pub async fn run_tcp_listener(
+    listener: TcpListener,
+    listen_port: u16,
+    settings: Arc<TcpSettings>,
+    tx: mpsc::Sender<RawMessage>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = shutdown.wait_for(|&stop| stop) => return,
+        };
+        match accepted {
+            Ok((stream, peer)) => {
+                let slot = match &settings.connection_limit {
+                    Some(limit) => match limit.acquire(peer) {
+                        Some(slot) => Some(slot),
+                        None => continue,
+                    },
+                    None => None,
+                };
+                increment_gauge!("syslog_active_tcp_connections", 1.0);
+                let connection = serve_connection(
+                    stream,
+                    peer,
+                    listen_port,
+                    Arc::clone(&settings),
+                    tx.clone(),
+                    shutdown.clone(),
+                );
+                tokio::spawn(async move {
+                    connection.await;
+                    drop(slot);
+                    decrement_gauge!("syslog_active_tcp_connections", 1.0);
+                });
+            }
+            Err(e) => {
+                increment_counter!("syslog_tcp_accept_errors_total");
+                if matches!(e.kind(), io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset) {
+                    // The client gave up before it was accepted; the next
+                    // one may be waiting already.
+                    debug!("TCP connection on port {} closed before it was accepted: {}", listen_port, e);
+                    continue;
+                }
+                count_error(ErrorKind::Io);
+                if out_of_descriptors(&e) {
+                    warn!(
+                        "Out of file descriptors accepting TCP connections on port {}; raise the open file limit: {}",
+                        listen_port, e
+                    );
+                } else {
+                    error!("TCP accept error on port {}: {}", listen_port, e);
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(ACCEPT_ERROR_BACKOFF) => {}
+                    _ = shutdown.wait_for(|&stop| stop) => return,
+                }
+            }
+        }
+    }
+}
+
+/// EMFILE or ENFILE, which have the same numbers on Linux, macOS and the BSDs.
+fn out_of_descriptors(e: &io::Error) -> bool {
+    cfg!(unix) && matches!(e.raw_os_error(), Some(23 | 24))
+}
+
+/// Works out who is really sending on `stream`, checks them against the
+/// source rules and completes the TLS handshake, then reads frames.
+async fn serve_connection(
+    mut stream: TcpStream,
+    mut peer: SocketAddr,
+    listen_port: u16,
+    settings: Arc<TcpSettings>,
+    tx: mpsc::Sender<RawMessage>,
+    shutdown: watch::Receiver<bool>,
+) {
+    if settings.proxy_protocol {
+        let header = tokio::time::timeout(PROXY_HEADER_TIMEOUT, proxy::read_header(&mut stream))
+            .await
+            .unwrap_or_else(|_| Err(SyslogError::MalformedProxyHeader("timed out waiting for the header".to_string())));
+        match header {
+            Ok(Some(client)) => peer = client,
+            // The proxy's own health checks carry no client address.
+            Ok(None) => {}
+            Err(e) => {
+                increment_counter!("syslog_proxy_parse_errors_total");
+                count_error(ErrorKind::Parse);
+                error!("Closing connection from {}: invalid PROXY header: {}", peer, e);
+                return;
+            }
+        }
+    }
+    if !settings.sources.load().permits(peer.ip()) {
+        increment_counter!("syslog_denied_total");
+        return;
+    }
+
+    info!("Accepted TCP connection from {}", peer);
+    match &settings.tls {
+        Some(acceptor) => {
+            // A client that never sends its ClientHello must not hold the
+            // connection open forever.
+            let timeout = settings.idle_timeout.unwrap_or(TLS_HANDSHAKE_TIMEOUT);
+            let handshake = tokio::time::timeout(timeout, acceptor.accept(stream))
+                .await
+                .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, format!("not done within {:?}", timeout))));
+            match handshake {
+                Ok(stream) => handle_connection(stream, peer, listen_port, &settings, tx, shutdown).await,
+                Err(e) => {
+                    increment_counter!("syslog_tls_handshake_errors_total");
+                    warn!("TLS handshake with {} failed: {}", peer, e);
+                }
+            }
+        }
+        None => handle_connection(stream, peer, listen_port, &settings, tx, shutdown).await,
+    }
+}
+
+async fn handle_connection<S: AsyncRead + Unpin>(
+    mut stream: S,
+    peer: SocketAddr,
+    listen_port: u16,
+    settings: &TcpSettings,
+    tx: mpsc::Sender<RawMessage>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut decoder = FrameDecoder::new(settings.max_frame);
+    let mut buf = [0; 8192];
+    loop {
+        // Every read starts the idle timer afresh.
+        let idle = async {
+            match settings.idle_timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        let read = tokio::select! {
+            read = stream.read(&mut buf) => read,
+            _ = idle => {
+                increment_counter!("syslog_tcp_idle_timeouts_total");
+                info!("Closing TCP connection from {}: nothing received for {:?}", peer, settings.idle_timeout.unwrap_or_default());
+                return;
+            }
+            // Only complete frames are kept; a partial one is abandoned.
+            _ = shutdown.wait_for(|&stop| stop) => return,
+        };
+        let size = match read {
+            Ok(0) => break,
+            Ok(size) => size,
+            Err(e) => {
+                count_error(ErrorKind::Io);
+                error!("TCP read error from {}: {}", peer, e);
+                return;
+            }
+        };
+
+        decoder.extend(&buf[..size]);
+        while let Some(frame) = decoder.next_frame() {
+            match frame {
+                Frame::Message(frame) => {
+                    if !send_frame(&tx, peer, listen_port, frame, settings).await {
+                        return;
+                    }
+                }
+                Frame::Oversized => {
+                    count_error(ErrorKind::Parse);
+                    error!("Rejected frame from {} longer than {} bytes", peer, settings.max_frame);
+                }
+                Frame::Invalid(reason) => framing_error(peer, reason),
+            }
+        }
+    }
+
+    // A sender may close the connection without terminating its last line.
+    match decoder.finish() {
+        Some(Frame::Message(frame)) => {
+            send_frame(&tx, peer, listen_port, frame, settings).await;
+        }
+        Some(Frame::Invalid(reason)) => framing_error(peer, reason),
+        Some(Frame::Oversized) | None => {}
+    }
+}
+
+fn framing_error(peer: SocketAddr, reason: &str) {
+    increment_counter!("syslog_framing_errors_total");
+    count_error(ErrorKind::Parse);
+    warn!("Framing error from {}: {}; skipping to the next frame", peer, reason);
+}
+
+/// Queues one frame, returning `false` once the processor is gone. A frame
+/// that waits longer than `settings.send_timeout` is dropped, but the
+/// connection stays open.
+async fn send_frame(
+    tx: &mpsc::Sender<RawMessage>,
+    peer: SocketAddr,
+    listen_port: u16,
+    frame: Vec<u8>,
+    settings: &TcpSettings,
+) -> bool {
+    let message = RawMessage::decode(peer.ip().to_string(), Some(listen_port), &frame);
+    let sent = match settings.send_timeout {
+        Some(timeout) => match tx.send_timeout(message, timeout).await {
+            Err(mpsc::error::SendTimeoutError::Timeout(_)) => {
+                increment_counter!("syslog_send_timeout_total");
+                warn!("Dropped frame from {}: the queue stayed full for {:?}", peer, timeout);
+                Ok(())
+            }
+            sent => sent.map_err(|_| SyslogError::ChannelSend),
+        },
+        None => tx.send(message).await.map_err(SyslogError::from),
+    };
+    if let Err(e) = sent {
+        e.count();
+        error!("Failed to send to channel: {}", e);
+        return false;
+    }
+    settings.queue.record(tx);
+    true
+}
+
+/// Splits a TCP byte stream into syslog messages, supporting both RFC6587
+/// octet counting (`MSG-LEN SP MSG`) and newline-terminated framing. Bytes
+/// are buffered until a complete frame is available, and frames are only
+/// decoded as UTF-8 once whole, so a character split across reads survives.
+///
+/// Frames longer than `max_frame` are reported as `Frame::Oversized` and
+/// their remaining bytes skipped, so the buffer never grows past the limit.
+///
+/// A connection starts octet counting with a length, a space and a `<PRI>`,
+/// so a plain line that happens to begin with a number is newline-framed.
+/// Once a connection has sent an octet-counted frame, every later frame must
+/// be one too, and its MSG must start with a `<PRI>`. Anything else means
+/// the sender lied about a length, so a `Frame::Invalid` is reported and
+/// bytes are skipped up to the next plausible frame start.
+#[derive(Debug)]
+struct FrameDecoder {
+    buf: Vec<u8>,
+    max_frame: usize,
+    /// Bytes still to discard from an oversize octet-counted frame.
+    skip: usize,
+    /// Discarding up to the next newline after an oversize line.
+    skip_line: bool,
+    /// The sender has used octet counting.
+    octet_counting: bool,
+    /// Discarding up to the next plausible frame start after a framing error.
+    resyncing: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Frame {
+    Message(Vec<u8>),
+    Oversized,
+    /// The stream broke the framing for the given reason.
+    Invalid(&'static str),
+}
+
+/// Longest `MSG-LEN SP <PRI>` header kept back while resynchronizing, in
+/// case it was split across reads.
+const MAX_HEADER: usize = 16;
+
+impl FrameDecoder {
+    fn new(max_frame: usize) -> Self {
+        FrameDecoder {
+            buf: Vec::new(),
+            max_frame,
+            skip: 0,
+            skip_line: false,
+            octet_counting: false,
+            resyncing: false,
+        }
+    }
+
+    fn extend(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    fn next_frame(&mut self) -> Option<Frame> {
+        if self.skip > 0 {
+            let skipped = self.skip.min(self.buf.len());
+            self.buf.drain(..skipped);
+            self.skip -= skipped;
+            if self.skip > 0 {
+                return None;
+            }
+        }
+        if self.skip_line {
+            match self.buf.iter().position(|&b| b == b'\n') {
+                Some(end) => {
+                    self.buf.drain(..=end);
+                    self.skip_line = false;
+                }
+                None => {
+                    self.buf.clear();
+                    return None;
+                }
+            }
+        }
+        if self.resyncing {
+            match frame_start(&self.buf, self.octet_counting) {
+                Some(start) => {
+                    self.buf.drain(..start);
+                    self.resyncing = false;
+                }
+                None => {
+                    self.buf.drain(..self.buf.len().saturating_sub(MAX_HEADER));
+                    return None;
+                }
+            }
+        }
+
+        // Skip stray line endings left between frames.
+        match self.buf.iter().position(|b| !matches!(b, b'\r' | b'\n')) {
+            Some(start) => {
+                self.buf.drain(..start);
+            }
+            None => {
+                self.buf.clear();
+                return None;
+            }
+        }
+
+        if self.buf[0].is_ascii_digit() {
+            // Wait for the whole length prefix before deciding on the framing.
+            let Some(digits) = self.buf.iter().position(|b| !b.is_ascii_digit()) else {
+                return self.overflow();
+            };
+            // Digits and a space are only a length prefix once the sender is
+            // known to use octet counting, or with a <PRI> straight after;
+            // otherwise they just begin a line, such as `12 errors found`.
+            let counted = match self.buf.get(digits + 1) {
+                _ if self.buf[digits] != b' ' => false,
+                _ if self.octet_counting => true,
+                None => return None,
+                Some(&next) => next == b'<',
+            };
+            if counted {
+                let declared = std::str::from_utf8(&self.buf[..digits])
+                    .ok()
+                    .and_then(|len| len.parse::<usize>().ok());
+                let Some(len) = declared else {
+                    return Some(self.invalid("length prefix is too large"));
+                };
+                let end = digits + 1 + len;
+                if len == 0 {
+                    return Some(self.invalid("declared length is zero"));
+                }
+                if len > self.max_frame {
+                    self.octet_counting = true;
+                    self.skip = end;
+                    return Some(Frame::Oversized);
+                }
+                match self.buf.get(digits + 1) {
+                    None => return None,
+                    Some(b'<') => {}
+                    Some(_) => return Some(self.invalid("frame does not start with a <PRI>")),
+                }
+                self.octet_counting = true;
+                if self.buf.len() < end {
+                    return None;
+                }
+                let frame = self.buf[digits + 1..end].to_vec();
+                self.buf.drain(..end);
+                return Some(Frame::Message(frame));
+            }
+            if self.octet_counting {
+                return Some(self.invalid("length prefix is not a number"));
+            }
+        } else if self.octet_counting {
+            return Some(self.invalid("expected a length prefix"));
+        }
+
+        let Some(end) = self.buf.iter().position(|&b| b == b'\n') else {
+            return self.overflow();
+        };
+        let mut frame: Vec<u8> = self.buf.drain(..=end).collect();
+        frame.pop();
+        if frame.last() == Some(&b'\r') {
+            frame.pop();
+        }
+        if frame.len() > self.max_frame {
+            return Some(Frame::Oversized);
+        }
+        Some(Frame::Message(frame))
+    }
+
+    /// Gives up on an unterminated line once it is already too long.
+    fn overflow(&mut self) -> Option<Frame> {
+        if self.buf.len() <= self.max_frame {
+            return None;
+        }
+        self.buf.clear();
+        self.skip_line = true;
+        Some(Frame::Oversized)
+    }
+
+    /// Starts skipping past the frame at the front of the buffer.
+    fn invalid(&mut self, reason: &'static str) -> Frame {
+        self.buf.drain(..1);
+        self.resyncing = true;
+        Frame::Invalid(reason)
+    }
+
+    /// Hands back what is left once the sender closes the connection: an
+    /// unterminated last line, or an error for a cut-off counted frame.
+    fn finish(mut self) -> Option<Frame> {
+        if self.skip > 0 || self.skip_line || self.resyncing {
+            return None;
+        }
+        while matches!(self.buf.last(), Some(b'\r' | b'\n')) {
+            self.buf.pop();
+        }
+        if self.buf.is_empty() {
+            return None;
+        }
+        if self.octet_counting {
+            return Some(Frame::Invalid("connection closed partway through a frame"));
+        }
+        Some(Frame::Message(self.buf))
+    }
+}
+
+/// Where the next frame plausibly starts in `buf`: a `<PRI>` header, or the
+/// length prefix directly in front of one. With `counted`, a header without a
+/// length prefix is no frame start.
+fn frame_start(buf: &[u8], counted: bool) -> Option<usize> {
+    (0..buf.len()).filter(|&i| is_priority(&buf[i..])).find_map(|pri| {
+        let digits = buf[..pri.saturating_sub(1)].iter().rev().take_while(|b| b.is_ascii_digit()).count();
+        if pri >= 2 && buf[pri - 1] == b' ' && digits > 0 {
+            Some(pri - 1 - digits)
+        } else {
+            (!counted).then_some(pri)
+        }
+    })
+}
+
+/// Whether `bytes` start with a `<PRI>` of one to three digits.
+fn is_priority(bytes: &[u8]) -> bool {
+    let Some(rest) = bytes.strip_prefix(b"<") else { return false };
+    let digits = rest.iter().take(4).take_while(|b| b.is_ascii_digit()).count();
+    (1..=3).contains(&digits) && rest.get(digits) == Some(&b'>')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes `stream` in one read, including what `finish` hands back.
+    fn decode(stream: &[u8]) -> Vec<Frame> {
+        let mut decoder = FrameDecoder::new(1024);
+        decoder.extend(stream);
+        let mut frames: Vec<Frame> = std::iter::from_fn(|| decoder.next_frame()).collect();
+        frames.extend(decoder.finish());
+        frames
+    }
+
+    fn message(frame: &str) -> Frame {
+        Frame::Message(frame.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn bogus_length_prefixes_are_skipped_up_to_the_next_frame() {
+        let frames = decode(b"9 <13>first12x <13>bogus10 <13>second");
+        assert_eq!(
+            frames,
+            [
+                message("<13>first"),
+                Frame::Invalid("length prefix is not a number"),
+                message("<13>second"),
+            ]
+        );
+        assert_eq!(
+            decode(b"9 <13>first4 abcd9 <13>third"),
+            [message("<13>first"), Frame::Invalid("frame does not start with a <PRI>"), message("<13>third")]
+        );
+
+        // Newline framing doesn't need a length prefix.
+        assert_eq!(decode(b"<13>one\nno pri\n"), [message("<13>one"), message("no pri")]);
+    }
+
+    #[test]
+    fn lines_starting_with_a_number_are_not_octet_counted() {
+        assert_eq!(
+            decode(b"12 errors found\n<13>next\n"),
+            [message("12 errors found"), message("<13>next")]
+        );
+        // A <PRI> straight after the space does make it a length prefix.
+        assert_eq!(decode(b"9 <13>first"), [message("<13>first")]);
+    }
+
+    #[test]
+    fn truncated_frames_are_reported() {
+        // The sender claimed more than it sent, so the next frame's start is
+        // swallowed and the rest of it skipped.
+        let frames = decode(b"20 <13>cut short10 <13>lost!9 <13>third");
+        assert_eq!(
+            frames,
+            [
+                message("<13>cut short10 <13>"),
+                Frame::Invalid("expected a length prefix"),
+                message("<13>third"),
+            ]
+        );
+
+        assert_eq!(
+            decode(b"9 <13>first40 <13>closed early"),
+            [message("<13>first"), Frame::Invalid("connection closed partway through a frame")]
+        );
+    }
+
+    #[test]
+    fn multibyte_characters_survive_byte_at_a_time_reads() {
+        let message = "<13>Oct 11 22:14:15 host app: deploy done 🚀 — café";
+        let counted = format!("{} {}", message.len(), message).repeat(2);
+        let lines = format!("{}\n", message).repeat(2);
+
+        for stream in [counted, lines] {
+            let mut decoder = FrameDecoder::new(1024);
+            let mut frames = Vec::new();
+            for byte in stream.as_bytes() {
+                decoder.extend(std::slice::from_ref(byte));
+                while let Some(frame) = decoder.next_frame() {
+                    frames.push(frame);
+                }
+            }
+
+            let decoded: Vec<String> = frames
+                .into_iter()
+                .map(|frame| match frame {
+                    Frame::Message(frame) => crate::decode_message(&frame),
+                    frame => panic!("unexpected {:?}", frame),
+                })
+                .collect();
+            assert_eq!(decoded, [message, message]);
+            assert_eq!(decoder.finish(), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn idle_connections_are_closed() {
+        let settings = TcpSettings {
+            tls: None,
+            max_frame: 1024,
+            sources: Arc::default(),
+            proxy_protocol: false,
+            send_timeout: None,
+            idle_timeout: Some(Duration::from_millis(100)),
+            connection_limit: None,
+            queue: Arc::new(QueueMonitor::new(None)),
+        };
+        let (tx, mut rx) = mpsc::channel(8);
+        let (_shutdown_tx, shutdown) = watch::channel(false);
+        let (mut client, server) = tokio::io::duplex(1024);
+        let peer: SocketAddr = "192.0.2.1:40000".parse().unwrap();
+        let connection = tokio::spawn(async move { handle_connection(server, peer, 514, &settings, tx, shutdown).await });
+
+        // Data arriving within the timeout keeps the connection open.
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_millis(60)).await;
+            tokio::io::AsyncWriteExt::write_all(&mut client, b"<13>still here\n").await.unwrap();
+            assert_eq!(rx.recv().await.unwrap().data, "<13>still here");
+        }
+        tokio::time::timeout(Duration::from_secs(5), connection).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_silent_connection_does_not_hold_up_accepts() {
+        let settings = Arc::new(TcpSettings {
+            tls: None,
+            max_frame: 1024,
+            sources: Arc::default(),
+            proxy_protocol: false,
+            send_timeout: None,
+            idle_timeout: None,
+            connection_limit: None,
+            queue: Arc::new(QueueMonitor::new(None)),
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, mut rx) = mpsc::channel(8);
+        let (shutdown_tx, shutdown) = watch::channel(false);
+        let accepting = tokio::spawn(run_tcp_listener(listener, addr.port(), settings, tx, shutdown));
+
+        let _silent = TcpStream::connect(addr).await.unwrap();
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        tokio::io::AsyncWriteExt::write_all(&mut client, b"<13>hello\n").await.unwrap();
+        let message = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(message.data, "<13>hello");
+
+        shutdown_tx.send(true).unwrap();
+        accepting.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_stalled_tls_handshake_is_given_up_on() {
+        crate::test_metrics::install();
+        let dir = std::env::temp_dir().join(format!("syslog-server-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rcgen::CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        std::fs::write(dir.join("cert.pem"), cert.pem()).unwrap();
+        std::fs::write(dir.join("key.pem"), key_pair.serialize_pem()).unwrap();
+        let acceptor = crate::tls::load_acceptor(&dir.join("cert.pem"), &dir.join("key.pem")).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        let settings = Arc::new(TcpSettings {
+            tls: Some(acceptor),
+            max_frame: 1024,
+            sources: Arc::default(),
+            proxy_protocol: false,
+            send_timeout: None,
+            idle_timeout: Some(Duration::from_millis(100)),
+            connection_limit: None,
+            queue: Arc::new(QueueMonitor::new(None)),
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, _rx) = mpsc::channel(8);
+        let (shutdown_tx, shutdown) = watch::channel(false);
+        let accepting = tokio::spawn(run_tcp_listener(listener, addr.port(), settings, tx, shutdown));
+
+        // The client connects but never sends a ClientHello.
+        let mut silent = TcpStream::connect(addr).await.unwrap();
+        let read = tokio::time::timeout(Duration::from_secs(5), silent.read(&mut [0; 16])).await.unwrap();
+        assert!(matches!(read, Ok(0) | Err(_)), "{:?}", read);
+        assert_eq!(crate::test_metrics::counter("syslog_tls_handshake_errors_total", &[]), 1);
+
+        shutdown_tx.send(true).unwrap();
+        accepting.await.unwrap();
+    }
+
+    #[test]
+    fn descriptor_exhaustion_is_recognised() {
+        assert_eq!(out_of_descriptors(&io::Error::from_raw_os_error(24)), cfg!(unix));
+        assert!(!out_of_descriptors(&io::Error::from(io::ErrorKind::ConnectionAborted)));
+    }
+
+    #[tokio::test]
+    async fn connections_past_the_cap_are_closed() {
+        let settings = Arc::new(TcpSettings {
+            tls: None,
+            max_frame: 1024,
+            sources: Arc::default(),
+            proxy_protocol: false,
+            send_timeout: None,
+            idle_timeout: None,
+            connection_limit: Some(ConnectionLimit::new(1)),
+            queue: Arc::new(QueueMonitor::new(None)),
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, mut rx) = mpsc::channel(8);
+        let (shutdown_tx, shutdown) = watch::channel(false);
+        let accepting = tokio::spawn(run_tcp_listener(listener, addr.port(), settings, tx, shutdown));
+
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        tokio::io::AsyncWriteExt::write_all(&mut first, b"<13>first\n").await.unwrap();
+        assert_eq!(rx.recv().await.unwrap().data, "<13>first");
+
+        let mut rejected = TcpStream::connect(addr).await.unwrap();
+        let read = tokio::time::timeout(Duration::from_secs(5), rejected.read(&mut [0; 16])).await.unwrap();
+        assert!(matches!(read, Ok(0) | Err(_)), "{:?}", read);
+
+        // Closing the first connection frees its slot.
+        drop(first);
+        let mut next = loop {
+            let mut next = TcpStream::connect(addr).await.unwrap();
+            if tokio::io::AsyncWriteExt::write_all(&mut next, b"<13>next\n").await.is_err() {
+                continue;
+            }
+            match tokio::time::timeout(Duration::from_millis(200), rx.recv()).await {
+                Ok(message) => {
+                    assert_eq!(message.unwrap().data, "<13>next");
+                    break next;
+                }
+                Err(_) => continue,
+            }
+        };
+        tokio::io::AsyncWriteExt::shutdown(&mut next).await.unwrap();
+
+        shutdown_tx.send(true).unwrap();
+        accepting.await.unwrap();
+    }
+}
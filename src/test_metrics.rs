@@ -0,0 +1,38 @@
+//! Reads back the metrics a test has emitted.
+
+use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+
+/// Records metrics per thread from here on. Tests each run on a thread of
+/// their own, and tokio's test runtime keeps their tasks on it, so they only
+/// ever see their own metrics.
+pub fn install() {
+    // Only the first install takes; the rest find it already in place.
+    let _ = DebuggingRecorder::per_thread().install();
+}
+
+fn value(name: &str, labels: &[(&str, &str)]) -> Option<DebugValue> {
+    let snapshot = Snapshotter::current_thread_snapshot()?;
+    snapshot.into_vec().into_iter().find_map(|(key, _, _, value)| {
+        let key = key.key();
+        let matches = key.name() == name
+            && key.labels().count() == labels.len()
+            && labels.iter().all(|(label, wanted)| key.labels().any(|l| l.key() == *label && l.value() == *wanted));
+        matches.then_some(value)
+    })
+}
+
+/// The counter's value on this thread, 0 if it was never incremented.
+pub fn counter(name: &str, labels: &[(&str, &str)]) -> u64 {
+    match value(name, labels) {
+        Some(DebugValue::Counter(count)) => count,
+        _ => 0,
+    }
+}
+
+/// The gauge's last value on this thread, if it was ever set.
+pub fn gauge(name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+    match value(name, labels) {
+        Some(DebugValue::Gauge(value)) => Some(value.into_inner()),
+        _ => None,
+    }
+}
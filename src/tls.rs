@@ -0,0 +1,28 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+use crate::SyslogError;
+
+/// Builds the acceptor used to wrap TCP connections in TLS (RFC5425) from a
+/// PEM certificate chain and private key.
+pub fn load_acceptor(cert_path: &Path, key_path: &Path) -> Result<TlsAcceptor, SyslogError> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| SyslogError::Config(format!("Failed to load TLS certificate {}: {}", cert_path.display(), e)))?;
+    if certs.is_empty() {
+        return Err(SyslogError::Config(format!("No certificates found in {}", cert_path.display())));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| SyslogError::Config(format!("Failed to load TLS private key {}: {}", key_path.display(), e)))?;
+
+    let config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|config| config.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|e| SyslogError::Config(format!("Invalid TLS certificate or key: {}", e)))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
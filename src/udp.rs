@@ -0,0 +1,72 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use metrics::increment_counter;
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, watch};
+use tracing::{error, warn};
+
+use crate::acl::SourceFilter;
+use crate::error::{count_error, ErrorKind};
+use crate::queue::QueueMonitor;
+use crate::RawMessage;
+
+/// Settings shared by the UDP receivers on every port.
+pub struct UdpSettings {
+    /// Size of the receive buffer; longer datagrams are cut off.
+    pub max_message_bytes: usize,
+    /// Swapped out when the config is reloaded.
+    pub sources: Arc<ArcSwap<SourceFilter>>,
+    /// Longest wait for queue space before a datagram is dropped; without
+    /// it datagrams are dropped as soon as the queue is full.
+    pub send_timeout: Option<Duration>,
+    pub queue: Arc<QueueMonitor>,
+}
+
+/// Receives syslog datagrams on `socket` (bound to `listen_port`) and feeds
+/// them into the processing channel until `shutdown` fires. Datagrams that
+/// fill the whole buffer were probably truncated, and datagrams from sources
+/// rejected by `settings.sources` never reach the channel.
+pub async fn run_udp_receiver(
+    socket: UdpSocket,
+    listen_port: u16,
+    settings: Arc<UdpSettings>,
+    tx: mpsc::Sender<RawMessage>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut buf = vec![0; settings.max_message_bytes];
+    loop {
+        let received = tokio::select! {
+            received = socket.recv_from(&mut buf) => received,
+            _ = shutdown.wait_for(|&stop| stop) => return,
+        };
+        match received {
+            Ok((size, addr)) => {
+                if !settings.sources.load().permits(addr.ip()) {
+                    increment_counter!("syslog_denied_total");
+                    continue;
+                }
+                // recv_from silently cuts off whatever does not fit
+                if size == buf.len() {
+                    increment_counter!("syslog_truncated_total");
+                    warn!("Datagram from {} filled the {}-byte buffer and was probably truncated", addr.ip(), size);
+                }
+                let message = RawMessage::decode(addr.ip().to_string(), Some(listen_port), &buf[..size]);
+                // Drop rather than wait when the queue is full. Awaiting here
+                // only moves the loss into the kernel socket buffer, where it
+                // overflows silently; dropping keeps the loop draining the
+                // socket and makes the loss visible as syslog_dropped_total.
+                // UDP has no flow control, so backpressure never reaches the
+                // sender anyway. A short `send_timeout` can still ride out
+                // brief bursts.
+                crate::queue_datagram(&tx, message, settings.send_timeout).await;
+                settings.queue.record(&tx);
+            }
+            Err(e) => {
+                count_error(ErrorKind::Io);
+                error!("Socket receive error: {}", e);
+            }
+        }
+    }
+}
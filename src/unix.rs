@@ -0,0 +1,70 @@
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use metrics::increment_counter;
+use tokio::net::UnixDatagram;
+use tokio::sync::{mpsc, watch};
+use tracing::{error, warn};
+
+use crate::error::{count_error, ErrorKind};
+use crate::queue::QueueMonitor;
+use crate::{RawMessage, SyslogError};
+
+/// `device_ip` recorded for messages received over the Unix socket.
+const LOCAL_SOURCE: &str = "local";
+
+/// Binds a Unix datagram socket at `path` (e.g. `/dev/log`) with the given
+/// permission bits. A socket left behind by a previous run is replaced, but
+/// any other kind of file at `path` is an error.
+pub fn bind(path: &Path, mode: u32) -> Result<UnixDatagram, SyslogError> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => return Err(SyslogError::Socket(format!("{} exists and is not a socket", path.display()))),
+        Err(_) => {}
+    }
+    let socket = UnixDatagram::bind(path).map_err(|e| SyslogError::Socket(format!("Failed to bind {}: {}", path.display(), e)))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(socket)
+}
+
+/// Feeds datagrams from local daemons into the processing channel until
+/// `shutdown` fires, then removes the socket file.
+pub async fn run_unix_receiver(
+    socket: UnixDatagram,
+    path: PathBuf,
+    max_message_bytes: usize,
+    send_timeout: Option<Duration>,
+    queue: Arc<QueueMonitor>,
+    tx: mpsc::Sender<RawMessage>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut buf = vec![0; max_message_bytes];
+    loop {
+        let received = tokio::select! {
+            received = socket.recv(&mut buf) => received,
+            _ = shutdown.wait_for(|&stop| stop) => break,
+        };
+        let size = match received {
+            Ok(size) => size,
+            Err(e) => {
+                count_error(ErrorKind::Io);
+                error!("Unix socket receive error: {}", e);
+                continue;
+            }
+        };
+        if size == buf.len() {
+            increment_counter!("syslog_truncated_total");
+            warn!("Datagram on {} filled the {}-byte buffer and was probably truncated", path.display(), size);
+        }
+        let message = RawMessage::decode(LOCAL_SOURCE.to_string(), None, &buf[..size]);
+        // Same tradeoff as UDP: senders never see backpressure, so drop.
+        crate::queue_datagram(&tx, message, send_timeout).await;
+        queue.record(&tx);
+    }
+
+    if let Err(e) = std::fs::remove_file(&path) {
+        warn!("Failed to remove {}: {}", path.display(), e);
+    }
+}
@@ -0,0 +1,262 @@
+//! Runs the server binary against real UDP datagrams and checks the CSV it
+//! writes.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{TcpListener, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait for the server to start and for rows to appear.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A running server, killed when dropped.
+struct Server {
+    child: Child,
+    port: u16,
+    metrics_port: u16,
+    output: PathBuf,
+}
+
+impl Server {
+    fn start(name: &str, extra_args: &[&str]) -> Server {
+        let output = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.csv", name));
+        let _ = std::fs::remove_file(&output);
+        let port = free_udp_port();
+        let metrics_port = free_tcp_port();
+        let child = Command::new(env!("CARGO_BIN_EXE_syslog-server"))
+            .args(["--bind-address", "127.0.0.1", "--port", &port.to_string()])
+            .args(["--metrics-port", &metrics_port.to_string()])
+            .args(["--flush-interval-ms", "20"])
+            .arg("--output")
+            .arg(&output)
+            .args(extra_args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("failed to start syslog-server");
+        let server = Server {
+            child,
+            port,
+            metrics_port,
+            output,
+        };
+
+        // The socket isn't bound the moment the process starts, so keep
+        // sending a probe until one makes it through.
+        let started = Instant::now();
+        while !server.rows().iter().any(|row| row["syslog"] == "probe") {
+            assert!(started.elapsed() < TIMEOUT, "server never wrote the probe message");
+            server.send(&["<14>Oct 11 22:14:15 test probe: probe"]);
+            thread::sleep(Duration::from_millis(100));
+        }
+        server
+    }
+
+    fn send(&self, messages: &[&str]) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for message in messages {
+            socket.send_to(message.as_bytes(), ("127.0.0.1", self.port)).unwrap();
+        }
+    }
+
+    /// Every complete row of the output CSV so far, keyed by column name.
+    fn rows(&self) -> Vec<HashMap<String, String>> {
+        let Ok(mut reader) = csv::Reader::from_path(&self.output) else {
+            return Vec::new();
+        };
+        // A row still being flushed fails to parse and is left for next time.
+        reader.deserialize().map_while(Result::ok).collect()
+    }
+
+    /// Waits for a row whose message is `syslog`.
+    fn wait_for(&self, syslog: &str) -> HashMap<String, String> {
+        let started = Instant::now();
+        loop {
+            if let Some(row) = self.rows().into_iter().find(|row| row["syslog"] == syslog) {
+                return row;
+            }
+            assert!(started.elapsed() < TIMEOUT, "no row with message `{}`", syslog);
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn free_udp_port() -> u16 {
+    UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+fn free_tcp_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+#[test]
+fn valid_priority_is_parsed() {
+    let server = Server::start("valid_priority", &[]);
+    server.send(&["<34>Oct 11 22:14:15 mymachine su: 'su root' failed for lonvick on /dev/pts/8"]);
+
+    let row = server.wait_for("'su root' failed for lonvick on /dev/pts/8");
+    assert_eq!(row["facility"], "4");
+    assert_eq!(row["severity"], "2");
+    assert_eq!(row["host"], "mymachine");
+    assert_eq!(row["app_name"], "su");
+    assert_eq!(row["device_ip"], "127.0.0.1");
+    assert_eq!(row["listen_port"], server.port.to_string());
+}
+
+#[test]
+fn missing_priority_gets_the_default() {
+    let server = Server::start("missing_priority", &[]);
+    server.send(&["no priority here"]);
+
+    let row = server.wait_for("no priority here");
+    assert_eq!(row["facility"], "1");
+    assert_eq!(row["severity"], "5");
+}
+
+#[test]
+fn invalid_priority_is_dropped_by_default() {
+    let server = Server::start("invalid_priority_dropped", &[]);
+    server.send(&["<999>bad priority", "<13>Oct 11 22:14:15 test marker: after"]);
+
+    // Datagrams from one socket are handled in order, so once the second
+    // message is written the first has been dealt with too.
+    server.wait_for("after");
+    assert!(server.rows().iter().all(|row| row["syslog"] != "<999>bad priority"));
+}
+
+#[test]
+fn invalid_priority_is_kept_when_asked() {
+    let server = Server::start("invalid_priority_kept", &["--on-malformed", "keep"]);
+    server.send(&["<999>bad priority"]);
+
+    let row = server.wait_for("<999>bad priority");
+    assert_eq!(row["facility"], "255");
+    assert_eq!(row["severity"], "255");
+}
+
+#[test]
+fn multiline_message_becomes_one_row() {
+    let server = Server::start("multiline", &[]);
+    server.send(&["<165>Oct 11 22:14:15 web app: first line\nsecond line\nthird line"]);
+
+    let row = server.wait_for("first linesecond linethird line");
+    assert_eq!(row["facility"], "20");
+    assert_eq!(row["severity"], "5");
+    let messages = server.rows().into_iter().filter(|row| row["syslog"] != "probe").count();
+    assert_eq!(messages, 1, "the multiline message was split into several rows");
+}
+
+#[test]
+fn http_ingest_feeds_the_same_output() {
+    let ingest_port = free_tcp_port();
+    let server = Server::start("http_ingest", &["--http-ingest-port", &ingest_port.to_string(), "--trust-xff"]);
+
+    let body = r#"["<11>Oct 11 22:14:15 hook app: first", "<14>Oct 11 22:14:16 hook app: second"]"#;
+    let request = format!(
+        "POST /ingest HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nX-Forwarded-For: 203.0.113.7\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    let mut stream = std::net::TcpStream::connect(("127.0.0.1", ingest_port)).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 202"), "unexpected response {:?}", response);
+
+    let row = server.wait_for("first");
+    assert_eq!(row["severity"], "3");
+    assert_eq!(row["device_ip"], "203.0.113.7");
+    assert_eq!(row["listen_port"], ingest_port.to_string());
+    server.wait_for("second");
+}
+
+#[test]
+fn no_metrics_leaves_the_port_closed() {
+    let server = Server::start("no_metrics", &["--no-metrics"]);
+    server.send(&["<13>Oct 11 22:14:15 test app: without metrics"]);
+
+    server.wait_for("without metrics");
+    assert!(std::net::TcpStream::connect(("127.0.0.1", server.metrics_port)).is_err());
+}
+
+#[test]
+fn ordered_output_keeps_the_arrival_order() {
+    let server = Server::start("ordered", &["--ordered", "--workers", "4", "--batch-size", "7"]);
+    let messages: Vec<String> = (0..300).map(|n| format!("seq {}", n)).collect();
+    server.send(&messages.iter().map(String::as_str).collect::<Vec<_>>());
+    server.wait_for("seq 299");
+
+    // A datagram may be lost, but none may be written out of order.
+    let written: Vec<u32> = server
+        .rows()
+        .iter()
+        .filter_map(|row| row["syslog"].strip_prefix("seq ")?.parse().ok())
+        .collect();
+    assert!(written.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", written);
+}
+
+/// Stands in for Loki: answers the first push with a 500 and later ones with
+/// a 204, sending each request body on the returned channel.
+fn fake_loki() -> (u16, std::sync::mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (tx, rx) = std::sync::mpsc::channel();
+    thread::spawn(move || {
+        let mut pushes = 0;
+        for stream in listener.incoming() {
+            let mut reader = std::io::BufReader::new(stream.unwrap());
+            // Requests on one kept-alive connection, one after another.
+            loop {
+                let mut length = None;
+                let mut line = String::new();
+                while std::io::BufRead::read_line(&mut reader, &mut line).unwrap_or(0) > 0 && line != "\r\n" {
+                    let lower = line.to_ascii_lowercase();
+                    if let Some(value) = lower.strip_prefix("content-length:") {
+                        length = value.trim().parse().ok();
+                    }
+                    line.clear();
+                }
+                let Some(length) = length else { break };
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                pushes += 1;
+                let status = if pushes == 1 { "500 Internal Server Error" } else { "204 No Content" };
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                if tx.send(String::from_utf8(body).unwrap()).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    (port, rx)
+}
+
+#[test]
+fn loki_pushes_are_retried() {
+    let (loki_port, pushes) = fake_loki();
+    let url = format!("http://127.0.0.1:{}/loki/api/v1/push", loki_port);
+    let server = Server::start("loki", &["--loki-url", &url]);
+    server.send(&["<11>Oct 11 22:14:15 host app: to loki"]);
+
+    let mut received = Vec::new();
+    while !received.last().is_some_and(|body: &String| body.contains("to loki")) {
+        received.push(pushes.recv_timeout(TIMEOUT).expect("no push with the message"));
+    }
+    // The push that got a 500 was sent again, unchanged.
+    assert!(received.len() >= 2, "{:?}", received);
+    assert_eq!(received[0], received[1]);
+    let push: serde_json::Value = serde_json::from_str(received.last().unwrap()).unwrap();
+    let stream = &push["streams"][0];
+    assert_eq!(stream["stream"], serde_json::json!({"device_ip": "127.0.0.1", "facility": "1", "severity": "3"}));
+}